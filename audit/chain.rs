@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use base::{DbConn, references::InternalReference, sql};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use user_auth_structs::TenantRef;
+
+use crate::tenants::internal::TidInternal;
+use super::structures::{AuditCheckpoint, ChainBreak, ChainedEntry};
+
+/// The previous hash of the first entry in every chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Selects the chain an entry belongs to - one per tenant, plus one for entries without a tenant
+pub fn chain_condition(tenant_id: Option<TidInternal>) -> String {
+    match tenant_id {
+        Some(tenant_id) => sql!("tenant_id {=}", tenant_id),
+        None => String::from("tenant_id IS NULL"),
+    }
+}
+
+/// Hashes an entry together with the hash of the entry before it
+pub fn hash_entry(entry: &ChainedEntry) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(entry.prev_hash.as_bytes());
+    hasher.update(format!(
+        "|{:?}|{:?}|{}|{}|{}|{:?}|{:?}|{}",
+        entry.actor_id,
+        entry.tenant_id,
+        entry.action,
+        entry.target_type,
+        entry.target_ref,
+        entry.changes,
+        entry.request_id,
+        entry.created_at
+    ).as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Signs a checkpoint so it can be checked after being exported
+pub fn sign_checkpoint(key: &str, tenant: &Option<TenantRef>, last_entry_id: u64, hash: &str, created_at: u64) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    let tenant = tenant.as_ref().map(|t| t.to_string()).unwrap_or_default();
+    mac.update(format!("{}|{}|{}|{}", tenant, last_entry_id, hash, created_at).as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+/// Walks a chain in order and reports the first entry that has been altered, or whose
+/// predecessor has been removed. Needs nothing but the entries, so can be run anywhere.
+pub fn verify_entries(entries: &[ChainedEntry]) -> Option<ChainBreak> {
+    let mut expected_prev = GENESIS_HASH;
+    for entry in entries {
+        if entry.prev_hash != expected_prev {
+            return Some(ChainBreak {
+                entry_id: entry.id,
+                reason: String::from("Previous hash does not match - an earlier entry was removed or altered"),
+            });
+        }
+        if hash_entry(entry) != entry.hash {
+            return Some(ChainBreak {
+                entry_id: entry.id,
+                reason: String::from("Entry hash does not match its contents - the entry was altered"),
+            });
+        }
+        expected_prev = &entry.hash;
+    }
+    None
+}
+
+/// Checks that every checkpoint is validly signed and that the entry it vouches for is still
+/// present and unchanged, which catches entries removed from the end of a chain
+pub fn verify_checkpoints(entries: &[ChainedEntry], checkpoints: &[AuditCheckpoint], key: &str) -> Option<ChainBreak> {
+    let hashes: HashMap<u64, &String> = entries.iter().map(|e| (e.id, &e.hash)).collect();
+    for checkpoint in checkpoints {
+        let signature = sign_checkpoint(
+            key, &checkpoint.tenant, checkpoint.last_entry_id, &checkpoint.hash, checkpoint.created_at
+        );
+        if signature != checkpoint.signature {
+            return Some(ChainBreak {
+                entry_id: checkpoint.last_entry_id,
+                reason: String::from("Checkpoint signature is invalid"),
+            });
+        }
+        if hashes.get(&checkpoint.last_entry_id) != Some(&&checkpoint.hash) {
+            return Some(ChainBreak {
+                entry_id: checkpoint.last_entry_id,
+                reason: String::from("Checkpointed entry is missing or altered"),
+            });
+        }
+    }
+    None
+}
+
+/// Identifies a chain in `auth_audit_chain_heads` - its tenant id, or 0 for entries without one
+fn chain_id(tenant_id: Option<TidInternal>) -> TidInternal {
+    tenant_id.unwrap_or(0)
+}
+
+/// Locks the head of a chain until the end of the current transaction and returns the hash of its
+/// last entry. Appends from any instance queue up behind the lock, so two entries never claim the
+/// same predecessor. A chain's head row is created on its first append, from the entries already
+/// in the log.
+pub fn lock_chain_head(db: &mut DbConn, tenant_id: Option<TidInternal>) -> String {
+    db.query_drop(&format!("
+        INSERT IGNORE INTO auth_audit_chain_heads (chain_id, hash)
+        VALUES ({}, COALESCE(
+            (SELECT hash FROM auth_audit_log WHERE {} ORDER BY id DESC LIMIT 1),
+            {}
+        ))
+    ", sql!("{}", chain_id(tenant_id)), chain_condition(tenant_id), sql!("{}", GENESIS_HASH)));
+
+    db.query_first(&sql!(
+        "SELECT hash FROM auth_audit_chain_heads WHERE chain_id {=} FOR UPDATE", chain_id(tenant_id)
+    ))
+        .map(|(hash,): (String,)| hash)
+        .unwrap_or(String::from(GENESIS_HASH))
+}
+
+/// Moves the head of a chain, locked with `lock_chain_head`, to a newly appended entry
+pub fn set_chain_head(db: &mut DbConn, tenant_id: Option<TidInternal>, hash: &str) {
+    db.query_drop(&sql!(
+        "UPDATE auth_audit_chain_heads SET hash = {} WHERE chain_id = {}", hash, chain_id(tenant_id)
+    ));
+}
+
+/// Retrieves the hash of the most recent entry in a chain
+pub fn get_chain_head(db: &mut DbConn, tenant_id: Option<TidInternal>) -> Option<(u64, String)> {
+    db.query_first(&format!(
+        "SELECT id, hash FROM auth_audit_log WHERE {} ORDER BY id DESC LIMIT 1",
+        chain_condition(tenant_id)
+    ))
+}
+
+/// Retrieves a whole chain, oldest first
+pub fn get_chain(db: &mut DbConn, tenant_id: Option<TidInternal>) -> Vec<ChainedEntry> {
+    db.query_map(&format!("
+        SELECT id, actor_id, tenant_id, action, target_type, target_ref, changes, request_id, created_at, prev_hash, hash
+        FROM auth_audit_log
+        WHERE {}
+        ORDER BY id ASC
+    ", chain_condition(tenant_id)),
+        |(id, actor_id, tenant_id, action, target_type, target_ref, changes, request_id, created_at, prev_hash, hash): (
+            u64, Option<u64>, Option<TidInternal>, String, String, String, Option<String>, Option<String>, u64, String, String
+        )| ChainedEntry {
+            id, actor_id, tenant_id, action, target_type, target_ref, changes, request_id, created_at, prev_hash, hash
+        }
+    )
+}
+
+/// Retrieves the checkpoints of a chain, oldest first
+pub fn get_checkpoints(db: &mut DbConn, tenant_id: Option<TidInternal>) -> Vec<AuditCheckpoint> {
+    db.query_map(&format!("
+        SELECT auth_tenants.tenant_ref, last_entry_id, hash, created_at, signature
+        FROM auth_audit_checkpoints
+            LEFT JOIN auth_tenants ON auth_tenants.id = auth_audit_checkpoints.tenant_id
+        WHERE auth_audit_checkpoints.{}
+        ORDER BY last_entry_id ASC
+    ", chain_condition(tenant_id)),
+        |(tenant, last_entry_id, hash, created_at, signature):
+        (Option<InternalReference<TenantRef>>, u64, String, u64, String)| AuditCheckpoint {
+            tenant: tenant.map(|t| t.inner()),
+            last_entry_id, hash, created_at, signature
+        }
+    )
+}
+
+/// Records a signed checkpoint at the head of every chain that has grown since its last one
+pub fn create_checkpoints(db: &mut DbConn, key: &str) {
+    let chains: Vec<(Option<TidInternal>, Option<InternalReference<TenantRef>>)> = db.query_map(&sql!("
+        SELECT DISTINCT auth_audit_log.tenant_id, auth_tenants.tenant_ref
+        FROM auth_audit_log
+            LEFT JOIN auth_tenants ON auth_tenants.id = auth_audit_log.tenant_id
+    "), |row| row);
+
+    for (tenant_id, tenant_ref) in chains {
+        let tenant = tenant_ref.map(|t| t.inner());
+        let (last_entry_id, hash) = match get_chain_head(db, tenant_id) {
+            Some(head) => head,
+            None => continue,
+        };
+        let already_checkpointed = db.query_count(&format!(
+            "SELECT COUNT(*) FROM auth_audit_checkpoints WHERE {} AND last_entry_id >= {}",
+            chain_condition(tenant_id), last_entry_id
+        )) > 0;
+        if already_checkpointed {
+            continue;
+        }
+
+        let created_at = crate::utils::time::now();
+        let signature = sign_checkpoint(key, &tenant, last_entry_id, &hash, created_at);
+        db.query_drop(&sql!(
+            "INSERT INTO auth_audit_checkpoints (tenant_id, last_entry_id, hash, created_at, signature)
+            VALUES ({}, {}, {}, {}, {})",
+            tenant_id, last_entry_id, hash, created_at, signature
+        ));
+    }
+}
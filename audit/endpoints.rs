@@ -0,0 +1,108 @@
+use std::str::FromStr;
+
+use base::{err_response, requests::UserRequest};
+use rocket::Route;
+use rocket_contrib::json::Json;
+use user_auth_structs::{TenantRef, UserRef};
+
+use crate::{UserAuthErrResponse, tenants, users};
+use crate::authz::{policy::authorize, structures::{Action, Resource}};
+use super::{chain, error::AuditEndpointError, internal, structures::*};
+
+pub fn get_endpoints() -> Vec<Route> {
+    routes![get_audit_log, verify_audit_log, get_audit_checkpoints]
+}
+
+/// Works out which chain the caller may look at: any for superusers, where no tenant means the
+/// chain of entries without a tenant, and only their own tenant's for tenant admins
+fn readable_tenant(
+    request: &mut UserRequest<crate::ConfigType>,
+    tenant: Option<TenantRef>,
+) -> Result<Option<TenantRef>, UserAuthErrResponse> {
+    let tenant = match request.user().is_superuser {
+        true  => tenant,
+        false => Some(tenant.unwrap_or(request.user_login_info().tenant_info.tenant_ref.clone())),
+    };
+    authorize(request, Action::AuditRead, Resource::none().with_tenant(tenant.clone()), AuditEndpointError::ReadingDenied)?;
+    Ok(tenant)
+}
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 500;
+
+/// Reads the audit log, newest first. Pass the id of the last entry received as `before` to
+/// get the next page. Tenant admins may only read their own tenant's entries.
+#[get("/?<tenant>&<actor>&<action>&<target>&<before>&<limit>")]
+pub fn get_audit_log(
+    tenant: Option<TenantRef>,
+    actor: Option<UserRef>,
+    action: Option<String>,
+    target: Option<String>,
+    before: Option<u64>,
+    limit: Option<u64>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<AuditEntry>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let tenant = readable_tenant(&mut request, tenant)?;
+
+
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return err_response!(AuditEndpointError::InvalidLimit(limit));
+    }
+    let action = match action {
+        Some(action) => Some(AuditAction::from_str(&action).map_err(|_|
+            UserAuthErrResponse::new(AuditEndpointError::InvalidAction(action))
+        )?),
+        None => None,
+    };
+    let tenant_id = match tenant {
+        Some(tenant_ref) => Some(tenants::internal::decode_tenant_ref(request.db(), tenant_ref)?),
+        None => None,
+    };
+    let actor_id = match actor {
+        Some(user_ref) => Some(users::internal::decode_any_user_ref(request.db(), user_ref)?),
+        None => None,
+    };
+
+    Ok(Json(internal::query(request.db(), &AuditQuery {
+        tenant_id, actor_id, action, target, before, limit
+    })))
+}
+
+/// Walks a tenant's audit chain and reports the first entry found to be altered or missing
+#[get("/verify?<tenant>")]
+pub fn verify_audit_log(
+    tenant: Option<TenantRef>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<ChainVerification>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let tenant = readable_tenant(&mut request, tenant)?;
+
+
+    let tenant_id = match tenant.clone() {
+        Some(tenant_ref) => Some(tenants::internal::decode_tenant_ref(request.db(), tenant_ref)?),
+        None => None,
+    };
+    let key = request.specific_config().audit_checkpoint_key.clone();
+
+    Ok(Json(internal::verify_chain(request.db(), tenant_id, tenant, &key)))
+}
+
+/// Exports the signed checkpoints of a tenant's audit chain
+#[get("/checkpoints?<tenant>")]
+pub fn get_audit_checkpoints(
+    tenant: Option<TenantRef>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<AuditCheckpoint>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let tenant = readable_tenant(&mut request, tenant)?;
+
+
+    let tenant_id = match tenant {
+        Some(tenant_ref) => Some(tenants::internal::decode_tenant_ref(request.db(), tenant_ref)?),
+        None => None,
+    };
+
+    Ok(Json(chain::get_checkpoints(request.db(), tenant_id)))
+}
@@ -0,0 +1,46 @@
+use base::{Status, requests::response::MicroserviceError};
+
+#[derive(Debug)]
+pub enum AuditEndpointError {
+    ReadingDenied,
+    InvalidAction(String),
+    InvalidLimit(u64),
+}
+
+impl MicroserviceError for AuditEndpointError {
+    fn err_code(&self) -> u16 {
+        match self {
+            AuditEndpointError::ReadingDenied    => 0x0300,
+            AuditEndpointError::InvalidAction(_) => 0x0301,
+            AuditEndpointError::InvalidLimit(_)  => 0x0302,
+        }
+    }
+
+    fn user_message(&self) -> String {
+        match self {
+            AuditEndpointError::ReadingDenied => format!("Permission denied"),
+            AuditEndpointError::InvalidAction(action) => format!("Unknown audit action [{}]", action),
+            AuditEndpointError::InvalidLimit(_) => format!("Limit must be between 1 and 500"),
+        }
+    }
+
+    fn detailed_message(&self) -> String {
+        match self {
+            AuditEndpointError::ReadingDenied => format!("Permission denied: reading audit log"),
+            AuditEndpointError::InvalidAction(action) => format!("Unknown audit action [{}]", action),
+            AuditEndpointError::InvalidLimit(limit) => format!("Invalid audit log page size [{}]", limit),
+        }
+    }
+
+    fn status(&self) -> base::Status {
+        match self {
+            AuditEndpointError::ReadingDenied    => Status::Forbidden,
+            AuditEndpointError::InvalidAction(_) => Status::BadRequest,
+            AuditEndpointError::InvalidLimit(_)  => Status::BadRequest,
+        }
+    }
+
+    fn err_prefix() -> u16 {
+        unimplemented!()
+    }
+}
@@ -0,0 +1,180 @@
+use std::str::FromStr;
+
+use base::{DbConn, db::error_handling::DatabaseErrHandler, references::InternalReference, requests::UserRequest, sql};
+use serde_json::{json, Map, Value};
+use user_auth_structs::{TenantRef, UserRef};
+
+use crate::{tenants::internal::TidInternal, users::{self, internal::UidInternal}, utils::time};
+use super::{chain, structures::*};
+
+/// Reduces the before and after states of a change to the fields that actually changed
+pub fn diff(before: &Option<Value>, after: &Option<Value>) -> Option<Value> {
+    match (before, after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => {
+            let mut changes = Map::new();
+            for (key, new) in after {
+                let old = before.get(key).unwrap_or(&Value::Null);
+                if old != new {
+                    changes.insert(key.clone(), json!({ "before": old, "after": new }));
+                }
+            }
+            for (key, old) in before {
+                if !after.contains_key(key) {
+                    changes.insert(key.clone(), json!({ "before": old, "after": Value::Null }));
+                }
+            }
+            Some(Value::Object(changes))
+        },
+        (None, None) => None,
+        (before, after) => Some(json!({ "before": before, "after": after })),
+    }
+}
+
+/// Appends an event to the audit log, chaining it to the previous entry of its tenant. Call
+/// inside the same transaction as the change it records, so that one is never kept without the
+/// other - the transaction also holds the chain's lock until it ends.
+pub fn insert_entry(
+    db: &mut DbConn,
+    actor_id: Option<UidInternal>,
+    request_id: Option<String>,
+    event: AuditEvent,
+) -> u64 {
+    let prev_hash = chain::lock_chain_head(db, event.tenant_id);
+
+    let mut entry = ChainedEntry {
+        id: 0,
+        actor_id,
+        tenant_id: event.tenant_id,
+        action: event.action.as_str().to_string(),
+        target_type: event.target.kind().to_string(),
+        target_ref: event.target.reference(),
+        changes: diff(&event.before, &event.after).map(|c| c.to_string()),
+        request_id,
+        created_at: time::now(),
+        prev_hash,
+        hash: String::new(),
+    };
+    entry.hash = chain::hash_entry(&entry);
+
+    let id = db.query_insert(&sql!(
+        "INSERT INTO auth_audit_log (actor_id, tenant_id, action, target_type, target_ref, changes, request_id, created_at, prev_hash, hash)
+        VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {}, {})",
+        entry.actor_id,
+        entry.tenant_id,
+        entry.action,
+        entry.target_type,
+        entry.target_ref,
+        entry.changes,
+        entry.request_id,
+        entry.created_at,
+        entry.prev_hash,
+        entry.hash
+    ));
+    chain::set_chain_head(db, event.tenant_id, &entry.hash);
+    id
+}
+
+/// Checks a tenant's chain, or the chain of entries without a tenant, from start to end
+pub fn verify_chain(db: &mut DbConn, tenant_id: Option<TidInternal>, tenant: Option<TenantRef>, key: &str)
+-> ChainVerification {
+    let entries = chain::get_chain(db, tenant_id);
+    let checkpoints = chain::get_checkpoints(db, tenant_id);
+
+    let first_break = chain::verify_entries(&entries)
+        .or_else(|| chain::verify_checkpoints(&entries, &checkpoints, key));
+
+    ChainVerification {
+        tenant,
+        entries_checked: entries.len(),
+        checkpoints_checked: checkpoints.len(),
+        first_break,
+    }
+}
+
+/// Appends an event to the audit log on behalf of the user making the request. Call inside the
+/// transaction of the change, like `insert_entry`.
+pub fn record(request: &mut UserRequest<crate::ConfigType>, event: AuditEvent) -> u64 {
+    let actor_ref = request.user_ref();
+    let actor_id = users::internal::decode_user_ref(request.db(), actor_ref).ok();
+    let request_id = request.logger().request_id().to_string();
+
+    insert_entry(request.db(), actor_id, Some(request_id), event)
+}
+
+fn entries_from_query(db: &mut DbConn, condition: String, limit: u64) -> Vec<AuditEntry> {
+    let db_err = db.err_handler();
+    db.query_map(&format!("
+        SELECT
+            auth_audit_log.id,
+            auth_users.user_ref,
+            auth_tenants.tenant_ref,
+            auth_audit_log.action,
+            auth_audit_log.target_type,
+            auth_audit_log.target_ref,
+            auth_audit_log.changes,
+            auth_audit_log.request_id,
+            auth_audit_log.created_at
+        FROM auth_audit_log
+            LEFT JOIN auth_users ON auth_users.id = auth_audit_log.actor_id
+            LEFT JOIN auth_tenants ON auth_tenants.id = auth_audit_log.tenant_id
+        WHERE {}
+        ORDER BY auth_audit_log.id DESC
+        LIMIT {}
+    ", condition, limit),
+        |(id, actor, tenant, action, target_type, target, changes, request_id, created_at): (
+            u64,
+            Option<InternalReference<UserRef>>,
+            Option<InternalReference<TenantRef>>,
+            String,
+            String,
+            String,
+            Option<String>,
+            Option<String>,
+            u64,
+        )| {
+            let action = AuditAction::from_str(&action).db_expect(&db_err);
+            AuditEntry {
+                id,
+                actor: actor.map(|a| a.inner()),
+                tenant: tenant.map(|t| t.inner()),
+                action,
+                target_type,
+                target,
+                changes: changes.and_then(|c| serde_json::from_str(&c).ok()),
+                request_id,
+                created_at,
+            }
+        },
+    )
+}
+
+/// Reads a page of the audit log matching the given filters, newest first
+pub fn query(db: &mut DbConn, query: &AuditQuery) -> Vec<AuditEntry> {
+    let mut conditions = vec![String::from("1 = 1")];
+    if let Some(tenant_id) = query.tenant_id {
+        conditions.push(sql!("auth_audit_log.tenant_id {=}", tenant_id));
+    }
+    if let Some(actor_id) = query.actor_id {
+        conditions.push(sql!("auth_audit_log.actor_id {=}", actor_id));
+    }
+    if let Some(action) = query.action {
+        conditions.push(sql!("auth_audit_log.action {=}", action));
+    }
+    if let Some(target) = &query.target {
+        conditions.push(sql!("auth_audit_log.target_ref {=}", target));
+    }
+    if let Some(before) = query.before {
+        conditions.push(sql!("auth_audit_log.id < {}", before));
+    }
+
+    entries_from_query(db, conditions.join(" AND "), query.limit)
+}
+
+/// Reads every audit entry either about the user or performed by them
+pub fn get_user_entries(db: &mut DbConn, user_id: UidInternal, user_ref: &UserRef) -> Vec<AuditEntry> {
+    let condition = sql!(
+        "(auth_audit_log.target_type = 'user' AND auth_audit_log.target_ref {=}) OR auth_audit_log.actor_id {=}",
+        user_ref.to_string(), user_id
+    );
+    entries_from_query(db, condition, u64::MAX)
+}
@@ -0,0 +1,7 @@
+pub mod chain;
+pub mod endpoints;
+pub mod error;
+pub mod internal;
+pub mod structures;
+
+pub use error::*;
@@ -0,0 +1,259 @@
+use std::str::FromStr;
+
+use base::db::to_sql::AsSql;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use user_auth_structs::{GroupRef, TenantRef, UserRef};
+
+use crate::tenants::internal::TidInternal;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    UserCreated,
+    UserUpdated,
+    UserDeleted,
+    UserRestored,
+    UserErased,
+    UserSuspended,
+    UserReactivated,
+    PasswordChanged,
+    SessionRevoked,
+    TenantCreated,
+    TenantUpdated,
+    TenantArchived,
+    TenantUnarchived,
+    TenantDeleted,
+    UserAddedToTenant,
+    UserRemovedFromTenant,
+    AdminPromoted,
+    AdminDemoted,
+    GroupCreated,
+    GroupUpdated,
+    GroupDeleted,
+    UserAddedToGroup,
+    UserRemovedFromGroup,
+    GroupOwnerAdded,
+    GroupOwnerRemoved,
+    GroupNested,
+    GroupUnnested,
+    RoleCreated,
+    RoleUpdated,
+    RoleDeleted,
+    RoleAssigned,
+    RoleUnassigned,
+    RoleDelegated,
+    RoleDelegationRevoked,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UserCreated           => "user_created",
+            Self::UserUpdated           => "user_updated",
+            Self::UserDeleted           => "user_deleted",
+            Self::UserRestored          => "user_restored",
+            Self::UserErased            => "user_erased",
+            Self::UserSuspended         => "user_suspended",
+            Self::UserReactivated       => "user_reactivated",
+            Self::PasswordChanged       => "password_changed",
+            Self::SessionRevoked        => "session_revoked",
+            Self::TenantCreated         => "tenant_created",
+            Self::TenantUpdated         => "tenant_updated",
+            Self::TenantArchived        => "tenant_archived",
+            Self::TenantUnarchived      => "tenant_unarchived",
+            Self::TenantDeleted         => "tenant_deleted",
+            Self::UserAddedToTenant     => "user_added_to_tenant",
+            Self::UserRemovedFromTenant => "user_removed_from_tenant",
+            Self::AdminPromoted         => "admin_promoted",
+            Self::AdminDemoted          => "admin_demoted",
+            Self::GroupCreated          => "group_created",
+            Self::GroupUpdated          => "group_updated",
+            Self::GroupDeleted          => "group_deleted",
+            Self::UserAddedToGroup      => "user_added_to_group",
+            Self::UserRemovedFromGroup  => "user_removed_from_group",
+            Self::GroupOwnerAdded       => "group_owner_added",
+            Self::GroupOwnerRemoved     => "group_owner_removed",
+            Self::GroupNested           => "group_nested",
+            Self::GroupUnnested         => "group_unnested",
+            Self::RoleCreated           => "role_created",
+            Self::RoleUpdated           => "role_updated",
+            Self::RoleDeleted           => "role_deleted",
+            Self::RoleAssigned          => "role_assigned",
+            Self::RoleUnassigned        => "role_unassigned",
+            Self::RoleDelegated         => "role_delegated",
+            Self::RoleDelegationRevoked => "role_delegation_revoked",
+        }
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user_created"             => Ok(Self::UserCreated),
+            "user_updated"             => Ok(Self::UserUpdated),
+            "user_deleted"             => Ok(Self::UserDeleted),
+            "user_restored"            => Ok(Self::UserRestored),
+            "user_erased"              => Ok(Self::UserErased),
+            "user_suspended"           => Ok(Self::UserSuspended),
+            "user_reactivated"         => Ok(Self::UserReactivated),
+            "password_changed"         => Ok(Self::PasswordChanged),
+            "session_revoked"          => Ok(Self::SessionRevoked),
+            "tenant_created"           => Ok(Self::TenantCreated),
+            "tenant_updated"           => Ok(Self::TenantUpdated),
+            "tenant_archived"          => Ok(Self::TenantArchived),
+            "tenant_unarchived"        => Ok(Self::TenantUnarchived),
+            "tenant_deleted"           => Ok(Self::TenantDeleted),
+            "user_added_to_tenant"     => Ok(Self::UserAddedToTenant),
+            "user_removed_from_tenant" => Ok(Self::UserRemovedFromTenant),
+            "admin_promoted"           => Ok(Self::AdminPromoted),
+            "admin_demoted"            => Ok(Self::AdminDemoted),
+            "group_created"            => Ok(Self::GroupCreated),
+            "group_updated"            => Ok(Self::GroupUpdated),
+            "group_deleted"            => Ok(Self::GroupDeleted),
+            "user_added_to_group"      => Ok(Self::UserAddedToGroup),
+            "user_removed_from_group"  => Ok(Self::UserRemovedFromGroup),
+            "group_owner_added"        => Ok(Self::GroupOwnerAdded),
+            "group_owner_removed"      => Ok(Self::GroupOwnerRemoved),
+            "group_nested"             => Ok(Self::GroupNested),
+            "group_unnested"           => Ok(Self::GroupUnnested),
+            "role_created"             => Ok(Self::RoleCreated),
+            "role_updated"             => Ok(Self::RoleUpdated),
+            "role_deleted"             => Ok(Self::RoleDeleted),
+            "role_assigned"            => Ok(Self::RoleAssigned),
+            "role_unassigned"          => Ok(Self::RoleUnassigned),
+            "role_delegated"           => Ok(Self::RoleDelegated),
+            "role_delegation_revoked"  => Ok(Self::RoleDelegationRevoked),
+            _ => Err("Invalid audit action"),
+        }
+    }
+}
+
+impl AsSql for AuditAction {
+    fn as_sql(&self) -> String {
+        AsSql::as_sql(&self.as_str())
+    }
+
+    fn get_eq_operator(&self) -> &'static str {
+        "="
+    }
+}
+
+/// The thing an audited action was performed on
+#[derive(Clone, Debug)]
+pub enum AuditTarget {
+    User(UserRef),
+    Tenant(TenantRef),
+    Group(GroupRef),
+    Session(u64),
+    Role(u64),
+}
+
+impl AuditTarget {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::User(_)    => "user",
+            Self::Tenant(_)  => "tenant",
+            Self::Group(_)   => "group",
+            Self::Session(_) => "session",
+            Self::Role(_)    => "role",
+        }
+    }
+
+    pub fn reference(&self) -> String {
+        match self {
+            Self::User(r)    => r.to_string(),
+            Self::Tenant(r)  => r.to_string(),
+            Self::Group(r)   => r.to_string(),
+            Self::Session(id) => id.to_string(),
+            Self::Role(id)    => id.to_string(),
+        }
+    }
+}
+
+/// An event to be written to the audit log. The actor and request id are filled in when recorded.
+pub struct AuditEvent {
+    pub action: AuditAction,
+    pub tenant_id: Option<TidInternal>,
+    pub target: AuditTarget,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction, tenant_id: Option<TidInternal>, target: AuditTarget) -> Self {
+        Self { action, tenant_id, target, before: None, after: None }
+    }
+
+    /// Attaches the state of the target before and after the change
+    pub fn with_change<T: Serialize>(mut self, before: &T, after: &T) -> Self {
+        self.before = serde_json::to_value(before).ok();
+        self.after = serde_json::to_value(after).ok();
+        self
+    }
+}
+
+/// An entry read back from the audit log
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub id: u64,
+    pub actor: Option<UserRef>,
+    pub tenant: Option<TenantRef>,
+    pub action: AuditAction,
+    pub target_type: String,
+    pub target: String,
+    pub changes: Option<Value>,
+    pub request_id: Option<String>,
+    pub created_at: u64,
+}
+
+/// Filters for reading the audit log, newest entries first
+pub struct AuditQuery {
+    pub tenant_id: Option<TidInternal>,
+    pub actor_id: Option<u64>,
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    pub before: Option<u64>,
+    pub limit: u64,
+}
+
+/// An audit log row with the fields covered by its hash
+pub struct ChainedEntry {
+    pub id: u64,
+    pub actor_id: Option<u64>,
+    pub tenant_id: Option<TidInternal>,
+    pub action: String,
+    pub target_type: String,
+    pub target_ref: String,
+    pub changes: Option<String>,
+    pub request_id: Option<String>,
+    pub created_at: u64,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// A signed record of the head of a chain at some point in time
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditCheckpoint {
+    pub tenant: Option<TenantRef>,
+    pub last_entry_id: u64,
+    pub hash: String,
+    pub created_at: u64,
+    pub signature: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChainBreak {
+    pub entry_id: u64,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChainVerification {
+    pub tenant: Option<TenantRef>,
+    pub entries_checked: usize,
+    pub checkpoints_checked: usize,
+    pub first_break: Option<ChainBreak>,
+}
@@ -0,0 +1,65 @@
+use base::{err_response, requests::{response::text_response::JsonBody, UserRequest}};
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use crate::UserAuthErrResponse;
+use super::{error::AuthzEndpointError, policy::{self, Subject}, structures::*};
+
+pub fn get_endpoints() -> Vec<Route> {
+    routes![check, check_batch]
+}
+
+const MAX_BATCH_SIZE: usize = 100;
+
+/// Anyone may check their own access; checking someone else's requires administering the tenant
+fn can_check(request: &UserRequest<crate::ConfigType>, check: &CheckRequest) -> bool {
+    request.user().is_superuser ||
+    request.user_login_info().is_admin_in_tenant(&check.tenant) ||
+    request.user().user_ref == check.user
+}
+
+fn decide(request: &mut UserRequest<crate::ConfigType>, check: CheckRequest) -> Result<Decision, UserAuthErrResponse> {
+    match Subject::load(request.db(), check.user, check.tenant)? {
+        Some(subject) => policy::evaluate(request.db(), &subject, check.action, &check.resource),
+        None => Ok(Decision::deny("user is not a member of the tenant")),
+    }
+}
+
+/// Decides whether a user, acting in a tenant, may perform an action on an optional resource
+#[post("/check", data = "<check>")]
+pub fn check(
+    check: JsonBody<CheckRequest>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Decision>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    if !can_check(&request, &check.0) {
+        return err_response!(AuthzEndpointError::CheckDenied);
+    }
+
+
+    decide(&mut request, check.0).map(|d| Json(d))
+}
+
+/// Decides several checks at once, answering in the same order
+#[post("/check/batch", data = "<batch>")]
+pub fn check_batch(
+    batch: JsonBody<BatchCheckRequest>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Decision>>, UserAuthErrResponse> {
+    let checks = batch.0.checks;
+    if checks.len() == 0 || checks.len() > MAX_BATCH_SIZE {
+        return err_response!(AuthzEndpointError::InvalidBatchSize(checks.len()));
+    }
+
+    //==PERMISSION CHECK==
+    if !checks.iter().all(|check| can_check(&request, check)) {
+        return err_response!(AuthzEndpointError::CheckDenied);
+    }
+
+
+    let mut decisions = Vec::with_capacity(checks.len());
+    for check in checks {
+        decisions.push(decide(&mut request, check)?);
+    }
+    Ok(Json(decisions))
+}
@@ -0,0 +1,41 @@
+use base::{Status, requests::response::MicroserviceError};
+
+#[derive(Debug)]
+pub enum AuthzEndpointError {
+    CheckDenied,
+    InvalidBatchSize(usize),
+}
+
+impl MicroserviceError for AuthzEndpointError {
+    fn err_code(&self) -> u16 {
+        match self {
+            AuthzEndpointError::CheckDenied         => 0x0600,
+            AuthzEndpointError::InvalidBatchSize(_) => 0x0601,
+        }
+    }
+
+    fn user_message(&self) -> String {
+        match self {
+            AuthzEndpointError::CheckDenied         => format!("Permission denied"),
+            AuthzEndpointError::InvalidBatchSize(_) => format!("Invalid number of checks"),
+        }
+    }
+
+    fn detailed_message(&self) -> String {
+        match self {
+            AuthzEndpointError::CheckDenied         => format!("Permission denied: checking another user's access requires being a superuser or admin of the tenant"),
+            AuthzEndpointError::InvalidBatchSize(n) => format!("A batch must contain between 1 and 100 checks, got {}", n),
+        }
+    }
+
+    fn status(&self) -> base::Status {
+        match self {
+            AuthzEndpointError::CheckDenied         => Status::Forbidden,
+            AuthzEndpointError::InvalidBatchSize(_) => Status::BadRequest,
+        }
+    }
+
+    fn err_prefix() -> u16 {
+        unimplemented!()
+    }
+}
@@ -0,0 +1,6 @@
+pub mod endpoints;
+pub mod error;
+pub mod policy;
+pub mod structures;
+
+pub use error::*;
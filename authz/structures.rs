@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+use user_auth_structs::{GroupRef, TenantRef, UserRef};
+
+/// Something a user may try to do. The names double as role permission names, so a role holding
+/// e.g. `groups.manage` grants that action in its tenant. A permission may be limited to a single
+/// group as `<action>@<group_ref>`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    #[serde(rename = "users.read")]             UsersRead,
+    #[serde(rename = "users.create")]           UsersCreate,
+    #[serde(rename = "users.update")]           UsersUpdate,
+    #[serde(rename = "users.delete")]           UsersDelete,
+    #[serde(rename = "users.privacy")]          UsersPrivacy,
+    #[serde(rename = "users.suspend")]          UsersSuspend,
+    #[serde(rename = "users.reactivate")]       UsersReactivate,
+    #[serde(rename = "users.reset_password")]   UsersResetPassword,
+    #[serde(rename = "users.sessions")]         UsersSessions,
+    #[serde(rename = "tenants.create")]         TenantsCreate,
+    #[serde(rename = "tenants.list")]           TenantsList,
+    #[serde(rename = "tenants.update")]         TenantsUpdate,
+    #[serde(rename = "tenants.archive")]        TenantsArchive,
+    #[serde(rename = "tenants.delete")]         TenantsDelete,
+    #[serde(rename = "tenants.read")]           TenantsRead,
+    #[serde(rename = "tenants.read_details")]   TenantsReadDetails,
+    #[serde(rename = "tenants.manage_members")] TenantsManageMembers,
+    #[serde(rename = "tenants.manage_admins")]  TenantsManageAdmins,
+    #[serde(rename = "groups.read")]            GroupsRead,
+    #[serde(rename = "groups.create")]          GroupsCreate,
+    #[serde(rename = "groups.update")]          GroupsUpdate,
+    #[serde(rename = "groups.manage")]          GroupsManage,
+    #[serde(rename = "groups.delete")]          GroupsDelete,
+    #[serde(rename = "groups.manage_owners")]   GroupsManageOwners,
+    #[serde(rename = "audit.read")]             AuditRead,
+    #[serde(rename = "webhooks.manage")]        WebhooksManage,
+    #[serde(rename = "roles.manage")]           RolesManage,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UsersRead            => "users.read",
+            Self::UsersCreate          => "users.create",
+            Self::UsersUpdate          => "users.update",
+            Self::UsersDelete          => "users.delete",
+            Self::UsersPrivacy         => "users.privacy",
+            Self::UsersSuspend         => "users.suspend",
+            Self::UsersReactivate      => "users.reactivate",
+            Self::UsersResetPassword   => "users.reset_password",
+            Self::UsersSessions        => "users.sessions",
+            Self::TenantsCreate        => "tenants.create",
+            Self::TenantsList          => "tenants.list",
+            Self::TenantsUpdate        => "tenants.update",
+            Self::TenantsArchive       => "tenants.archive",
+            Self::TenantsDelete        => "tenants.delete",
+            Self::TenantsRead          => "tenants.read",
+            Self::TenantsReadDetails   => "tenants.read_details",
+            Self::TenantsManageMembers => "tenants.manage_members",
+            Self::TenantsManageAdmins  => "tenants.manage_admins",
+            Self::GroupsRead           => "groups.read",
+            Self::GroupsCreate         => "groups.create",
+            Self::GroupsUpdate         => "groups.update",
+            Self::GroupsManage         => "groups.manage",
+            Self::GroupsDelete         => "groups.delete",
+            Self::GroupsManageOwners   => "groups.manage_owners",
+            Self::AuditRead            => "audit.read",
+            Self::WebhooksManage       => "webhooks.manage",
+            Self::RolesManage          => "roles.manage",
+        }
+    }
+
+    /// Whether a role may grant this action - actions that reach beyond a single tenant are
+    /// reserved for superusers, and those that hand out permissions for tenant admins, so that a
+    /// role can't be used to grant more than it holds
+    pub fn is_delegable(&self) -> bool {
+        match self {
+            Self::UsersCreate | Self::UsersDelete | Self::UsersPrivacy |
+            Self::TenantsCreate | Self::TenantsList | Self::TenantsArchive | Self::TenantsDelete |
+            Self::TenantsManageMembers | Self::TenantsManageAdmins | Self::RolesManage => false,
+            _ => true,
+        }
+    }
+
+    /// Who may perform the action
+    pub fn rule(&self) -> Rule {
+        match self {
+            Self::UsersRead            => Rule::SelfOrTenantMember,
+            Self::UsersCreate          => Rule::ExplicitTenantAdmin,
+            Self::UsersUpdate          => Rule::SelfOrTenantAdmin,
+            Self::UsersDelete          => Rule::Superuser,
+            Self::UsersPrivacy         => Rule::Superuser,
+            Self::UsersSuspend         => Rule::ExplicitTenantAdmin,
+            Self::UsersReactivate      => Rule::ExplicitTenantAdmin,
+            Self::UsersResetPassword   => Rule::SelfOrTenantAdmin,
+            Self::UsersSessions        => Rule::SelfOrTenantAdmin,
+            Self::TenantsCreate        => Rule::Superuser,
+            Self::TenantsList          => Rule::Superuser,
+            Self::TenantsUpdate        => Rule::TenantAdmin,
+            Self::TenantsArchive       => Rule::Superuser,
+            Self::TenantsDelete        => Rule::Superuser,
+            Self::TenantsRead          => Rule::TenantMember,
+            Self::TenantsReadDetails   => Rule::TenantAdmin,
+            Self::TenantsManageMembers => Rule::Superuser,
+            Self::TenantsManageAdmins  => Rule::TenantAdmin,
+            Self::GroupsRead           => Rule::GroupMemberOwnerOrTenantAdmin,
+            Self::GroupsCreate         => Rule::TenantAdmin,
+            Self::GroupsUpdate         => Rule::GroupOwnerOrTenantAdmin,
+            Self::GroupsManage         => Rule::GroupOwnerOrTenantAdmin,
+            Self::GroupsDelete         => Rule::TenantAdmin,
+            Self::GroupsManageOwners   => Rule::TenantAdmin,
+            Self::AuditRead            => Rule::TenantAdmin,
+            Self::WebhooksManage       => Rule::TenantAdmin,
+            Self::RolesManage          => Rule::TenantAdmin,
+        }
+    }
+
+    /// Conditions on the resource that hold whoever is asking, superusers included
+    pub fn constraints(&self) -> &'static [Constraint] {
+        match self {
+            Self::UsersSuspend | Self::UsersReactivate => &[Constraint::NotSelf, Constraint::UserInTenant],
+            Self::GroupsManage | Self::GroupsManageOwners => &[Constraint::UserInTenant],
+            _ => &[],
+        }
+    }
+}
+
+/// Who may perform an action, the tenant being the resource's tenant
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    Superuser,
+    TenantMember,
+    TenantAdmin,
+    /// Tenant admins, but only when the tenant is given - without one the action reaches beyond
+    /// any single tenant and is reserved for superusers. Tenant admins can't perform it on
+    /// superusers or on fellow admins, so that they can't lock each other out.
+    ExplicitTenantAdmin,
+    /// The user the action is performed on, or members of the tenant if the user is in it too
+    SelfOrTenantMember,
+    /// The user the action is performed on, or admins of the tenant if the user is in it
+    SelfOrTenantAdmin,
+    GroupMemberOwnerOrTenantAdmin,
+    /// Owners of the group, directly or through an owning group, or admins of its tenant
+    GroupOwnerOrTenantAdmin,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Constraint {
+    /// The user the action is performed on may not be the subject
+    NotSelf,
+    /// The user the action is performed on must be a member of the tenant, when the resource
+    /// names one directly or through a group
+    UserInTenant,
+}
+
+/// What an action is performed on. Fields that don't apply are left out; a missing tenant means
+/// the tenant the subject is acting in, or the group's tenant if a group is given.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Resource {
+    pub user:   Option<UserRef>,
+    pub group:  Option<GroupRef>,
+    pub tenant: Option<TenantRef>,
+}
+
+impl Resource {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn user(user: UserRef) -> Self {
+        Self { user: Some(user), ..Self::default() }
+    }
+
+    pub fn group(group: GroupRef) -> Self {
+        Self { group: Some(group), ..Self::default() }
+    }
+
+    pub fn tenant(tenant: TenantRef) -> Self {
+        Self { tenant: Some(tenant), ..Self::default() }
+    }
+
+    pub fn with_user(mut self, user: UserRef) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    pub fn with_tenant(mut self, tenant: Option<TenantRef>) -> Self {
+        self.tenant = tenant;
+        self
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct Decision {
+    pub allowed: bool,
+    pub reason:  String,
+}
+
+impl Decision {
+    pub fn allow(reason: &str) -> Self {
+        Self { allowed: true, reason: reason.to_string() }
+    }
+
+    pub fn deny(reason: &str) -> Self {
+        Self { allowed: false, reason: reason.to_string() }
+    }
+}
+
+/// Asks whether a user, acting in a tenant, may perform an action
+#[derive(Deserialize)]
+pub struct CheckRequest {
+    pub user:     UserRef,
+    pub tenant:   TenantRef,
+    pub action:   Action,
+    #[serde(default)]
+    pub resource: Resource,
+}
+
+#[derive(Deserialize)]
+pub struct BatchCheckRequest {
+    pub checks: Vec<CheckRequest>,
+}
@@ -0,0 +1,34 @@
+use base::{DbPool, requests::UserRequest};
+use rocket::{Route, State, http::ContentType, response::{Content, Stream}};
+
+use crate::{UserAuthErrResponse, tenants};
+use super::{error::EventEndpointError, internal, stream::{EventStream, LastEventId, StreamSlot}};
+
+pub fn get_endpoints() -> Vec<Route> {
+    routes![stream_events]
+}
+
+/// Streams changes in the caller's tenant as server-sent events, once they have been relayed.
+/// Reconnecting clients resume from the id in their `Last-Event-ID` header; otherwise only new
+/// events are sent. Only a limited
+/// number of streams may be open at once, as each holds a worker while open.
+#[get("/stream")]
+pub fn stream_events(
+    last_event_id: LastEventId,
+    pool: State<DbPool>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Content<Stream<EventStream>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    // events are filtered per caller as they are streamed, see `stream::is_visible`
+    let user = request.user_login_info().clone();
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), user.tenant_info.tenant_ref.clone())?;
+
+
+    let max_streams = request.specific_config().max_event_streams.unwrap_or(crate::DEFAULT_MAX_EVENT_STREAMS);
+    let slot = StreamSlot::acquire(max_streams)
+        .ok_or(UserAuthErrResponse::new(EventEndpointError::TooManyStreams))?;
+    let last_relay_seq = last_event_id.0.unwrap_or_else(|| internal::get_latest_relay_seq(request.db()));
+
+    let stream = EventStream::new(pool.inner().clone(), slot, tenant_id, user, last_relay_seq);
+    Ok(Content(ContentType::new("text", "event-stream"), Stream::from(stream)))
+}
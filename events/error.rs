@@ -0,0 +1,36 @@
+use base::{Status, requests::response::MicroserviceError};
+
+#[derive(Debug)]
+pub enum EventEndpointError {
+    TooManyStreams,
+}
+
+impl MicroserviceError for EventEndpointError {
+    fn err_code(&self) -> u16 {
+        match self {
+            EventEndpointError::TooManyStreams => 0x0700,
+        }
+    }
+
+    fn user_message(&self) -> String {
+        match self {
+            EventEndpointError::TooManyStreams => format!("Too many event streams open, try again later"),
+        }
+    }
+
+    fn detailed_message(&self) -> String {
+        match self {
+            EventEndpointError::TooManyStreams => format!("Event stream limit reached"),
+        }
+    }
+
+    fn status(&self) -> base::Status {
+        match self {
+            EventEndpointError::TooManyStreams => Status::ServiceUnavailable,
+        }
+    }
+
+    fn err_prefix() -> u16 {
+        unimplemented!()
+    }
+}
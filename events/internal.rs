@@ -0,0 +1,137 @@
+use std::str::FromStr;
+
+use base::{DbConn, db::error_handling::DatabaseErrHandler, references::InternalReference, sql};
+use serde_json::Value;
+use user_auth_structs::TenantRef;
+
+use crate::{tenants::{self, internal::TidInternal}, users::internal::UidInternal};
+use super::structures::*;
+
+/// Writes an event to the outbox. Call this inside the same transaction as the change it
+/// describes, so that the event is committed if and only if the change is.
+pub fn publish(db: &mut DbConn, tenant_id: Option<TidInternal>, event: EventType, data: Value) -> u64 {
+    db.query_insert(&sql!(
+        "INSERT INTO auth_outbox (tenant_id, event, data, occurred_at) VALUES ({}, {}, {}, UNIX_TIMESTAMP())",
+        tenant_id, event, data.to_string()
+    ))
+}
+
+/// Writes an event about a user to the outbox once for every tenant they belong to, or once
+/// without a tenant if they belong to none. Call this inside the same transaction as the change.
+pub fn publish_for_user(db: &mut DbConn, user_id: UidInternal, event: EventType, data: Value) {
+    let tenant_ids = tenants::internal::get_user_tenant_ids(user_id, db);
+    if tenant_ids.is_empty() {
+        publish(db, None, event, data);
+    }
+    for tenant_id in tenant_ids {
+        publish(db, Some(tenant_id), event, data.clone());
+    }
+}
+
+/// Reads the events relayed after the given relay sequence number, in the order they were relayed,
+/// optionally only those of one tenant. Unlike event ids, which are taken when the event is
+/// written, sequence numbers are handed out as relays commit, so no event turns up behind one
+/// already read.
+pub fn get_relayed_events_after(db: &mut DbConn, after: u64, tenant_id: Option<TidInternal>, limit: u64) -> Vec<OutboxEvent> {
+    let mut conditions = vec![sql!("auth_outbox.relay_seq > {}", after)];
+    if let Some(tenant_id) = tenant_id {
+        conditions.push(sql!("auth_outbox.tenant_id {=}", tenant_id));
+    }
+    events_from_query(db, conditions.join(" AND "), "auth_outbox.relay_seq", format!("LIMIT {}", limit))
+}
+
+/// Claims the oldest events not yet relayed. Call inside a transaction: the rows
+/// stay locked until it ends, and another relay skips past them instead of waiting or handing
+/// them over twice. Unlike reading after an event id, this also finds events whose transaction
+/// committed after a later one's.
+pub fn claim_unrelayed_events(db: &mut DbConn, limit: u64) -> Vec<OutboxEvent> {
+    events_from_query(
+        db,
+        String::from("auth_outbox.relayed_at IS NULL"),
+        "auth_outbox.id",
+        format!("LIMIT {} FOR UPDATE OF auth_outbox SKIP LOCKED", limit)
+    )
+}
+
+fn events_from_query(db: &mut DbConn, condition: String, order_by: &str, tail: String) -> Vec<OutboxEvent> {
+    let db_err = db.err_handler();
+    db.query_map(&format!("
+        SELECT auth_outbox.id, auth_outbox.relay_seq, auth_outbox.tenant_id, auth_tenants.tenant_ref, event, data, occurred_at
+        FROM auth_outbox
+            LEFT JOIN auth_tenants ON auth_tenants.id = auth_outbox.tenant_id
+        WHERE {}
+        ORDER BY {} ASC
+        {}
+    ", condition, order_by, tail),
+        |(event_id, relay_seq, tenant_id, tenant, event, data, occurred_at):
+        (u64, Option<u64>, Option<TidInternal>, Option<InternalReference<TenantRef>>, String, String, u64)| OutboxEvent {
+            event_id,
+            relay_seq,
+            tenant_id,
+            tenant: tenant.map(|t| t.inner()),
+            event: EventType::from_str(&event).db_expect(&db_err),
+            data: serde_json::from_str(&data).unwrap_or(Value::Null),
+            occurred_at,
+        }
+    )
+}
+
+/// Claims the oldest events held back for a sink that failed to handle them, locking them like
+/// `claim_unrelayed_events` does
+pub fn claim_held_back_events(db: &mut DbConn, sink: &str, limit: u64) -> Vec<OutboxEvent> {
+    events_from_query(
+        db,
+        sql!("auth_outbox.id IN (SELECT event_id FROM auth_outbox_held_back WHERE sink {=})", sink),
+        "auth_outbox.id",
+        format!("LIMIT {} FOR UPDATE OF auth_outbox SKIP LOCKED", limit)
+    )
+}
+
+pub fn has_held_back_events(db: &mut DbConn, sink: &str) -> bool {
+    db.query_count(&sql!("SELECT COUNT(*) FROM auth_outbox_held_back WHERE sink {=}", sink)) > 0
+}
+
+/// Keeps an event for a sink to be handed to it again on a later run
+pub fn hold_back(db: &mut DbConn, event_id: u64, sink: &str) {
+    db.query_drop(&sql!(
+        "INSERT IGNORE INTO auth_outbox_held_back (event_id, sink) VALUES ({}, {})", event_id, sink
+    ));
+}
+
+pub fn release_held_back(db: &mut DbConn, event_id: u64, sink: &str) {
+    db.query_drop(&sql!(
+        "DELETE FROM auth_outbox_held_back WHERE event_id {=} AND sink {=}", event_id, sink
+    ));
+}
+
+/// Marks an event as relayed: every sink has handled it, or holds it back to try again
+pub fn mark_relayed(db: &mut DbConn, event_id: u64) {
+    db.query_drop(&sql!("UPDATE auth_outbox SET relayed_at = UNIX_TIMESTAMP() WHERE id = {}", event_id));
+}
+
+/// Gives the events just relayed the next relay sequence numbers. Call last thing before
+/// committing: the sequence row stays locked until then, so relays number their events in the
+/// order they commit.
+pub fn number_relayed_events(db: &mut DbConn, event_ids: &Vec<u64>) {
+    if event_ids.is_empty() {
+        return;
+    }
+    let last_seq = db.query_first(&sql!("SELECT last_seq FROM auth_outbox_sequence FOR UPDATE"))
+        .map(|(last_seq,): (u64,)| last_seq)
+        .unwrap_or(0);
+    for (offset, event_id) in event_ids.iter().enumerate() {
+        db.query_drop(&sql!(
+            "UPDATE auth_outbox SET relay_seq = {} WHERE id = {}", last_seq + 1 + offset as u64, *event_id
+        ));
+    }
+    db.query_drop(&sql!(
+        "UPDATE auth_outbox_sequence SET last_seq = {}", last_seq + event_ids.len() as u64
+    ));
+}
+
+/// The relay sequence number of the most recently relayed event, or 0 if there are none
+pub fn get_latest_relay_seq(db: &mut DbConn) -> u64 {
+    db.query_first(&sql!("SELECT COALESCE(MAX(relay_seq), 0) FROM auth_outbox"))
+        .map(|(relay_seq,): (u64,)| relay_seq)
+        .unwrap_or(0)
+}
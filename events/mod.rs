@@ -0,0 +1,9 @@
+pub mod endpoints;
+pub mod error;
+pub mod internal;
+pub mod relay;
+pub mod sinks;
+pub mod stream;
+pub mod structures;
+
+pub use error::*;
@@ -0,0 +1,46 @@
+use base::DbConn;
+
+use crate::utils::transaction::Transaction;
+use super::{internal, sinks::EventSink};
+
+const BATCH_SIZE: u64 = 100;
+
+/// Hands every outbox event not yet relayed to each sink, then marks it as relayed. An event a
+/// sink fails to handle is held back for that sink alone: the sink is given its held-back events
+/// again on the next runs, and any newer events are held back behind them until it catches up,
+/// while the other sinks carry on. Relayed events are numbered in the order the relays commit,
+/// which is the order event streams send them in.
+pub fn relay(db: &mut DbConn, sinks: &Vec<Box<dyn EventSink>>) {
+    let mut tx = Transaction::start(db);
+
+    let mut behind: Vec<&'static str> = Vec::new();
+    for sink in sinks {
+        for event in internal::claim_held_back_events(&mut tx, sink.name(), BATCH_SIZE) {
+            match sink.handle(&mut tx, &event) {
+                Ok(()) => internal::release_held_back(&mut tx, event.event_id, sink.name()),
+                Err(_) => break,
+            }
+        }
+        if internal::has_held_back_events(&mut tx, sink.name()) {
+            behind.push(sink.name());
+        }
+    }
+
+    let mut relayed = Vec::new();
+    for event in internal::claim_unrelayed_events(&mut tx, BATCH_SIZE) {
+        for sink in sinks {
+            if behind.contains(&sink.name()) {
+                internal::hold_back(&mut tx, event.event_id, sink.name());
+                continue;
+            }
+            if sink.handle(&mut tx, &event).is_err() {
+                internal::hold_back(&mut tx, event.event_id, sink.name());
+                behind.push(sink.name());
+            }
+        }
+        internal::mark_relayed(&mut tx, event.event_id);
+        relayed.push(event.event_id);
+    }
+    internal::number_relayed_events(&mut tx, &relayed);
+    tx.commit();
+}
@@ -0,0 +1,82 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf, sync::Mutex};
+
+use base::DbConn;
+
+use crate::webhooks;
+use super::structures::OutboxEvent;
+
+/// Somewhere the relay publishes outbox events to. Events are handed over oldest first, though
+/// one whose transaction committed late can arrive after newer ones, and an event is handed over
+/// again if the relay stops before recording that it was handled, so sinks must treat a repeated
+/// `event_id` as already seen.
+pub trait EventSink: Send + Sync {
+    /// Records which events the sink has handled - must be stable across restarts
+    fn name(&self) -> &'static str;
+
+    fn handle(&self, db: &mut DbConn, event: &OutboxEvent) -> Result<(), String>;
+}
+
+/// Queues webhook deliveries for the event's tenant
+pub struct WebhookSink;
+
+impl EventSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    fn handle(&self, db: &mut DbConn, event: &OutboxEvent) -> Result<(), String> {
+        webhooks::internal::enqueue(db, event);
+        Ok(())
+    }
+}
+
+/// Appends each event as a line of JSON to a file. A repeated event is written again, so the
+/// file may hold the same `event_id` twice.
+pub struct LogFileSink {
+    pub path: PathBuf,
+}
+
+impl EventSink for LogFileSink {
+    fn name(&self) -> &'static str {
+        "log_file"
+    }
+
+    fn handle(&self, _db: &mut DbConn, event: &OutboxEvent) -> Result<(), String> {
+        let line = serde_json::to_string(event).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())
+    }
+}
+
+type Subscriber = Box<dyn Fn(&OutboxEvent) + Send>;
+
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+
+/// Registers a function to be called with every event as it is relayed. It may be called twice
+/// with the same event, and should skip an `event_id` it has already seen.
+pub fn subscribe<F>(subscriber: F)
+where
+    F: Fn(&OutboxEvent) + Send + 'static
+{
+    SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner()).push(Box::new(subscriber));
+}
+
+/// Hands each event to the subscribers registered in this process
+pub struct InProcessSink;
+
+impl EventSink for InProcessSink {
+    fn name(&self) -> &'static str {
+        "in_process"
+    }
+
+    fn handle(&self, _db: &mut DbConn, event: &OutboxEvent) -> Result<(), String> {
+        for subscriber in SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            subscriber(event);
+        }
+        Ok(())
+    }
+}
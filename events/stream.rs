@@ -0,0 +1,174 @@
+use std::{
+    io::{self, Read}, str::FromStr, thread, time::{Duration, Instant},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use base::DbPool;
+use rocket::{Outcome, Request, request::{self, FromRequest}};
+use token_auth_structs::LoggedInUser;
+use user_auth_structs::GroupRef;
+
+use crate::tenants::internal::TidInternal;
+use super::{internal, structures::*};
+
+/// How often the outbox is checked for new events
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// A comment is sent after this long without events, so dead connections are noticed
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// Streams are closed after this long, so that a worker is not held forever and changes to the
+/// caller's permissions take effect - clients reconnect on their own, resuming from the last id
+const MAX_STREAM_DURATION: Duration = Duration::from_secs(5 * 60);
+/// Milliseconds clients are told to wait before reconnecting
+const RETRY_MILLIS: u64 = 3000;
+const BATCH_SIZE: u64 = 100;
+
+/// The `Last-Event-ID` header sent by clients when reconnecting to an event stream. Stream ids
+/// are relay sequence numbers rather than event ids.
+pub struct LastEventId(pub Option<u64>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for LastEventId {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(LastEventId(
+            request.headers().get_one("Last-Event-ID").and_then(|id| id.trim().parse().ok())
+        ))
+    }
+}
+
+static OPEN_STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// One of the limited number of streams that may be open at once, given back when dropped
+pub struct StreamSlot(());
+
+impl StreamSlot {
+    /// Takes a slot, unless `max` streams are already open
+    pub fn acquire(max: usize) -> Option<Self> {
+        OPEN_STREAMS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| match open < max {
+                true  => Some(open + 1),
+                false => None,
+            })
+            .ok()
+            .map(|_| StreamSlot(()))
+    }
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        OPEN_STREAMS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Whether the caller may see an event of their tenant, following what they may read. Every
+/// member may read the tenant and its users, so sees events about them; only tenant admins see
+/// events about every group, other users only those about groups they are in, like they can
+/// only read the members of those groups.
+fn is_visible(event: &OutboxEvent, user: &LoggedInUser) -> bool {
+    if user.user.is_superuser || user.tenant_info.is_tenant_admin {
+        return true;
+    }
+    let is_group_event = matches!(event.event,
+        EventType::GroupCreated | EventType::GroupUpdated | EventType::GroupDeleted |
+        EventType::GroupMemberAdded | EventType::GroupMemberRemoved
+    );
+    if !is_group_event {
+        return true;
+    }
+
+    let group_ref = match &event.data["group"] {
+        serde_json::Value::String(group_ref) => Some(group_ref.as_str()),
+        group => group["group_ref"].as_str(),
+    };
+    match group_ref.and_then(|group_ref| GroupRef::from_str(group_ref).ok()) {
+        Some(group_ref) => user.is_in_group(&group_ref),
+        None => false,
+    }
+}
+
+/// Formats an event in the server-sent events wire format, with its relay sequence number as id
+fn format_event(relay_seq: u64, event: &OutboxEvent) -> String {
+    format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        relay_seq, event.event.as_str(), serde_json::to_string(event).unwrap_or_default()
+    )
+}
+
+/// A never-ending body of server-sent events for one tenant, read from the outbox in the order
+/// they were relayed. A database connection is only taken from the pool while checking for new
+/// events.
+pub struct EventStream {
+    pool: DbPool,
+    _slot: StreamSlot,
+    tenant_id: TidInternal,
+    user: LoggedInUser,
+    last_relay_seq: u64,
+    buffer: Vec<u8>,
+    position: usize,
+    opened_at: Instant,
+    last_sent_at: Instant,
+}
+
+impl EventStream {
+    /// Opens a stream of the events relayed after `last_relay_seq`
+    pub fn new(pool: DbPool, slot: StreamSlot, tenant_id: TidInternal, user: LoggedInUser, last_relay_seq: u64) -> Self {
+        Self {
+            pool,
+            _slot: slot,
+            tenant_id,
+            user,
+            last_relay_seq,
+            buffer: format!("retry: {}\n\n", RETRY_MILLIS).into_bytes(),
+            position: 0,
+            opened_at: Instant::now(),
+            last_sent_at: Instant::now(),
+        }
+    }
+
+    fn fill_buffer(&mut self) {
+        self.buffer.clear();
+        self.position = 0;
+
+        // with no connection to spare, only a keep-alive is sent and the next poll tries again
+        let events = match self.pool.get_one() {
+            Some(mut db) => internal::get_relayed_events_after(&mut db, self.last_relay_seq, Some(self.tenant_id), BATCH_SIZE),
+            None => Vec::new(),
+        };
+        for event in events {
+            let relay_seq = event.relay_seq.unwrap_or(self.last_relay_seq);
+            self.last_relay_seq = relay_seq;
+            if is_visible(&event, &self.user) {
+                self.buffer.extend(format_event(relay_seq, &event).into_bytes());
+            }
+        }
+
+        if self.buffer.is_empty() && self.last_sent_at.elapsed() >= KEEP_ALIVE_INTERVAL {
+            self.buffer.extend(b": keep-alive\n\n");
+        }
+        if !self.buffer.is_empty() {
+            self.last_sent_at = Instant::now();
+        }
+    }
+}
+
+impl Read for EventStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.position < self.buffer.len() {
+                let count = buf.len().min(self.buffer.len() - self.position);
+                buf[..count].copy_from_slice(&self.buffer[self.position..self.position + count]);
+                self.position += count;
+                return Ok(count);
+            }
+
+            if self.opened_at.elapsed() >= MAX_STREAM_DURATION {
+                return Ok(0);
+            }
+
+            self.fill_buffer();
+            if self.buffer.is_empty() {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
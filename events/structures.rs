@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+use base::db::to_sql::AsSql;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use user_auth_structs::TenantRef;
+
+use crate::tenants::internal::TidInternal;
+
+/// Lifecycle events published through the outbox
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    TenantCreated,
+    TenantUpdated,
+    TenantArchived,
+    TenantUnarchived,
+    TenantDeleted,
+    UserCreated,
+    UserUpdated,
+    UserDeleted,
+    UserAddedToTenant,
+    UserRemovedFromTenant,
+    AdminPromoted,
+    AdminDemoted,
+    GroupCreated,
+    GroupUpdated,
+    GroupDeleted,
+    GroupMemberAdded,
+    GroupMemberRemoved,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TenantCreated         => "tenant_created",
+            Self::TenantUpdated         => "tenant_updated",
+            Self::TenantArchived        => "tenant_archived",
+            Self::TenantUnarchived      => "tenant_unarchived",
+            Self::TenantDeleted         => "tenant_deleted",
+            Self::UserCreated           => "user_created",
+            Self::UserUpdated           => "user_updated",
+            Self::UserDeleted           => "user_deleted",
+            Self::UserAddedToTenant     => "user_added_to_tenant",
+            Self::UserRemovedFromTenant => "user_removed_from_tenant",
+            Self::AdminPromoted         => "admin_promoted",
+            Self::AdminDemoted          => "admin_demoted",
+            Self::GroupCreated          => "group_created",
+            Self::GroupUpdated          => "group_updated",
+            Self::GroupDeleted          => "group_deleted",
+            Self::GroupMemberAdded      => "group_member_added",
+            Self::GroupMemberRemoved    => "group_member_removed",
+        }
+    }
+}
+
+impl FromStr for EventType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tenant_created"           => Ok(Self::TenantCreated),
+            "tenant_updated"           => Ok(Self::TenantUpdated),
+            "tenant_archived"          => Ok(Self::TenantArchived),
+            "tenant_unarchived"        => Ok(Self::TenantUnarchived),
+            "tenant_deleted"           => Ok(Self::TenantDeleted),
+            "user_created"             => Ok(Self::UserCreated),
+            "user_updated"             => Ok(Self::UserUpdated),
+            "user_deleted"             => Ok(Self::UserDeleted),
+            "user_added_to_tenant"     => Ok(Self::UserAddedToTenant),
+            "user_removed_from_tenant" => Ok(Self::UserRemovedFromTenant),
+            "admin_promoted"           => Ok(Self::AdminPromoted),
+            "admin_demoted"            => Ok(Self::AdminDemoted),
+            "group_created"            => Ok(Self::GroupCreated),
+            "group_updated"            => Ok(Self::GroupUpdated),
+            "group_deleted"            => Ok(Self::GroupDeleted),
+            "group_member_added"       => Ok(Self::GroupMemberAdded),
+            "group_member_removed"     => Ok(Self::GroupMemberRemoved),
+            _ => Err("Invalid event type"),
+        }
+    }
+}
+
+impl AsSql for EventType {
+    fn as_sql(&self) -> String {
+        AsSql::as_sql(&self.as_str())
+    }
+
+    fn get_eq_operator(&self) -> &'static str {
+        "="
+    }
+}
+
+/// An event read back from the outbox, in the form handed to sinks
+#[derive(Serialize, Clone)]
+pub struct OutboxEvent {
+    pub event_id: u64,
+    /// Where the event comes in the order events were relayed, once it has been
+    #[serde(skip)]
+    pub relay_seq: Option<u64>,
+    #[serde(skip)]
+    pub tenant_id: Option<TidInternal>,
+    pub tenant: Option<TenantRef>,
+    pub event: EventType,
+    pub data: Value,
+    pub occurred_at: u64,
+}
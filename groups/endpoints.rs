@@ -1,16 +1,22 @@
 use base::{Status, err_response, requests::{response::text_response::JsonBody, UserRequest}};
 use rocket::{response::status::Created, Route};
 use rocket_contrib::json::Json;
-use serde_json::Value;
-use user_auth_structs::{Group, GroupRef, User, UserRef};
+use serde_json::{json, Value};
+use user_auth_structs::{Group, GroupRef, TenantRef, User, UserRef};
 
-use crate::{UserAuthErrResponse, tenants, users, utils::cache_updater::update_user_info};
+use crate::{UserAuthErrResponse, authz::{policy::authorize, structures::{Action, Resource}}, audit::{self, structures::{AuditAction, AuditEvent, AuditTarget}}, tenants, users, utils::{cache_updater::{refresh_user_info, update_user_info}, pagination::{ListQuery, Page, SortKey}, transaction::Transaction}};
 
-use super::{errors::GroupEndpointError, internal::{self, decode_group_ref, get_group_tenant}};
-use super::structures::*;
+use super::{errors::GroupEndpointError, internal::{self, decode_group_ref, get_group_tenant, GroupOwner}};
+use super::{rules::{validate_rule, MembershipRule}, structures::*};
 
 pub fn get_endpoints() -> Vec<Route> {
-    routes![get_group, get_users_in_group, create_group, add_user_to_group, patch_group, remove_user_from_group, delete_group]
+    routes![
+        get_group, get_users_in_group, create_group, add_user_to_group, patch_group, remove_user_from_group, delete_group,
+        change_members,
+        get_group_owners, add_user_owner, remove_user_owner, add_group_owner, remove_group_owner,
+        get_child_groups, nest_group, unnest_group,
+        get_group_rule, set_group_rule, preview_rule
+    ]
 }
 
 #[get("/<group_ref>")]
@@ -20,38 +26,36 @@ pub fn get_group(
 ) -> Result<Json<Group>, UserAuthErrResponse> {
     //==CHECK PERMISSIONS==
     let group_id = decode_group_ref(request.db(), group_ref.clone())?;
-    let group_tenant = get_group_tenant(request.db(), group_id);
-    if 
-        !request.user().is_superuser &&
-        !request.user_login_info().is_admin_in_tenant(&group_tenant) &&
-        !request.user_login_info().is_in_group(&group_ref)
-    {
-        return err_response!(GroupEndpointError::ReadingDenied);
-    }
+    authorize(&mut request, Action::GroupsRead, Resource::group(group_ref.clone()), GroupEndpointError::ReadingDenied)?;
 
 
     internal::get_non_special_group(group_id, request.db()).map(|g| Json(g))
 }
 
-#[get("/<group_ref>/users")]
+/// Lists a group's members a page at a time, sorted and filtered like a tenant's users. With
+/// `transitive`, members of the groups nested in it are included and marked as inherited.
+#[get("/<group_ref>/users?<transitive>&<cursor>&<limit>&<sort>&<name>&<email_domain>&<status>")]
 pub fn get_users_in_group(
     group_ref: GroupRef,
+    transitive: Option<bool>,
+    cursor: Option<String>,
+    limit: Option<u64>,
+    sort: Option<String>,
+    name: Option<String>,
+    email_domain: Option<String>,
+    status: Option<String>,
     mut request: UserRequest<crate::ConfigType>,
-) -> Result<Json<Vec<User>>, UserAuthErrResponse> {
+) -> Result<Json<Page<GroupMember>>, UserAuthErrResponse> {
     //==CHECK PERMISSIONS==
     let group_id = decode_group_ref(request.db(), group_ref.clone())?;
-    let group_tenant = get_group_tenant(request.db(), group_id);
-    if 
-        !request.user().is_superuser &&
-        !request.user_login_info().is_admin_in_tenant(&group_tenant) &&
-        !request.user_login_info().is_in_group(&group_ref)
-    {
-        return err_response!(GroupEndpointError::ReadingDenied);
-    }
+    authorize(&mut request, Action::GroupsRead, Resource::group(group_ref.clone()), GroupEndpointError::ReadingDenied)?;
 
 
+    let query = ListQuery::parse(
+        cursor, limit, sort, name, email_domain, status, &[SortKey::Username, SortKey::Lastname, SortKey::Created]
+    ).map_err(|e| UserAuthErrResponse::new(GroupEndpointError::InvalidField(e)))?;
     internal::get_non_special_group(group_id, request.db())?;
-    internal::get_users_in_group(group_id, request.db()).map(|u| Json(u))
+    internal::get_group_member_page(group_id, transitive.unwrap_or(false), &query, request.db()).map(|u| Json(u))
 }
 
 #[post("/", data = "<group>")]
@@ -62,47 +66,51 @@ pub fn create_group(
     let group = group.0;
 
     //==CHECK PERMISSIONS==
-    if 
-        !request.user().is_superuser &&
-        !request.user_login_info().is_admin_in_tenant(&group.tenant)
-    {
-        return err_response!(GroupEndpointError::CreationDenied);
-    }
+    authorize(&mut request, Action::GroupsCreate, Resource::tenant(group.tenant.clone()), GroupEndpointError::CreationDenied)?;
 
 
-    tenants::internal::decode_tenant_ref(request.db(), group.tenant.clone())?;
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), group.tenant.clone())?;
 
-    let info = internal::create_group(group, request.db())?;
+    let mut tx = Transaction::start(&mut request);
+    let info = internal::create_group(group.clone(), tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::GroupCreated, Some(tenant_id), AuditTarget::Group(info.0.clone())
+    ).with_change(&Value::Null, &json!(group)));
+    tx.commit();
 
     Ok(Created(format!("/groups/{}", info.0), None))
 }
 
-#[post("/<group_ref>/users/<user_ref>")]
+/// Adds a user to a group, optionally only between `valid_from` and `valid_until` (unix
+/// timestamps) - expired memberships are removed in the background
+#[post("/<group_ref>/users/<user_ref>?<valid_from>&<valid_until>")]
 pub fn add_user_to_group(
     group_ref: GroupRef,
     user_ref: UserRef,
+    valid_from: Option<u64>,
+    valid_until: Option<u64>,
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Status, UserAuthErrResponse> {
     //==CHECK PERMISSIONS==
-    let user_id = users::internal::decode_user_ref(request.db(), user_ref)?;
-    let group_id = decode_group_ref(request.db(), group_ref)?;
+    let user_id = users::internal::decode_user_ref(request.db(), user_ref.clone())?;
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
     let group_tenant = get_group_tenant(request.db(), group_id);
-    if 
-        !request.user().is_superuser &&
-        !request.user_login_info().is_admin_in_tenant(&group_tenant)
-    {
-        return err_response!(GroupEndpointError::ModificationDenied);
-    }
-    else{
-        let user_tenants = tenants::internal::get_user_tenant_refs(user_id, request.db());
-        if !request.user_login_info().is_in_these_tenants(&user_tenants){
-            return err_response!(GroupEndpointError::ModificationDenied);
-        }
-    }
+    authorize(
+        &mut request, Action::GroupsManage, Resource::group(group_ref.clone()).with_user(user_ref.clone()),
+        GroupEndpointError::ModificationDenied
+    )?;
     
 
     
-    internal::add_user_to_group(group_id, user_id, request.db())?;
+    let window = MembershipWindow { valid_from, valid_until };
+    let mut tx = Transaction::start(&mut request);
+    internal::add_user_to_group_within(group_id, user_id, window, tx.db())?;
+    let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), group_tenant).ok();
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserAddedToGroup, tenant_id, AuditTarget::User(user_ref.clone())
+    ).with_change(&Value::Null, &json!({ "group": group_ref, "valid_from": valid_from, "valid_until": valid_until })));
+    tx.commit();
+
     update_user_info(&request.create_http_client(), request.user_login_info().clone(), request.db())?;
     Ok(Status::NoContent)
 }
@@ -114,22 +122,67 @@ pub fn remove_user_from_group(
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Status, UserAuthErrResponse> {
     //==CHECK PERMISSIONS==
-    let user_id = users::internal::decode_user_ref(request.db(), user_ref)?;
-    let group_id = decode_group_ref(request.db(), group_ref)?;
+    let user_id = users::internal::decode_user_ref(request.db(), user_ref.clone())?;
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
     let group_tenant = get_group_tenant(request.db(), group_id);
-    if 
-        !request.user().is_superuser &&
-        !request.user_login_info().is_admin_in_tenant(&group_tenant)
-    {
-        return err_response!(GroupEndpointError::ModificationDenied);
-    }
+    authorize(
+        &mut request, Action::GroupsManage, Resource::group(group_ref.clone()).with_user(user_ref.clone()),
+        GroupEndpointError::ModificationDenied
+    )?;
 
     
-    internal::remove_user_from_group(group_id, user_id, request.db())?;
+    let mut tx = Transaction::start(&mut request);
+    internal::remove_user_from_group(group_id, user_id, tx.db())?;
+    let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), group_tenant).ok();
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserRemovedFromGroup, tenant_id, AuditTarget::User(user_ref.clone())
+    ).with_change(&json!({ "group": group_ref }), &Value::Null));
+    tx.commit();
+
     update_user_info(&request.create_http_client(), request.user_login_info().clone(), request.db())?;
     Ok(Status::NoContent)
 }
 
+/// Adds and removes many members of a normal group at once. Every item is checked before any is
+/// applied, and the result of each is returned; users whose membership changed have their session
+/// refreshed once.
+#[patch("/<group_ref>/users", data = "<changes>")]
+pub fn change_members(
+    group_ref: GroupRef,
+    changes: JsonBody<BulkMembershipChange>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<MembershipChangeResult>>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
+    let group_tenant = get_group_tenant(request.db(), group_id);
+    authorize(&mut request, Action::GroupsManage, Resource::group(group_ref.clone()), GroupEndpointError::ModificationDenied)?;
+
+
+    let changes = changes.0;
+    validate_bulk_membership_change(&changes)
+        .map_err(|e| UserAuthErrResponse::new(GroupEndpointError::InvalidField(e)))?;
+
+    let mut tx = Transaction::start(&mut request);
+    let (results, changed) = internal::change_members(group_id, &changes, tx.db())?;
+    let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), group_tenant)?;
+    for result in results.iter().filter(|result| result.applied) {
+        let (action, before, after) = match result.change {
+            MembershipChange::Add    => (AuditAction::UserAddedToGroup, Value::Null, json!({ "group": group_ref })),
+            MembershipChange::Remove => (AuditAction::UserRemovedFromGroup, json!({ "group": group_ref }), Value::Null),
+        };
+        audit::internal::record(&mut tx, AuditEvent::new(
+            action, Some(tenant_id), AuditTarget::User(result.user.clone())
+        ).with_change(&before, &after));
+    }
+    tx.commit();
+
+    let client = request.create_http_client();
+    for user_id in changed {
+        refresh_user_info(&client, user_id, tenant_id, request.db())?;
+    }
+    Ok(Json(results))
+}
+
 #[patch("/<group_ref>", data="<changes>")]
 pub fn patch_group(
     group_ref: GroupRef,
@@ -137,17 +190,21 @@ pub fn patch_group(
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Status, UserAuthErrResponse> {
     //==CHECK PERMISSIONS==
-    let group_id = decode_group_ref(request.db(), group_ref)?;
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
     let group_tenant = get_group_tenant(request.db(), group_id);
-    if 
-        !request.user().is_superuser &&
-        !request.user_login_info().is_admin_in_tenant(&group_tenant)
-    {
-        return err_response!(GroupEndpointError::ModificationDenied);
-    }
+    authorize(&mut request, Action::GroupsUpdate, Resource::group(group_ref.clone()), GroupEndpointError::ModificationDenied)?;
 
     
-    internal::patch_group(group_id, changes.0, request.db())?;
+    let before = internal::get_non_special_group(group_id, request.db())?;
+    let mut tx = Transaction::start(&mut request);
+    internal::patch_group(group_id, changes.0, tx.db())?;
+    let after = internal::get_non_special_group(group_id, tx.db())?;
+
+    let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), group_tenant).ok();
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::GroupUpdated, tenant_id, AuditTarget::Group(group_ref)
+    ).with_change(&before, &after));
+    tx.commit();
     Ok(Status::NoContent)
 }
 
@@ -157,17 +214,279 @@ pub fn delete_group(
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Status, UserAuthErrResponse> {
     //==CHECK PERMISSIONS==
-    let group_id = decode_group_ref(request.db(), group_ref)?;
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
     let group_tenant = get_group_tenant(request.db(), group_id);
-    if 
-        !request.user().is_superuser &&
-        !request.user_login_info().is_admin_in_tenant(&group_tenant)
-    {
-        return err_response!(GroupEndpointError::DeletionDenied);
-    }
+    authorize(&mut request, Action::GroupsDelete, Resource::group(group_ref.clone()), GroupEndpointError::DeletionDenied)?;
     
-    internal::delete_group(group_id, request.db())?;
+    let before = internal::get_non_special_group(group_id, request.db())?;
+    let mut tx = Transaction::start(&mut request);
+    internal::delete_group(group_id, tx.db())?;
+    let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), group_tenant).ok();
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::GroupDeleted, tenant_id, AuditTarget::Group(group_ref.clone())
+    ).with_change(&json!(before), &Value::Null));
+    tx.commit();
+    internal::forget_group_ref(group_ref);
+
     update_user_info(&request.create_http_client(), request.user_login_info().clone(), request.db())?;
 
     Ok(Status::NoContent)
 }
+
+#[get("/<group_ref>/owners")]
+pub fn get_group_owners(
+    group_ref: GroupRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<GroupOwners>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
+    authorize(&mut request, Action::GroupsRead, Resource::group(group_ref.clone()), GroupEndpointError::ReadingDenied)?;
+
+
+    internal::get_non_special_group(group_id, request.db())?;
+    Ok(Json(internal::get_group_owners(group_id, request.db())))
+}
+
+/// Adds or removes an owner of a group, which has to be a normal group - owning the special groups
+/// would amount to managing tenant membership or admins
+fn change_owner(
+    group_ref: GroupRef,
+    owner: GroupOwner,
+    added: bool,
+    change: Value,
+    request: &mut UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
+    let group = internal::get_non_special_group(group_id, request.db())?;
+
+    if let (true, GroupOwner::Group(owner_id)) = (added, owner) {
+        if get_group_tenant(request.db(), owner_id) != group.tenant {
+            return err_response!(GroupEndpointError::OwnerInOtherTenant);
+        }
+    }
+
+    let mut tx = Transaction::start(request);
+    match added {
+        true  => internal::add_group_owner(group_id, owner, tx.db())?,
+        false => internal::remove_group_owner(group_id, owner, tx.db())?,
+    }
+
+    let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), group.tenant).ok();
+    let (action, before, after) = match added {
+        true  => (AuditAction::GroupOwnerAdded, Value::Null, change),
+        false => (AuditAction::GroupOwnerRemoved, change, Value::Null),
+    };
+    audit::internal::record(&mut tx, AuditEvent::new(
+        action, tenant_id, AuditTarget::Group(group_ref)
+    ).with_change(&before, &after));
+    tx.commit();
+
+    Ok(Status::NoContent)
+}
+
+#[post("/<group_ref>/owners/users/<user_ref>")]
+pub fn add_user_owner(
+    group_ref: GroupRef,
+    user_ref: UserRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let user_id = users::internal::decode_user_ref(request.db(), user_ref.clone())?;
+    authorize(
+        &mut request, Action::GroupsManageOwners, Resource::group(group_ref.clone()).with_user(user_ref.clone()),
+        GroupEndpointError::ModificationDenied
+    )?;
+
+
+    change_owner(group_ref, GroupOwner::User(user_id), true, json!({ "user": user_ref }), &mut request)
+}
+
+#[delete("/<group_ref>/owners/users/<user_ref>")]
+pub fn remove_user_owner(
+    group_ref: GroupRef,
+    user_ref: UserRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let user_id = users::internal::decode_user_ref(request.db(), user_ref.clone())?;
+    authorize(&mut request, Action::GroupsManageOwners, Resource::group(group_ref.clone()), GroupEndpointError::ModificationDenied)?;
+
+
+    change_owner(group_ref, GroupOwner::User(user_id), false, json!({ "user": user_ref }), &mut request)
+}
+
+#[post("/<group_ref>/owners/groups/<owner_ref>")]
+pub fn add_group_owner(
+    group_ref: GroupRef,
+    owner_ref: GroupRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let owner_id = decode_group_ref(request.db(), owner_ref.clone())?;
+    authorize(&mut request, Action::GroupsManageOwners, Resource::group(group_ref.clone()), GroupEndpointError::ModificationDenied)?;
+
+
+    change_owner(group_ref, GroupOwner::Group(owner_id), true, json!({ "group": owner_ref }), &mut request)
+}
+
+#[delete("/<group_ref>/owners/groups/<owner_ref>")]
+pub fn remove_group_owner(
+    group_ref: GroupRef,
+    owner_ref: GroupRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let owner_id = decode_group_ref(request.db(), owner_ref.clone())?;
+    authorize(&mut request, Action::GroupsManageOwners, Resource::group(group_ref.clone()), GroupEndpointError::ModificationDenied)?;
+
+
+    change_owner(group_ref, GroupOwner::Group(owner_id), false, json!({ "group": owner_ref }), &mut request)
+}
+
+#[get("/<group_ref>/groups")]
+pub fn get_child_groups(
+    group_ref: GroupRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Group>>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
+    authorize(&mut request, Action::GroupsRead, Resource::group(group_ref.clone()), GroupEndpointError::ReadingDenied)?;
+
+
+    internal::get_non_special_group(group_id, request.db())?;
+    Ok(Json(internal::get_child_groups(group_id, request.db())))
+}
+
+/// Nests a group in another, or takes it back out, then pushes the changed groups of everyone in
+/// the nested group to the token server
+fn change_nesting(
+    group_ref: GroupRef,
+    child_ref: GroupRef,
+    nested: bool,
+    request: &mut UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
+    let child_id = decode_group_ref(request.db(), child_ref.clone())?;
+
+    let mut tx = Transaction::start(request);
+    match nested {
+        true  => internal::nest_group(group_id, child_id, tx.db())?,
+        false => internal::unnest_group(group_id, child_id, tx.db())?,
+    }
+
+    let group_tenant = get_group_tenant(tx.db(), group_id);
+    let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), group_tenant)?;
+    let (action, before, after) = match nested {
+        true  => (AuditAction::GroupNested, Value::Null, json!({ "group": child_ref })),
+        false => (AuditAction::GroupUnnested, json!({ "group": child_ref }), Value::Null),
+    };
+    audit::internal::record(&mut tx, AuditEvent::new(
+        action, Some(tenant_id), AuditTarget::Group(group_ref)
+    ).with_change(&before, &after));
+    tx.commit();
+
+    let client = request.create_http_client();
+    for user_id in internal::get_member_ids(child_id, request.db())? {
+        refresh_user_info(&client, user_id, tenant_id, request.db())?;
+    }
+    Ok(Status::NoContent)
+}
+
+#[post("/<group_ref>/groups/<child_ref>")]
+pub fn nest_group(
+    group_ref: GroupRef,
+    child_ref: GroupRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    authorize(&mut request, Action::GroupsManage, Resource::group(group_ref.clone()), GroupEndpointError::ModificationDenied)?;
+
+
+    change_nesting(group_ref, child_ref, true, &mut request)
+}
+
+#[delete("/<group_ref>/groups/<child_ref>")]
+pub fn unnest_group(
+    group_ref: GroupRef,
+    child_ref: GroupRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    authorize(&mut request, Action::GroupsManage, Resource::group(group_ref.clone()), GroupEndpointError::ModificationDenied)?;
+
+
+    change_nesting(group_ref, child_ref, false, &mut request)
+}
+
+#[get("/<group_ref>/rule")]
+pub fn get_group_rule(
+    group_ref: GroupRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<MembershipRule>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
+    authorize(&mut request, Action::GroupsRead, Resource::group(group_ref.clone()), GroupEndpointError::ReadingDenied)?;
+
+
+    match internal::get_group_rule(group_id, request.db()) {
+        Some(rule) => Ok(Json(rule)),
+        None => err_response!(GroupEndpointError::NotDynamic),
+    }
+}
+
+/// Replaces a dynamic group's rule, then pushes the changed groups of everyone who was or now is a
+/// member to the token server
+#[put("/<group_ref>/rule", data = "<rule>")]
+pub fn set_group_rule(
+    group_ref: GroupRef,
+    rule: JsonBody<MembershipRule>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let group_id = decode_group_ref(request.db(), group_ref.clone())?;
+    let group_tenant = get_group_tenant(request.db(), group_id);
+    authorize(&mut request, Action::GroupsUpdate, Resource::group(group_ref.clone()), GroupEndpointError::ModificationDenied)?;
+
+
+    let rule = rule.0;
+    let before = internal::get_group_rule(group_id, request.db());
+    let mut affected = internal::get_user_ids_in_group(group_id, request.db())?;
+
+    let mut tx = Transaction::start(&mut request);
+    internal::set_group_rule(group_id, &rule, tx.db())?;
+    let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), group_tenant)?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::GroupUpdated, Some(tenant_id), AuditTarget::Group(group_ref)
+    ).with_change(&json!({ "rule": before }), &json!({ "rule": rule })));
+    tx.commit();
+
+    for user_id in internal::get_user_ids_in_group(group_id, request.db())? {
+        if !affected.contains(&user_id) {
+            affected.push(user_id);
+        }
+    }
+    let client = request.create_http_client();
+    for user_id in affected {
+        refresh_user_info(&client, user_id, tenant_id, request.db())?;
+    }
+    Ok(Status::NoContent)
+}
+
+/// Lists the users of a tenant a rule would make members of a dynamic group, without saving it
+#[post("/rule/preview?<tenant>", data = "<rule>")]
+pub fn preview_rule(
+    tenant: TenantRef,
+    rule: JsonBody<MembershipRule>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<User>>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    authorize(&mut request, Action::GroupsCreate, Resource::tenant(tenant.clone()), GroupEndpointError::CreationDenied)?;
+
+
+    let rule = rule.0;
+    validate_rule(&rule).map_err(|e| UserAuthErrResponse::new(GroupEndpointError::InvalidField(e)))?;
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), tenant)?;
+
+    let matches = internal::get_rule_matches(&rule, tenant_id, request.db())?;
+    Ok(Json(matches.into_iter().map(|(_, user)| user).collect()))
+}
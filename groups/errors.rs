@@ -12,7 +12,17 @@ pub enum GroupEndpointError {
     ReadingDenied,
     ModificationDenied,
     CreationDenied,
-    DeletionDenied
+    DeletionDenied,
+    OwnerAlreadyAdded,
+    NotAnOwner,
+    OwnerInOtherTenant,
+    GroupInOtherTenant,
+    GroupAlreadyNested,
+    GroupNotNested,
+    NestingCycle,
+    DynamicMembership,
+    NotDynamic,
+    UserNotInTenant,
 }
 
 impl MicroserviceError for GroupEndpointError {
@@ -26,6 +36,16 @@ impl MicroserviceError for GroupEndpointError {
             GroupEndpointError::ModificationDenied => 0x0205,
             GroupEndpointError::CreationDenied => 0x0206,
             GroupEndpointError::DeletionDenied => 0x0207,
+            GroupEndpointError::OwnerAlreadyAdded => 0x0208,
+            GroupEndpointError::NotAnOwner => 0x0209,
+            GroupEndpointError::OwnerInOtherTenant => 0x020A,
+            GroupEndpointError::GroupInOtherTenant => 0x020B,
+            GroupEndpointError::GroupAlreadyNested => 0x020C,
+            GroupEndpointError::GroupNotNested => 0x020D,
+            GroupEndpointError::NestingCycle => 0x020E,
+            GroupEndpointError::DynamicMembership => 0x020F,
+            GroupEndpointError::NotDynamic => 0x0210,
+            GroupEndpointError::UserNotInTenant => 0x0211,
         }
     }
 
@@ -39,6 +59,16 @@ impl MicroserviceError for GroupEndpointError {
             GroupEndpointError::ModificationDenied => format!("Chaning group information denined"),
             GroupEndpointError::CreationDenied => format!("Creating group denied"),
             GroupEndpointError::DeletionDenied => format!("Deleting group denied"),
+            GroupEndpointError::OwnerAlreadyAdded => format!("Already an owner of the group"),
+            GroupEndpointError::NotAnOwner => format!("Not an owner of the group"),
+            GroupEndpointError::OwnerInOtherTenant => format!("Owner belongs to a different tenant"),
+            GroupEndpointError::GroupInOtherTenant => format!("Group belongs to a different tenant"),
+            GroupEndpointError::GroupAlreadyNested => format!("Group already nested in group"),
+            GroupEndpointError::GroupNotNested => format!("Group not nested in group"),
+            GroupEndpointError::NestingCycle => format!("Groups cannot contain themselves"),
+            GroupEndpointError::DynamicMembership => format!("Members of dynamic groups cannot be changed"),
+            GroupEndpointError::NotDynamic => format!("Group is not dynamic"),
+            GroupEndpointError::UserNotInTenant => format!("User is not a member of the group's tenant"),
         }
     }
 
@@ -52,6 +82,16 @@ impl MicroserviceError for GroupEndpointError {
             GroupEndpointError::ModificationDenied => format!("Chaning group information denined"),
             GroupEndpointError::CreationDenied => format!("Creating group denied"),
             GroupEndpointError::DeletionDenied => format!("Deleting group denied"),
+            GroupEndpointError::OwnerAlreadyAdded => format!("The user or group already owns the group"),
+            GroupEndpointError::NotAnOwner => format!("The user or group does not own the group"),
+            GroupEndpointError::OwnerInOtherTenant => format!("Group owners must belong to the group's tenant"),
+            GroupEndpointError::GroupInOtherTenant => format!("Only groups of the same tenant can be nested"),
+            GroupEndpointError::GroupAlreadyNested => format!("Group already nested in group"),
+            GroupEndpointError::GroupNotNested => format!("Group not nested in group"),
+            GroupEndpointError::NestingCycle => format!("Nesting the group would make it contain itself"),
+            GroupEndpointError::DynamicMembership => format!("Dynamic groups get their members from their rule, so can't be added to or removed from"),
+            GroupEndpointError::NotDynamic => format!("Only dynamic groups have a membership rule"),
+            GroupEndpointError::UserNotInTenant => format!("Only members of the group's tenant can be added to or removed from it"),
         }
     }
 
@@ -65,6 +105,16 @@ impl MicroserviceError for GroupEndpointError {
             GroupEndpointError::ModificationDenied => Status::Forbidden,
             GroupEndpointError::CreationDenied => Status::Forbidden,
             GroupEndpointError::DeletionDenied => Status::Forbidden,
+            GroupEndpointError::OwnerAlreadyAdded => Status::BadRequest,
+            GroupEndpointError::NotAnOwner => Status::BadRequest,
+            GroupEndpointError::OwnerInOtherTenant => Status::BadRequest,
+            GroupEndpointError::GroupInOtherTenant => Status::BadRequest,
+            GroupEndpointError::GroupAlreadyNested => Status::BadRequest,
+            GroupEndpointError::GroupNotNested => Status::BadRequest,
+            GroupEndpointError::NestingCycle => Status::BadRequest,
+            GroupEndpointError::DynamicMembership => Status::BadRequest,
+            GroupEndpointError::NotDynamic => Status::BadRequest,
+            GroupEndpointError::UserNotInTenant => Status::BadRequest,
         }
     }
 
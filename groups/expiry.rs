@@ -0,0 +1,70 @@
+use base::{DbConn, sql};
+use sdk_base::Client;
+
+use crate::{tenants::{self, internal::TidInternal}, users::internal::UidInternal, utils::{cache_updater::refresh_user_info, transaction::Transaction}};
+use super::internal::{self, GidInternal};
+
+fn group_tenant_id(group_id: GidInternal, db: &mut DbConn) -> Option<TidInternal> {
+    let group_tenant = internal::get_group_tenant(db, group_id);
+    tenants::internal::decode_tenant_ref(db, group_tenant).ok()
+}
+
+/// Removes memberships that have ended and pushes the groups of their users, along with those
+/// whose memberships have just started, to the token server. Either is retried on the next run if
+/// the token server can't be reached: a membership that has started stays awaiting its start, and
+/// one that has ended leaves its user's refresh queued.
+pub fn sweep(client: &Client, db: &mut DbConn) {
+    let ended: Vec<(UidInternal, GidInternal)> = db.query_map(
+        "SELECT user_id, group_id FROM auth_usergroups WHERE valid_until <= UNIX_TIMESTAMP()",
+        |(user_id, group_id): (UidInternal, GidInternal)| (user_id, group_id)
+    );
+    for (user_id, group_id) in ended {
+        let tenant_id = group_tenant_id(group_id, db);
+        let mut tx = Transaction::start(db);
+        if internal::remove_user_from_group(group_id, user_id, &mut tx).is_ok() {
+            if let Some(tenant_id) = tenant_id {
+                tx.query_drop(&sql!(
+                    "INSERT IGNORE INTO auth_pending_refreshes (user_id, tenant_id) VALUES ({}, {})",
+                    user_id, tenant_id
+                ));
+            }
+            tx.commit();
+        }
+    }
+
+    // users and tenants deleted since have no tokens left to refresh
+    db.query_drop("
+        DELETE FROM auth_pending_refreshes
+        WHERE
+            tenant_id NOT IN (SELECT id FROM auth_tenants) OR
+            user_id NOT IN (SELECT id FROM auth_users WHERE is_deleted = 0)
+    ");
+    let pending: Vec<(UidInternal, TidInternal)> = db.query_map(
+        "SELECT user_id, tenant_id FROM auth_pending_refreshes",
+        |(user_id, tenant_id): (UidInternal, TidInternal)| (user_id, tenant_id)
+    );
+    for (user_id, tenant_id) in pending {
+        if refresh_user_info(client, user_id, tenant_id, db).is_ok() {
+            db.query_drop(&sql!(
+                "DELETE FROM auth_pending_refreshes WHERE user_id {=} AND tenant_id {=}", user_id, tenant_id
+            ));
+        }
+    }
+
+    let started: Vec<(UidInternal, GidInternal)> = db.query_map(
+        "SELECT user_id, group_id FROM auth_usergroups WHERE awaiting_start = 1 AND valid_from <= UNIX_TIMESTAMP()",
+        |(user_id, group_id): (UidInternal, GidInternal)| (user_id, group_id)
+    );
+    for (user_id, group_id) in started {
+        let refreshed = match group_tenant_id(group_id, db) {
+            Some(tenant_id) => refresh_user_info(client, user_id, tenant_id, db).is_ok(),
+            None => true,
+        };
+        if refreshed {
+            db.query_drop(&sql!(
+                "UPDATE auth_usergroups SET awaiting_start = 0 WHERE user_id {=} AND group_id {=}",
+                user_id, group_id
+            ));
+        }
+    }
+}
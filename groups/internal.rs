@@ -1,16 +1,41 @@
 use std::str::FromStr;
 
-use base::{DbConn, db::error_handling::DatabaseErrHandler, err_response, references::InternalReference, replace_json, sql};
-use serde_json::Value;
+use base::{DbConn, db::error_handling::DatabaseErrHandler, err_response, references::InternalReference, replace_json, requests::response::MicroserviceError, sql};
+use serde_json::{json, Value};
 use user_auth_structs::{Group, GroupRef, TenantRef, User, UserRef};
 
 
-use crate::{UserAuthErrResponse, groups::errors::GroupEndpointError, tenants::internal::TidInternal, users::internal::UidInternal};
-use cached::proc_macro::cached;
-use super::structures::*;
+use crate::{
+    UserAuthErrResponse, events::{self, structures::EventType}, groups::errors::GroupEndpointError, roles,
+    tenants::{self, internal::TidInternal}, users::{self, internal::UidInternal, UserEndpointError},
+    utils::{pagination::{ListQuery, Page}, time}
+};
+use cached::{proc_macro::cached, Cached};
+use super::{rules::{validate_rule, MembershipRule}, structures::*};
 
 pub type GidInternal = u64;
 
+/// Memberships only count from their `valid_from` until their `valid_until`, where those are set
+pub const ACTIVE_MEMBERSHIP: &str = "
+    (auth_usergroups.valid_from IS NULL OR auth_usergroups.valid_from <= UNIX_TIMESTAMP()) AND
+    (auth_usergroups.valid_until IS NULL OR auth_usergroups.valid_until > UNIX_TIMESTAMP())";
+
+/// An owner of a group: a user, or every member of another group
+#[derive(Clone, Copy)]
+pub enum GroupOwner {
+    User(UidInternal),
+    Group(GidInternal),
+}
+
+impl GroupOwner {
+    fn condition(&self) -> String {
+        match self {
+            GroupOwner::User(user_id)   => sql!("user_id {=} AND owner_group_id IS NULL", *user_id),
+            GroupOwner::Group(group_id) => sql!("owner_group_id {=} AND user_id IS NULL", *group_id),
+        }
+    }
+}
+
 
 #[cached(size=512, option=true, convert="{reference.clone()}", key="InternalReference<GroupRef>")]
 /// Converts an external user id to an internal user id
@@ -24,6 +49,12 @@ pub fn decode_group_ref(db: &mut DbConn, reference: GroupRef) -> Result<GidInter
         .ok_or(UserAuthErrResponse::new(GroupEndpointError::NonExistentGroup))
 }
 
+/// Drops a deleted group's reference from the cache. Call once the deletion is committed, or the
+/// reference could be cached again in between.
+pub fn forget_group_ref(reference: GroupRef) {
+    DECODE_GROUP_REF_CACHED.lock().unwrap().cache_remove(&InternalReference::new(reference));
+}
+
 #[cached(size=512, convert="{group_id.clone()}", key="GidInternal")]
 pub fn get_group_tenant(db: &mut DbConn, group_id: GidInternal) -> TenantRef{
     let ret: (InternalReference<TenantRef>,) = db.query_first(&sql!("
@@ -71,36 +102,218 @@ pub fn get_group(id: GidInternal, db: &mut DbConn) -> Result<RawGroup, UserAuthE
 }
 
 pub fn get_non_special_group(id: GidInternal, db: &mut DbConn) -> Result<Group, UserAuthErrResponse> {
+    let group = get_group(id,db)?;
+    match group.group_type{
+        GroupType::Normal | GroupType::Dynamic => Ok(group.into()),
+        _ => {err_response!(GroupEndpointError::NonExistentGroup)}
+    }
+}
+
+/// Retrieves a normal group - fails for dynamic groups, whose members can't be managed
+pub fn get_static_group(id: GidInternal, db: &mut DbConn) -> Result<Group, UserAuthErrResponse> {
     let group = get_group(id,db)?;
     match group.group_type{
         GroupType::Normal => Ok(group.into()),
+        GroupType::Dynamic => err_response!(GroupEndpointError::DynamicMembership),
         _ => {err_response!(GroupEndpointError::NonExistentGroup)}
     }
 }
 
-/// Will check if user is already in group
+/// Retrieves the rule of a dynamic group, or `None` for any other group
+pub fn get_group_rule(id: GidInternal, db: &mut DbConn) -> Option<MembershipRule> {
+    let (rule,): (Option<String>,) = db.query_first(&sql!(
+        "SELECT rule FROM auth_groups WHERE id {=} AND group_type {=}", id, GroupType::Dynamic
+    ))?;
+    rule.and_then(|rule| serde_json::from_str(&rule).ok())
+}
+
+/// The dynamic groups of a tenant, with their rules
+fn get_dynamic_groups(tenant_id: TidInternal, db: &mut DbConn) -> Vec<(GidInternal, MembershipRule)> {
+    let groups: Vec<(GidInternal, Option<String>)> = db.query_map(&sql!(
+        "SELECT id, rule FROM auth_groups WHERE tenant_id {=} AND group_type {=}", tenant_id, GroupType::Dynamic
+    ), |(group_id, rule): (GidInternal, Option<String>)| (group_id, rule));
+
+    groups.into_iter()
+        .filter_map(|(group_id, rule)| Some((group_id, serde_json::from_str(&rule?).ok()?)))
+        .collect()
+}
+
+/// Checks the groups a rule refers to belong to the tenant and aren't dynamic, so evaluating a rule
+/// never depends on another rule
+fn check_rule_groups(rule: &MembershipRule, tenant: &TenantRef, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    for group_ref in rule.referenced_groups() {
+        let group_id = decode_group_ref(db, group_ref)?;
+        let group = get_group(group_id, db)?;
+        if &group.tenant != tenant {
+            return err_response!(GroupEndpointError::GroupInOtherTenant);
+        }
+        if let GroupType::Dynamic = group.group_type {
+            return err_response!(GroupEndpointError::InvalidField("Membership rules cannot refer to dynamic groups"));
+        }
+    }
+    Ok(())
+}
+
+/// Publishes a membership change - the event depends on the type of group, as membership of the
+/// special groups is what makes a user a tenant member or admin
+fn publish_membership_change(group_id: GidInternal, user_id: UidInternal, added: bool, db: &mut DbConn)
+-> Result<(), UserAuthErrResponse> {
+    let group = get_group(group_id, db)?;
+    let tenant_id = tenants::internal::decode_tenant_ref(db, group.tenant.clone())?;
+    let user_ref = users::internal::encode_user_ref(db, user_id);
+
+    let (event, data) = match (&group.group_type, added) {
+        (GroupType::SuperGroup, true)  => (EventType::UserAddedToTenant, json!({ "user": user_ref })),
+        (GroupType::SuperGroup, false) => (EventType::UserRemovedFromTenant, json!({ "user": user_ref })),
+        (GroupType::AdminGroup, true)  => (EventType::AdminPromoted, json!({ "user": user_ref })),
+        (GroupType::AdminGroup, false) => (EventType::AdminDemoted, json!({ "user": user_ref })),
+        (GroupType::Normal, true) | (GroupType::Dynamic, true) =>
+            (EventType::GroupMemberAdded, json!({ "group": group.group_ref, "user": user_ref })),
+        (GroupType::Normal, false) | (GroupType::Dynamic, false) =>
+            (EventType::GroupMemberRemoved, json!({ "group": group.group_ref, "user": user_ref })),
+    };
+    events::internal::publish(db, Some(tenant_id), event, data);
+    Ok(())
+}
+
+/// Whether there is a membership row at all, counting those that haven't started or have ended
+fn has_membership(group_id: GidInternal, user_id: UidInternal, db: &mut DbConn) -> bool {
+    db.query_count(&sql!(
+        "SELECT COUNT(*) FROM auth_usergroups WHERE user_id {=} AND group_id {=}", user_id, group_id
+    )) > 0
+}
+
+/// Will check if user is already in group. Call inside a transaction, as this also publishes an event
 pub fn add_user_to_group(group_id: GidInternal, user_id: UidInternal, db: &mut DbConn) -> Result<(), UserAuthErrResponse>{
-    if get_user_ids_in_group(group_id, db)?.contains(&user_id) {
+    add_user_to_group_within(group_id, user_id, MembershipWindow::default(), db)
+}
+
+/// Fails for dynamic groups, whose members follow from their rule
+fn check_not_dynamic(group_id: GidInternal, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    match get_group(group_id, db)?.group_type {
+        GroupType::Dynamic => err_response!(GroupEndpointError::DynamicMembership),
+        _ => Ok(()),
+    }
+}
+
+/// Adds a user to a group for a limited time - only normal groups take a window. Call inside a
+/// transaction, as this also publishes an event.
+pub fn add_user_to_group_within(group_id: GidInternal, user_id: UidInternal, window: MembershipWindow, db: &mut DbConn)
+-> Result<(), UserAuthErrResponse> {
+    check_not_dynamic(group_id, db)?;
+    if has_membership(group_id, user_id, db) {
         return err_response!(GroupEndpointError::UserAlreadyInGroup(user_id, group_id));
     }
-    db.query_drop(&sql!("INSERT INTO auth_usergroups (user_id, group_id) VALUES ({}, {})", user_id, group_id));
-    Ok(())
+    if !window.is_unbounded() {
+        get_static_group(group_id, db)?;
+        validate_membership_window(&window, time::now())
+            .map_err(|e| UserAuthErrResponse::new(GroupEndpointError::InvalidField(e)))?;
+    }
+
+    let awaiting_start = window.valid_from.map(|from| from > time::now()).unwrap_or(false);
+    db.query_drop(&sql!(
+        "INSERT INTO auth_usergroups (user_id, group_id, valid_from, valid_until, awaiting_start) VALUES ({}, {}, {}, {}, {})",
+        user_id, group_id, window.valid_from, window.valid_until, awaiting_start
+    ));
+    publish_membership_change(group_id, user_id, true, db)
 }
 
-/// Will check if user is in group
+/// Will check if user is in group, whether or not the membership is current. Call inside a
+/// transaction, as this also publishes an event
 pub fn remove_user_from_group(group_id: GidInternal, user_id: UidInternal, db: &mut DbConn) -> Result<(), UserAuthErrResponse>{
-    if !get_user_ids_in_group(group_id, db)?.contains(&user_id) {
+    check_not_dynamic(group_id, db)?;
+    if !has_membership(group_id, user_id, db) {
         return err_response!(GroupEndpointError::UserNotInGroup(user_id, group_id));
     }
     db.query_drop(&sql!("
         DELETE FROM auth_usergroups WHERE (user_id {=}) and (group_id {=})
     ", user_id, group_id));
-    Ok(())
+
+    // roles delegated and groups owned in a tenant only last as long as the user is a member of it
+    let group = get_group(group_id, db)?;
+    if let GroupType::SuperGroup = group.group_type {
+        let tenant_id = tenants::internal::decode_tenant_ref(db, group.tenant)?;
+        roles::internal::revoke_user_delegations(user_id, tenant_id, db);
+        db.query_drop(&sql!("
+            DELETE auth_group_owners
+            FROM auth_group_owners, auth_groups
+            WHERE
+                auth_group_owners.group_id = auth_groups.id AND
+                auth_group_owners.user_id {=} AND
+                auth_groups.tenant_id {=}
+        ", user_id, tenant_id));
+    }
+    publish_membership_change(group_id, user_id, false, db)
+}
+
+/// Checks one item of a bulk membership change, returning the user's internal id if it can be applied
+fn check_membership_change(
+    group_id: GidInternal,
+    tenant_id: TidInternal,
+    change: MembershipChange,
+    user_ref: &UserRef,
+    seen: &Vec<UidInternal>,
+    db: &mut DbConn,
+) -> Result<UidInternal, String> {
+    let user_id = users::internal::decode_user_ref(db, user_ref.clone())
+        .map_err(|_| UserEndpointError::UserNonExistent.to_string())?;
+    if seen.contains(&user_id) {
+        return Err(String::from("User listed more than once"));
+    }
+    if !tenants::internal::get_user_tenant_ids(user_id, db).contains(&tenant_id) {
+        return Err(GroupEndpointError::UserNotInTenant.user_message());
+    }
+    match (change, has_membership(group_id, user_id, db)) {
+        (MembershipChange::Add, true) => Err(GroupEndpointError::UserAlreadyInGroup(user_id, group_id).user_message()),
+        (MembershipChange::Remove, false) => Err(GroupEndpointError::UserNotInGroup(user_id, group_id).user_message()),
+        _ => Ok(user_id),
+    }
+}
+
+/// Adds and removes the members of a normal group, checking every item before applying any. Items
+/// that fail the checks are reported and skipped. Returns the result of each item and the users whose
+/// membership changed. Call inside a transaction, as this also publishes events.
+pub fn change_members(group_id: GidInternal, changes: &BulkMembershipChange, db: &mut DbConn)
+-> Result<(Vec<MembershipChangeResult>, Vec<UidInternal>), UserAuthErrResponse> {
+    let group = get_static_group(group_id, db)?;
+    let tenant_id = tenants::internal::decode_tenant_ref(db, group.tenant)?;
+
+    let items = changes.add.iter().map(|user_ref| (MembershipChange::Add, user_ref))
+        .chain(changes.remove.iter().map(|user_ref| (MembershipChange::Remove, user_ref)));
+
+    let mut results = Vec::new();
+    let mut valid: Vec<(MembershipChange, UidInternal)> = Vec::new();
+    for (change, user_ref) in items {
+        let seen: Vec<UidInternal> = valid.iter().map(|(_, user_id)| *user_id).collect();
+        let checked = check_membership_change(group_id, tenant_id, change, user_ref, &seen, db);
+        if let Ok(user_id) = checked {
+            valid.push((change, user_id));
+        }
+        results.push(MembershipChangeResult {
+            user: user_ref.clone(),
+            change,
+            applied: checked.is_ok(),
+            error: checked.err(),
+        });
+    }
+
+    for (change, user_id) in &valid {
+        match change {
+            MembershipChange::Add    => add_user_to_group(group_id, *user_id, db)?,
+            MembershipChange::Remove => remove_user_from_group(group_id, *user_id, db)?,
+        }
+    }
+    Ok((results, valid.into_iter().map(|(_, user_id)| user_id).collect()))
 }
 
 pub fn get_user_ids_in_group(group_id: GidInternal, db: &mut DbConn) -> Result<Vec<UidInternal>, UserAuthErrResponse>{
+    if let Some(rule) = get_group_rule(group_id, db) {
+        let tenant_id = tenants::internal::decode_tenant_ref(db, get_group_tenant(db, group_id))?;
+        return Ok(get_rule_matches(&rule, tenant_id, db)?.into_iter().map(|(user_id, _)| user_id).collect());
+    }
+
     let results: Vec<UidInternal> = db.query_map(
-        &sql!(
+        &format!("{} AND {}", sql!(
             "SELECT 
                 auth_users.id
             FROM auth_users, auth_usergroups
@@ -110,7 +323,7 @@ pub fn get_user_ids_in_group(group_id: GidInternal, db: &mut DbConn) -> Result<V
                 AND auth_usergroups.group_id {=}
             ",
             group_id
-        ),
+        ), ACTIVE_MEMBERSHIP),
         |(user_id,): (UidInternal,)| {
             user_id
         },
@@ -119,67 +332,242 @@ pub fn get_user_ids_in_group(group_id: GidInternal, db: &mut DbConn) -> Result<V
     Ok(results)
 }
 
-pub fn get_users_in_group(group_id: GidInternal, db: &mut DbConn) -> Result<Vec<User>, UserAuthErrResponse> {
-    let results: Vec<User> = db.query_map(
-        &sql!(
-            "SELECT 
-                auth_users.user_ref,
-                username,
-                firstname,
-                lastname,
-                email,
-                timezone,
-                is_superuser
-            FROM auth_users, auth_usergroups
-            WHERE
-                is_deleted = 0 AND
-                auth_usergroups.user_id = auth_users.id
-                AND auth_usergroups.group_id {=}
-            ",
-            group_id
-        ),
-        |(user_ref, username, firstname, lastname, email, timezone, is_superuser): (
-            InternalReference<UserRef>,
-            String,
-            String,
-            String,
-            Option<String>,
-            String,
-            u8,
-        )| {
-            let is_superuser = is_superuser == 1;
-            User {
-                user_ref: user_ref.inner(),
-                username,
-                firstname,
-                lastname,
-                email,
-                timezone,
-                is_superuser,
-            }
-        },
+/// Formats internal ids for an `IN (...)` condition - they are numbers, so need no escaping
+pub fn id_list(ids: &Vec<u64>) -> String {
+    ids.iter().map(|id| id.to_string()).collect::<Vec<String>>().join(",")
+}
+
+/// Follows nesting from the given groups, one column of `auth_group_children` to the other, and
+/// returns every group reached
+fn follow_nesting(start: Vec<GidInternal>, from: &str, to: &str, db: &mut DbConn) -> Vec<GidInternal> {
+    let mut found: Vec<GidInternal> = Vec::new();
+    let mut frontier = start;
+    while !frontier.is_empty() {
+        let next: Vec<GidInternal> = db.query_map(&format!(
+            "SELECT DISTINCT {} FROM auth_group_children WHERE {} IN ({})", to, from, id_list(&frontier)
+        ), |(group_id,): (GidInternal,)| group_id);
+
+        frontier = next.into_iter().filter(|group_id| !found.contains(group_id)).collect();
+        found.extend(frontier.iter().cloned());
+    }
+    found
+}
+
+/// The groups containing any of the given ones, directly or through other groups
+pub fn get_ancestor_group_ids(group_ids: Vec<GidInternal>, db: &mut DbConn) -> Vec<GidInternal> {
+    follow_nesting(group_ids, "child_group_id", "parent_group_id", db)
+}
+
+/// The groups nested inside any of the given ones, directly or through other groups
+pub fn get_descendant_group_ids(group_ids: Vec<GidInternal>, db: &mut DbConn) -> Vec<GidInternal> {
+    follow_nesting(group_ids, "parent_group_id", "child_group_id", db)
+}
+
+/// The groups a user is a member of in a tenant through stored memberships, including those they
+/// inherit by being a member of a group nested inside them
+fn get_static_user_group_ids(user_id: UidInternal, tenant_id: TidInternal, db: &mut DbConn) -> Vec<GidInternal> {
+    let mut group_ids: Vec<GidInternal> = db.query_map(&format!("{} AND {}", sql!("
+        SELECT auth_groups.id
+        FROM auth_groups, auth_usergroups
+        WHERE
+            auth_groups.tenant_id {=} and
+            auth_usergroups.user_id {=}
+            and auth_usergroups.group_id = auth_groups.id
+    ",
+        tenant_id,
+        user_id
+    ), ACTIVE_MEMBERSHIP),
+        |(group_id,): (GidInternal,)| group_id
     );
 
-    Ok(results)
+    let mut inherited = get_ancestor_group_ids(group_ids.clone(), db);
+    inherited.retain(|group_id| !group_ids.contains(group_id));
+    group_ids.append(&mut inherited);
+    group_ids
+}
+
+fn get_group_refs(group_ids: &Vec<GidInternal>, db: &mut DbConn) -> Vec<GroupRef> {
+    if group_ids.is_empty() {
+        return Vec::new();
+    }
+    db.query_map(&format!("SELECT group_ref FROM auth_groups WHERE id IN ({})", id_list(group_ids)),
+        |(group_ref,): (InternalReference<GroupRef>,)| group_ref.inner()
+    )
+}
+
+/// An SQL condition on `auth_users` holding for the users of a tenant who match a rule. Being a
+/// member of a group the rule refers to includes being a member of a group nested inside it; a
+/// group that no longer exists has no members.
+fn rule_condition(rule: &MembershipRule, tenant_id: TidInternal, db: &mut DbConn)
+-> Result<String, UserAuthErrResponse> {
+    let supergroup = tenants::internal::get_tenant_supergroup(tenant_id, db)?;
+    let in_tenant = format!(
+        "auth_users.id IN (SELECT user_id FROM auth_usergroups WHERE group_id = {} AND {})",
+        supergroup, ACTIVE_MEMBERSHIP
+    );
+
+    let matches = rule.to_sql(&mut |group_ref| {
+        let group_id = match decode_group_ref(db, group_ref.clone()) {
+            Ok(group_id) => group_id,
+            Err(_) => return String::from("1 = 0"),
+        };
+        let mut group_ids = vec![group_id];
+        group_ids.append(&mut get_descendant_group_ids(vec![group_id], db));
+        format!("
+            auth_users.id IN (
+                SELECT user_id FROM auth_usergroups, auth_groups
+                WHERE
+                    auth_usergroups.group_id = auth_groups.id AND
+                    auth_groups.tenant_id = {} AND
+                    auth_groups.id IN ({}) AND
+                    {}
+            )", tenant_id, id_list(&group_ids), ACTIVE_MEMBERSHIP
+        )
+    });
+    Ok(format!("{} AND {}", in_tenant, matches))
+}
+
+/// The users of a tenant who match a rule, whether or not it is saved to a group yet
+pub fn get_rule_matches(rule: &MembershipRule, tenant_id: TidInternal, db: &mut DbConn)
+-> Result<Vec<(UidInternal, User)>, UserAuthErrResponse> {
+    let condition = rule_condition(rule, tenant_id, db)?;
+    Ok(db.query_map(&format!("
+        SELECT id, user_ref, username, firstname, lastname, email, timezone, is_superuser
+        FROM auth_users
+        WHERE auth_users.is_deleted = 0 AND {}
+        ORDER BY auth_users.id
+    ", condition),
+        |(user_id, user_ref, username, firstname, lastname, email, timezone, is_superuser):
+        (UidInternal, InternalReference<UserRef>, String, String, String, Option<String>, String, bool)|
+        (user_id, User {
+            user_ref: user_ref.inner(),
+            username, firstname, lastname, email, timezone, is_superuser
+        })
+    ))
+}
+
+/// The groups a user is a member of in a tenant: those they were added to, those they inherit
+/// through nesting, and the dynamic groups whose rule they match
+pub fn get_user_group_ids(user_id: UidInternal, tenant_id: TidInternal, db: &mut DbConn) -> Vec<GidInternal> {
+    let mut group_ids = get_static_user_group_ids(user_id, tenant_id, db);
+
+    let dynamic_groups = get_dynamic_groups(tenant_id, db);
+    if dynamic_groups.is_empty() || !tenants::internal::get_user_tenant_ids(user_id, db).contains(&tenant_id) {
+        return group_ids;
+    }
+    let user = match users::internal::get_user(user_id, db) {
+        Ok(user) => user,
+        Err(_) => return group_ids,
+    };
+    let groups = get_group_refs(&group_ids, db);
+    for (group_id, rule) in dynamic_groups {
+        if rule.matches(&user, &groups) {
+            group_ids.push(group_id);
+        }
+    }
+    group_ids
 }
 
 pub fn get_user_group_refs(user_id: UidInternal, tenant_id: TidInternal, db: &mut DbConn) -> Vec<GroupRef>{
-    db.query_map(&sql!("
-        SELECT group_ref
+    let group_ids = get_user_group_ids(user_id, tenant_id, db);
+    get_group_refs(&group_ids, db)
+}
+
+/// Retrieves a page of a group's members, and if `transitive` also of the members of the groups
+/// nested in it, marked as inherited. The members of dynamic groups are worked out from the rule
+/// first, and then filtered and sorted like the others.
+pub fn get_group_member_page(group_id: GidInternal, transitive: bool, query: &ListQuery, db: &mut DbConn)
+-> Result<Page<GroupMember>, UserAuthErrResponse> {
+    let tenant_id = tenants::internal::decode_tenant_ref(db, get_group_tenant(db, group_id))?;
+    let rule = get_group_rule(group_id, db);
+
+    let condition = match &rule {
+        Some(rule) => rule_condition(rule, tenant_id, db)?,
+        None => {
+            let mut group_ids = vec![group_id];
+            if transitive {
+                group_ids.append(&mut get_descendant_group_ids(vec![group_id], db));
+            }
+            format!(
+                "auth_users.id IN (SELECT user_id FROM auth_usergroups WHERE group_id IN ({}) AND {})",
+                id_list(&group_ids), ACTIVE_MEMBERSHIP
+            )
+        },
+    };
+    let page = users::internal::get_user_page(condition, tenant_id, query, db);
+
+    let user_ids: Vec<UidInternal> = page.items.iter().map(|(user_id, _)| *user_id).collect();
+    let direct: Vec<UidInternal> = match rule.is_none() && transitive && !user_ids.is_empty() {
+        true => db.query_map(&format!("{} AND {} AND user_id IN ({})",
+            sql!("SELECT user_id FROM auth_usergroups WHERE group_id {=}", group_id),
+            ACTIVE_MEMBERSHIP, id_list(&user_ids)
+        ), |(user_id,): (UidInternal,)| user_id),
+        false => user_ids,
+    };
+    Ok(page.map(|(user_id, user)| GroupMember { user, inherited: !direct.contains(&user_id) }))
+}
+
+/// The ids of every user who is a member of the group, directly or through a nested group
+pub fn get_member_ids(group_id: GidInternal, db: &mut DbConn) -> Result<Vec<UidInternal>, UserAuthErrResponse> {
+    let mut user_ids = get_user_ids_in_group(group_id, db)?;
+    for nested_id in get_descendant_group_ids(vec![group_id], db) {
+        for user_id in get_user_ids_in_group(nested_id, db)? {
+            if !user_ids.contains(&user_id) {
+                user_ids.push(user_id);
+            }
+        }
+    }
+    Ok(user_ids)
+}
+
+/// Retrieves the normal and dynamic groups a user is a member of in a tenant, including those
+/// inherited through nesting
+pub fn get_user_tenant_groups(user_id: UidInternal, tenant_id: TidInternal, db: &mut DbConn) -> Vec<Group> {
+    let group_ids = get_user_group_ids(user_id, tenant_id, db);
+    if group_ids.is_empty() {
+        return Vec::new();
+    }
+    db.query_map(&format!("{} AND auth_groups.id IN ({})", sql!("
+        SELECT group_ref, auth_groups.name, tenant_ref
+        FROM auth_groups, auth_tenants
+        WHERE
+            auth_groups.tenant_id = auth_tenants.id and
+            auth_groups.group_type IN ({}, {})
+    ",
+        GroupType::Normal,
+        GroupType::Dynamic
+    ), id_list(&group_ids)),
+        |(group_ref, name, tenant): (InternalReference<GroupRef>, String, InternalReference<TenantRef>)| Group {
+            group_ref: group_ref.inner(),
+            name,
+            tenant: tenant.inner(),
+        }
+    )
+}
+
+/// Retrieves the normal groups a user is a member of, across all of their tenants
+pub fn get_user_groups(user_id: UidInternal, db: &mut DbConn) -> Vec<Group>{
+    db.query_map(&format!("{} AND {}", sql!("
+        SELECT group_ref, auth_groups.name, tenant_ref
         FROM auth_groups, auth_tenants, auth_usergroups
         WHERE
             auth_groups.tenant_id = auth_tenants.id and
-            auth_tenants.id {=} and 
+            auth_groups.group_type {=} and
             auth_usergroups.user_id {=}
             and auth_usergroups.group_id = auth_groups.id
     ",
-        tenant_id,
+        GroupType::Normal,
         user_id
-    ),
-        |(group_ref,): (InternalReference<GroupRef>,)| group_ref.inner()
+    ), ACTIVE_MEMBERSHIP),
+        |(group_ref, name, tenant): (InternalReference<GroupRef>, String, InternalReference<TenantRef>)| Group {
+            group_ref: group_ref.inner(),
+            name,
+            tenant: tenant.inner(),
+        }
     )
 }
 
+/// Call inside a transaction, as this also publishes an event
 pub fn create_group(group: CreateGroup, db: &mut DbConn) -> Result<(GroupRef, GidInternal), UserAuthErrResponse> {
     validate_create_group(&group).map_err(|e|{UserAuthErrResponse::new(GroupEndpointError::InvalidField(e))})?;
 
@@ -189,20 +577,51 @@ pub fn create_group(group: CreateGroup, db: &mut DbConn) -> Result<(GroupRef, Gi
             (*suggested)
         )) == 0
     });
-    let tenant_id= crate::tenants::internal::decode_tenant_ref(db, group.tenant)?;
-
+    let tenant_id= crate::tenants::internal::decode_tenant_ref(db, group.tenant.clone())?;
+    if let Some(rule) = &group.rule {
+        check_rule_groups(rule, &group.tenant, db)?;
+    }
 
+    let (group_type, rule) = match &group.rule {
+        Some(rule) => (GroupType::Dynamic, Some(json!(rule).to_string())),
+        None => (GroupType::Normal, None),
+    };
     let group_id = db.query_insert(&sql!(
-        "INSERT INTO auth_groups (group_ref, name, group_type, tenant_id) VALUES ({}, {}, {}, {})",
+        "INSERT INTO auth_groups (group_ref, name, group_type, tenant_id, rule) VALUES ({}, {}, {}, {}, {})",
         group_ref,
         group.name,
-        GroupType::Normal,
-        tenant_id
+        group_type,
+        tenant_id,
+        rule
+    ));
+
+    let group_ref = group_ref.inner();
+    events::internal::publish(db, Some(tenant_id), EventType::GroupCreated, json!({
+        "group": Group { group_ref: group_ref.clone(), name: group.name, tenant: group.tenant }
+    }));
+
+    Ok((group_ref, group_id))
+}
+
+/// Replaces the rule of a dynamic group. Call inside a transaction, as this also publishes an event
+pub fn set_group_rule(gid: GidInternal, rule: &MembershipRule, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    if get_group_rule(gid, db).is_none() {
+        return err_response!(GroupEndpointError::NotDynamic);
+    }
+    validate_rule(rule).map_err(|e| UserAuthErrResponse::new(GroupEndpointError::InvalidField(e)))?;
+    let group = get_non_special_group(gid, db)?;
+    check_rule_groups(rule, &group.tenant, db)?;
+
+    db.query_drop(&sql!(
+        "UPDATE auth_groups SET rule = {} WHERE id {=}", json!(rule).to_string(), gid
     ));
 
-    Ok((group_ref.inner(), group_id))
+    let tenant_id = tenants::internal::decode_tenant_ref(db, group.tenant.clone())?;
+    events::internal::publish(db, Some(tenant_id), EventType::GroupUpdated, json!({ "group": group, "rule": rule }));
+    Ok(())
 }
 
+/// Call inside a transaction, as this also publishes an event
 pub fn patch_group(gid: GidInternal, changes: Value, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
     let group = get_non_special_group(gid, db)?;
     let group: CreateGroup = group.into();
@@ -211,6 +630,9 @@ pub fn patch_group(gid: GidInternal, changes: Value, db: &mut DbConn) -> Result<
     if changes.get("tenant").is_some(){
         return err_response!(GroupEndpointError::InvalidField("Cannot change group tenant id!"));
     }
+    if changes.get("rule").is_some(){
+        return err_response!(GroupEndpointError::InvalidField("Membership rules are changed through the group's rule"));
+    }
 
     let new_group = replace_json::replace_existing(group, &changes);
     validate_create_group(&new_group).map_err(|e|{UserAuthErrResponse::new(GroupEndpointError::InvalidField(e))})?;
@@ -224,22 +646,170 @@ pub fn patch_group(gid: GidInternal, changes: Value, db: &mut DbConn) -> Result<
         gid
     ));
 
+    let tenant_id = tenants::internal::decode_tenant_ref(db, new_group.tenant.clone())?;
+    let group = get_non_special_group(gid, db)?;
+    events::internal::publish(db, Some(tenant_id), EventType::GroupUpdated, json!({ "group": group }));
+
     Ok(())
 }
 
+/// Call inside a transaction, as this also publishes an event
 pub fn delete_group(gid: GidInternal, db: &mut DbConn) -> Result<(), UserAuthErrResponse>{
-    get_non_special_group(gid, db)?;
+    let group = get_non_special_group(gid, db)?;
+    let tenant_id = tenants::internal::decode_tenant_ref(db, group.tenant.clone())?;
 
-    
-    db.start_transaction();
+    events::internal::publish(db, Some(tenant_id), EventType::GroupDeleted, json!({ "group": group }));
 
     db.query_drop(&sql!(
         "DELETE FROM auth_usergroups WHERE group_id {=}", gid
     ));
+    db.query_drop(&sql!(
+        "DELETE FROM auth_group_roles WHERE group_id {=}", gid
+    ));
+    db.query_drop(&sql!(
+        "DELETE FROM auth_delegated_roles WHERE group_id {=}", gid
+    ));
+    db.query_drop(&sql!(
+        "DELETE FROM auth_group_owners WHERE group_id {=} OR owner_group_id {=}", gid, gid
+    ));
+    db.query_drop(&sql!(
+        "DELETE FROM auth_group_children WHERE parent_group_id {=} OR child_group_id {=}", gid, gid
+    ));
     db.query_drop(&sql!(
         "DELETE FROM auth_groups WHERE id {=}", gid
     ));
 
-    db.commit();
+    Ok(())
+}
+
+pub fn get_group_owners(group_id: GidInternal, db: &mut DbConn) -> GroupOwners {
+    let users = db.query_map(&sql!("
+        SELECT user_ref
+        FROM auth_group_owners, auth_users
+        WHERE auth_group_owners.user_id = auth_users.id AND auth_group_owners.group_id {=}
+    ", group_id), |(user_ref,): (InternalReference<UserRef>,)| user_ref.inner());
+    let groups = db.query_map(&sql!("
+        SELECT group_ref
+        FROM auth_group_owners, auth_groups
+        WHERE auth_group_owners.owner_group_id = auth_groups.id AND auth_group_owners.group_id {=}
+    ", group_id), |(group_ref,): (InternalReference<GroupRef>,)| group_ref.inner());
+
+    GroupOwners { users, groups }
+}
+
+fn has_owner(group_id: GidInternal, owner: GroupOwner, db: &mut DbConn) -> bool {
+    db.query_count(&format!(
+        "SELECT COUNT(*) FROM auth_group_owners WHERE {} AND {}",
+        sql!("group_id {=}", group_id), owner.condition()
+    )) > 0
+}
+
+/// Will check if the owner already owns the group - doesn't check they belong to its tenant
+pub fn add_group_owner(group_id: GidInternal, owner: GroupOwner, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    if has_owner(group_id, owner, db) {
+        return err_response!(GroupEndpointError::OwnerAlreadyAdded);
+    }
+    let (user_id, owner_group_id) = match owner {
+        GroupOwner::User(user_id)   => (Some(user_id), None),
+        GroupOwner::Group(group_id) => (None, Some(group_id)),
+    };
+    db.query_drop(&sql!(
+        "INSERT INTO auth_group_owners (group_id, user_id, owner_group_id) VALUES ({}, {}, {})",
+        group_id, user_id, owner_group_id
+    ));
+    Ok(())
+}
+
+/// Will check if the owner owns the group
+pub fn remove_group_owner(group_id: GidInternal, owner: GroupOwner, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    if !has_owner(group_id, owner, db) {
+        return err_response!(GroupEndpointError::NotAnOwner);
+    }
+    db.query_drop(&format!(
+        "DELETE FROM auth_group_owners WHERE {} AND {}",
+        sql!("group_id {=}", group_id), owner.condition()
+    ));
+    Ok(())
+}
+
+/// Whether a user owns a group, either directly or by being a member of one of the owning groups,
+/// possibly through a nested group
+pub fn is_group_owner(group_id: GidInternal, user_id: UidInternal, db: &mut DbConn) -> bool {
+    if has_owner(group_id, GroupOwner::User(user_id), db) {
+        return true;
+    }
+
+    let owner_group_ids: Vec<GidInternal> = db.query_map(&sql!(
+        "SELECT owner_group_id FROM auth_group_owners WHERE group_id {=} AND owner_group_id IS NOT NULL", group_id
+    ), |(owner_group_id,): (GidInternal,)| owner_group_id);
+    if owner_group_ids.is_empty() {
+        return false;
+    }
+
+    // owning groups belong to the group's tenant, so only the user's groups there matter
+    let tenant_id = match tenants::internal::decode_tenant_ref(db, get_group_tenant(db, group_id)) {
+        Ok(tenant_id) => tenant_id,
+        Err(_) => return false,
+    };
+    let member_of = get_user_group_ids(user_id, tenant_id, db);
+
+    owner_group_ids.iter().any(|owner_group_id| member_of.contains(owner_group_id))
+}
+
+pub fn get_child_groups(parent_id: GidInternal, db: &mut DbConn) -> Vec<Group> {
+    db.query_map(&sql!("
+        SELECT group_ref, auth_groups.name, tenant_ref
+        FROM auth_group_children, auth_groups, auth_tenants
+        WHERE
+            auth_group_children.child_group_id = auth_groups.id AND
+            auth_groups.tenant_id = auth_tenants.id AND
+            auth_group_children.parent_group_id {=}
+    ", parent_id),
+        |(group_ref, name, tenant): (InternalReference<GroupRef>, String, InternalReference<TenantRef>)| Group {
+            group_ref: group_ref.inner(),
+            name,
+            tenant: tenant.inner(),
+        }
+    )
+}
+
+fn is_nested(parent_id: GidInternal, child_id: GidInternal, db: &mut DbConn) -> bool {
+    db.query_count(&sql!(
+        "SELECT COUNT(*) FROM auth_group_children WHERE parent_group_id {=} AND child_group_id {=}",
+        parent_id, child_id
+    )) > 0
+}
+
+/// Nests a group inside another - doesn't check permissions. Both have to be normal groups of the
+/// same tenant, and the parent may not already be nested inside the child.
+pub fn nest_group(parent_id: GidInternal, child_id: GidInternal, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    let parent = get_static_group(parent_id, db)?;
+    let child = get_static_group(child_id, db)?;
+    if parent.tenant != child.tenant {
+        return err_response!(GroupEndpointError::GroupInOtherTenant);
+    }
+    if is_nested(parent_id, child_id, db) {
+        return err_response!(GroupEndpointError::GroupAlreadyNested);
+    }
+    if parent_id == child_id || get_descendant_group_ids(vec![child_id], db).contains(&parent_id) {
+        return err_response!(GroupEndpointError::NestingCycle);
+    }
+
+    db.query_drop(&sql!(
+        "INSERT INTO auth_group_children (parent_group_id, child_group_id) VALUES ({}, {})",
+        parent_id, child_id
+    ));
+    Ok(())
+}
+
+/// Will check if the group is nested in the parent
+pub fn unnest_group(parent_id: GidInternal, child_id: GidInternal, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    if !is_nested(parent_id, child_id, db) {
+        return err_response!(GroupEndpointError::GroupNotNested);
+    }
+    db.query_drop(&sql!(
+        "DELETE FROM auth_group_children WHERE parent_group_id {=} AND child_group_id {=}",
+        parent_id, child_id
+    ));
     Ok(())
 }
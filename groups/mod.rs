@@ -1,4 +1,6 @@
 pub mod endpoints;
 pub mod errors;
+pub mod expiry;
 pub mod internal;
+pub mod rules;
 pub mod structures;
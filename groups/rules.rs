@@ -0,0 +1,102 @@
+use base::sql;
+use serde::{Deserialize, Serialize};
+use user_auth_structs::{GroupRef, User};
+
+use crate::utils::{pagination::{like_prefix, like_suffix}, timezone::is_valid_timezone};
+
+/// Rules are nested no deeper than this, to keep them readable and cheap to evaluate
+const MAX_RULE_DEPTH: usize = 5;
+
+/// Decides membership of a dynamic group from a user's attributes and their other groups
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MembershipRule {
+    /// The user's email address is at the domain, ignoring case
+    EmailDomain { domain: String },
+    Timezone { timezone: String },
+    UsernamePrefix { prefix: String },
+    /// The user is a member of a group of the same tenant that isn't dynamic itself
+    MemberOf { group: GroupRef },
+    All { rules: Vec<MembershipRule> },
+    Any { rules: Vec<MembershipRule> },
+    Not { rule: Box<MembershipRule> },
+}
+
+impl MembershipRule {
+    /// Whether the user matches the rule, given the groups they are in within the rule's tenant
+    pub fn matches(&self, user: &User, groups: &Vec<GroupRef>) -> bool {
+        match self {
+            Self::EmailDomain { domain } => match &user.email {
+                Some(email) => email.to_lowercase().ends_with(&format!("@{}", domain.to_lowercase())),
+                None => false,
+            },
+            Self::Timezone { timezone }     => &user.timezone == timezone,
+            Self::UsernamePrefix { prefix } => user.username.starts_with(prefix.as_str()),
+            Self::MemberOf { group }        => groups.contains(group),
+            Self::All { rules }             => rules.iter().all(|rule| rule.matches(user, groups)),
+            Self::Any { rules }             => rules.iter().any(|rule| rule.matches(user, groups)),
+            Self::Not { rule }              => !rule.matches(user, groups),
+        }
+    }
+
+    /// Compiles the rule to an SQL condition on `auth_users` that holds for exactly the users
+    /// `matches` would accept, so matches can be found in one query. `member_of` gives the
+    /// condition for being a member of a group the rule refers to.
+    pub fn to_sql<F>(&self, member_of: &mut F) -> String
+    where
+        F: FnMut(&GroupRef) -> String
+    {
+        match self {
+            Self::EmailDomain { domain } => sql!(
+                "(auth_users.email IS NOT NULL AND LOWER(auth_users.email) LIKE {})",
+                like_suffix(&format!("@{}", domain.to_lowercase()))
+            ),
+            Self::Timezone { timezone }     => sql!("(auth_users.timezone = {})", timezone),
+            Self::UsernamePrefix { prefix } => sql!("(auth_users.username LIKE BINARY {})", like_prefix(prefix)),
+            Self::MemberOf { group }        => format!("({})", member_of(group)),
+            Self::All { rules }             => format!("({})", rules.iter()
+                .map(|rule| rule.to_sql(member_of)).collect::<Vec<String>>().join(" AND ")),
+            Self::Any { rules }             => format!("({})", rules.iter()
+                .map(|rule| rule.to_sql(member_of)).collect::<Vec<String>>().join(" OR ")),
+            Self::Not { rule }              => format!("(NOT {})", rule.to_sql(member_of)),
+        }
+    }
+
+    /// Every group the rule refers to, which have to be checked against its tenant
+    pub fn referenced_groups(&self) -> Vec<GroupRef> {
+        match self {
+            Self::MemberOf { group } => vec![group.clone()],
+            Self::All { rules } | Self::Any { rules } =>
+                rules.iter().flat_map(|rule| rule.referenced_groups()).collect(),
+            Self::Not { rule } => rule.referenced_groups(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn validate_rule_at(rule: &MembershipRule, depth: usize) -> Result<(), &'static str> {
+    if depth > MAX_RULE_DEPTH {
+        return Err("Membership rules cannot be nested more than 5 deep");
+    }
+    match rule {
+        MembershipRule::EmailDomain { domain } if domain.len() == 0 || domain.contains("@") =>
+            Err("Email domain must be a domain name, without the @"),
+        MembershipRule::Timezone { timezone } if !is_valid_timezone(timezone) =>
+            Err("Invalid timezone in membership rule"),
+        MembershipRule::UsernamePrefix { prefix } if prefix.len() == 0 =>
+            Err("Username prefix cannot be empty"),
+        MembershipRule::All { rules } | MembershipRule::Any { rules } => {
+            if rules.len() == 0 {
+                return Err("Combined membership rules need at least one rule");
+            }
+            rules.iter().map(|rule| validate_rule_at(rule, depth + 1)).collect()
+        },
+        MembershipRule::Not { rule } => validate_rule_at(rule, depth + 1),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the shape of a rule - the groups it refers to are checked against the database separately
+pub fn validate_rule(rule: &MembershipRule) -> Result<(), &'static str> {
+    validate_rule_at(rule, 1)
+}
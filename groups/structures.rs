@@ -2,19 +2,25 @@ use std::str::FromStr;
 
 use base::db::to_sql::AsSql;
 use serde::{Deserialize, Serialize};
-use user_auth_structs::{Group, GroupRef, TenantRef};
+use user_auth_structs::{Group, GroupRef, TenantRef, User, UserRef};
+
+use super::rules::{validate_rule, MembershipRule};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CreateGroup {
     pub name: String,
     pub tenant: TenantRef,
+    /// Makes the group dynamic, its members being the tenant's users who match the rule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<MembershipRule>,
 }
 
 impl From<Group> for CreateGroup{
     fn from(g: Group) -> Self {
         Self{
             name: g.name,
-            tenant: g.tenant
+            tenant: g.tenant,
+            rule: None,
         }
     }
 }
@@ -26,6 +32,9 @@ pub fn validate_create_group(group: &CreateGroup) -> Result<(), &'static str>{
     else if group.name.len() > 45{
         return Err("Group name cannot be longer than 45 characters");
     }
+    if let Some(rule) = &group.rule {
+        validate_rule(rule)?;
+    }
     Ok(())
 }
 
@@ -52,6 +61,8 @@ pub enum GroupType {
     Normal,
     SuperGroup,
     AdminGroup,
+    /// Membership is computed from a rule rather than stored
+    Dynamic,
 }
 
 impl FromStr for GroupType {
@@ -62,6 +73,7 @@ impl FromStr for GroupType {
             "n" => Ok(Self::Normal),
             "s" => Ok(Self::SuperGroup),
             "a" => Ok(Self::AdminGroup),
+            "d" => Ok(Self::Dynamic),
             _ => Err("Invalid group type in database"),
         }
     }
@@ -73,6 +85,7 @@ impl AsSql for GroupType {
             Self::Normal => &"n",
             Self::SuperGroup => &"s",
             Self::AdminGroup => &"a",
+            Self::Dynamic => &"d",
         })
     }
 
@@ -80,3 +93,80 @@ impl AsSql for GroupType {
         "="
     }
 }
+
+/// Those who may manage a group's membership without being tenant admins: users named directly,
+/// and every member of the owning groups
+#[derive(Serialize, Clone)]
+pub struct GroupOwners {
+    pub users: Vec<UserRef>,
+    pub groups: Vec<GroupRef>,
+}
+
+#[derive(Serialize)]
+pub struct GroupMember {
+    #[serde(flatten)]
+    pub user: User,
+    /// Whether the user is only a member through a group nested inside this one
+    pub inherited: bool,
+}
+
+/// When a membership counts, as unix timestamps - either end may be left open
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct MembershipWindow {
+    pub valid_from: Option<u64>,
+    pub valid_until: Option<u64>,
+}
+
+impl MembershipWindow {
+    pub fn is_unbounded(&self) -> bool {
+        self.valid_from.is_none() && self.valid_until.is_none()
+    }
+}
+
+pub fn validate_membership_window(window: &MembershipWindow, now: u64) -> Result<(), &'static str> {
+    if let Some(valid_until) = window.valid_until {
+        if valid_until <= now {
+            return Err("valid_until must be in the future");
+        }
+        if window.valid_from.map(|from| from >= valid_until).unwrap_or(false) {
+            return Err("valid_from must be before valid_until");
+        }
+    }
+    Ok(())
+}
+
+/// Users to add to and remove from a group in one request
+#[derive(Deserialize)]
+pub struct BulkMembershipChange {
+    #[serde(default)]
+    pub add: Vec<UserRef>,
+    #[serde(default)]
+    pub remove: Vec<UserRef>,
+}
+
+pub fn validate_bulk_membership_change(change: &BulkMembershipChange) -> Result<(), &'static str> {
+    let count = change.add.len() + change.remove.len();
+    if count == 0 {
+        return Err("No users to add or remove");
+    }
+    else if count > 500 {
+        return Err("Cannot change more than 500 memberships at once");
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MembershipChange {
+    Add,
+    Remove,
+}
+
+/// The outcome of one item of a bulk membership change
+#[derive(Serialize)]
+pub struct MembershipChangeResult {
+    pub user: UserRef,
+    pub change: MembershipChange,
+    pub applied: bool,
+    pub error: Option<String>,
+}
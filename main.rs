@@ -1,16 +1,27 @@
 #![feature(decl_macro)]
-use std::{path::PathBuf, sync::Mutex};
+use std::{path::PathBuf, sync::Mutex, time::Duration};
 use base::requests::response::{MicroserviceError, MicroserviceErrorResponse};
+use audit::AuditEndpointError;
+use authz::AuthzEndpointError;
+use events::EventEndpointError;
 use cache::PasswordResetTokenCache;
+use sdk_base::Client;
 use groups::errors::GroupEndpointError;
 use serde::Deserialize;
 use tenants::TenantEndpointError;
 use users::UserEndpointError;
+use roles::RoleEndpointError;
+use webhooks::WebhookEndpointError;
 
+mod audit;
+mod authz;
+mod events;
 mod groups;
+mod roles;
 mod tenants;
 mod users;
 mod utils;
+mod webhooks;
 mod cache;
 
 #[macro_use]
@@ -23,15 +34,34 @@ type ConfigType = UserAuthConf;
 pub struct UserAuthConf {
     default_password_hash_id:         u16,
     password_reset_template_filename: PathBuf,
-    password_reset_uri:               String
+    password_reset_uri:               String,
+    deleted_user_retention_days:      Option<u64>,
+    audit_checkpoint_key:             String,
+    /// Every event is appended to this file as a line of JSON. Events are written at least once:
+    /// one can be written again after the relay stops part way, so readers should skip an
+    /// `event_id` they have already seen.
+    event_log_file:                   Option<PathBuf>,
+    token_lifetime_secs:              Option<u64>,
+    max_event_streams:                Option<usize>
 }
 
+/// How long tokens live if not configured, which should match the token server's setting
+const DEFAULT_TOKEN_LIFETIME_SECS: u64 = 24 * 60 * 60;
+/// How many event streams may be open at once if not configured - each holds a worker, so this
+/// should stay well below the number of workers
+const DEFAULT_MAX_EVENT_STREAMS: usize = 8;
+
 pub type UserAuthErrResponse = MicroserviceErrorResponse<UserAuthError>;
 
 pub enum UserAuthError {
     UserEndpoint(UserEndpointError),
     TenantEndpoint(TenantEndpointError),
     GroupEndpoint(GroupEndpointError),
+    AuditEndpoint(AuditEndpointError),
+    WebhookEndpoint(WebhookEndpointError),
+    RoleEndpoint(RoleEndpointError),
+    AuthzEndpoint(AuthzEndpointError),
+    EventEndpoint(EventEndpointError),
 }
 impl From<UserEndpointError> for UserAuthError {
     fn from(e: UserEndpointError) -> Self {
@@ -48,12 +78,42 @@ impl From<GroupEndpointError> for UserAuthError {
         Self::GroupEndpoint(e)
     }
 }
+impl From<AuditEndpointError> for UserAuthError {
+    fn from(e: AuditEndpointError) -> Self {
+        Self::AuditEndpoint(e)
+    }
+}
+impl From<WebhookEndpointError> for UserAuthError {
+    fn from(e: WebhookEndpointError) -> Self {
+        Self::WebhookEndpoint(e)
+    }
+}
+impl From<RoleEndpointError> for UserAuthError {
+    fn from(e: RoleEndpointError) -> Self {
+        Self::RoleEndpoint(e)
+    }
+}
+impl From<AuthzEndpointError> for UserAuthError {
+    fn from(e: AuthzEndpointError) -> Self {
+        Self::AuthzEndpoint(e)
+    }
+}
+impl From<EventEndpointError> for UserAuthError {
+    fn from(e: EventEndpointError) -> Self {
+        Self::EventEndpoint(e)
+    }
+}
 impl MicroserviceError for UserAuthError {
     fn err_code(&self) -> u16 {
         match self {
             UserAuthError::UserEndpoint(e) => e.err_code(),
             UserAuthError::TenantEndpoint(e) => e.err_code(),
             UserAuthError::GroupEndpoint(e) => e.err_code(),
+            UserAuthError::AuditEndpoint(e) => e.err_code(),
+            UserAuthError::WebhookEndpoint(e) => e.err_code(),
+            UserAuthError::RoleEndpoint(e) => e.err_code(),
+            UserAuthError::AuthzEndpoint(e) => e.err_code(),
+            UserAuthError::EventEndpoint(e) => e.err_code(),
         }
     }
 
@@ -62,6 +122,11 @@ impl MicroserviceError for UserAuthError {
             UserAuthError::UserEndpoint(e) => e.user_message(),
             UserAuthError::TenantEndpoint(e) => e.user_message(),
             UserAuthError::GroupEndpoint(e) => e.user_message(),
+            UserAuthError::AuditEndpoint(e) => e.user_message(),
+            UserAuthError::WebhookEndpoint(e) => e.user_message(),
+            UserAuthError::RoleEndpoint(e) => e.user_message(),
+            UserAuthError::AuthzEndpoint(e) => e.user_message(),
+            UserAuthError::EventEndpoint(e) => e.user_message(),
         }
     }
 
@@ -70,6 +135,11 @@ impl MicroserviceError for UserAuthError {
             UserAuthError::UserEndpoint(e) => e.detailed_message(),
             UserAuthError::TenantEndpoint(e) => e.detailed_message(),
             UserAuthError::GroupEndpoint(e) => e.detailed_message(),
+            UserAuthError::AuditEndpoint(e) => e.detailed_message(),
+            UserAuthError::WebhookEndpoint(e) => e.detailed_message(),
+            UserAuthError::RoleEndpoint(e) => e.detailed_message(),
+            UserAuthError::AuthzEndpoint(e) => e.detailed_message(),
+            UserAuthError::EventEndpoint(e) => e.detailed_message(),
         }
     }
 
@@ -78,6 +148,11 @@ impl MicroserviceError for UserAuthError {
             UserAuthError::UserEndpoint(e) => e.status(),
             UserAuthError::TenantEndpoint(e) => e.status(),
             UserAuthError::GroupEndpoint(e) => e.status(),
+            UserAuthError::AuditEndpoint(e) => e.status(),
+            UserAuthError::WebhookEndpoint(e) => e.status(),
+            UserAuthError::RoleEndpoint(e) => e.status(),
+            UserAuthError::AuthzEndpoint(e) => e.status(),
+            UserAuthError::EventEndpoint(e) => e.status(),
         }
     }
 
@@ -92,11 +167,100 @@ fn main() {
     let password_reset_token_cache = PasswordResetTokenCache::new();
 
     let init = base::init::<ConfigType>("user_auth");
-    
+
+    // deleted users are anonymized once they have been deleted for longer than the retention period
+    let retention_days = init.specific_config.deleted_user_retention_days;
+    let deleted_user_purge = utils::scheduler::schedule(
+        "Deleted user purge", Duration::from_secs(60 * 60), move |db| {
+            retention_days.map(|retention_days| format!(
+                "Anonymized {} deleted users", users::internal::purge_deleted_users(retention_days, db)
+            ))
+        }
+    );
+
+    // sessions are forgotten once their token has expired
+    let expired_session_purge = utils::scheduler::schedule(
+        "Expired session purge", Duration::from_secs(60 * 60), |db| {
+            Some(format!(
+                "Removed {} expired sessions", users::internal::purge_expired_sessions(db)
+            ))
+        }
+    );
+
+    // the audit log is checkpointed regularly so that removing entries from its end is detectable
+    let checkpoint_key = init.specific_config.audit_checkpoint_key.clone();
+    let audit_checkpoints = utils::scheduler::schedule(
+        "Audit checkpoints", Duration::from_secs(60 * 60), move |db| {
+            audit::chain::create_checkpoints(db, &checkpoint_key);
+            None
+        }
+    );
+
+    // events written to the outbox are relayed to every sink, a failing sink holding up only itself
+    let mut sinks: Vec<Box<dyn events::sinks::EventSink>> = vec![
+        Box::new(events::sinks::WebhookSink),
+        Box::new(events::sinks::InProcessSink),
+    ];
+    if let Some(path) = init.specific_config.event_log_file.clone() {
+        sinks.push(Box::new(events::sinks::LogFileSink { path }));
+    }
+    let event_relay = utils::scheduler::schedule(
+        "Event relay", Duration::from_secs(2), move |db| {
+            events::relay::relay(db, &sinks);
+            None
+        }
+    );
+
+    // tokens of users removed from a tenant that could not be revoked at the time are retried
+    let revocation_locations = init.global_config.microservice_locations.clone();
+    let token_revocation_retry = utils::scheduler::schedule(
+        "Token revocation retry", Duration::from_secs(60), move |db| {
+            let client = Client::new(
+                String::new(), String::from("token-revocation-retry"), revocation_locations.clone()
+            );
+            match users::internal::retry_token_revocations(&client, db) {
+                0 => None,
+                revoked => Some(format!("Revoked the tokens of {} removed tenant members", revoked)),
+            }
+        }
+    );
+
+    // webhook deliveries are sent in the background, retrying failures with back-off
+    let webhook_delivery = utils::scheduler::schedule(
+        "Webhook delivery", Duration::from_secs(10), |db| {
+            webhooks::delivery::deliver_pending(db);
+            None
+        }
+    );
+
+    // memberships that have ended are removed, and users told about their changed groups
+    let microservice_locations = init.global_config.microservice_locations.clone();
+    let membership_expiry = utils::scheduler::schedule(
+        "Membership expiry", Duration::from_secs(60), move |db| {
+            let client = Client::new(
+                String::new(), String::from("membership-expiry"), microservice_locations.clone()
+            );
+            groups::expiry::sweep(&client, db);
+            None
+        }
+    );
+
     init.rocket
         .mount("/user", users::endpoints::get_endpoints())
         .mount("/tenant", tenants::endpoints::get_endpoints())
         .mount("/group", groups::endpoints::get_endpoints())
+        .mount("/audit", audit::endpoints::get_endpoints())
+        .mount("/event", events::endpoints::get_endpoints())
+        .mount("/webhook", webhooks::endpoints::get_endpoints())
+        .mount("/role", roles::endpoints::get_endpoints())
+        .mount("/authz", authz::endpoints::get_endpoints())
         .manage(Mutex::new(password_reset_token_cache))
+        .attach(deleted_user_purge)
+        .attach(expired_session_purge)
+        .attach(audit_checkpoints)
+        .attach(event_relay)
+        .attach(token_revocation_retry)
+        .attach(webhook_delivery)
+        .attach(membership_expiry)
         .launch();
 }
@@ -0,0 +1,272 @@
+use base::{Status, err_response, requests::{response::text_response::JsonBody, UserRequest}};
+use rocket::{response::status::Created, Route};
+use rocket_contrib::json::Json;
+use serde_json::{json, Value};
+use user_auth_structs::{GroupRef, TenantRef};
+
+use crate::{
+    UserAuthErrResponse, groups,
+    authz::{policy::authorize, structures::{Action, Resource}},
+    audit::{self, structures::{AuditAction, AuditEvent, AuditTarget}},
+    tenants::{self, internal::TidInternal},
+    users::{self, internal::UidInternal},
+    utils::{cache_updater::refresh_user_info, transaction::Transaction}
+};
+use super::{error::RoleEndpointError, internal, structures::*};
+
+pub fn get_endpoints() -> Vec<Route> {
+    routes![
+        create_role, get_tenant_roles, get_role, patch_role, delete_role, assign_role, unassign_role,
+        get_builtin_roles, get_delegations, delegate_role, revoke_delegation
+    ]
+}
+
+/// Retrieves a role, checking the caller administers its tenant
+fn get_administered_role(
+    role_id: u64,
+    request: &mut UserRequest<crate::ConfigType>,
+) -> Result<(Role, TidInternal), UserAuthErrResponse> {
+    let (role, tenant_id) = internal::get_role(role_id, request.db())?;
+    authorize(request, Action::RolesManage, Resource::tenant(role.tenant_ref.clone()), RoleEndpointError::ModificationDenied)?;
+    Ok((role, tenant_id))
+}
+
+/// Pushes new permissions to the token server for users whose roles have changed
+fn refresh_users(
+    user_ids: Vec<UidInternal>,
+    tenant_id: TidInternal,
+    request: &mut UserRequest<crate::ConfigType>,
+) -> Result<(), UserAuthErrResponse> {
+    let client = request.create_http_client();
+    for user_id in user_ids {
+        refresh_user_info(&client, user_id, tenant_id, request.db())?;
+    }
+    Ok(())
+}
+
+#[post("/?<tenant>", data = "<role>")]
+pub fn create_role(
+    tenant: TenantRef,
+    role: JsonBody<CreateRole>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Created<()>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    authorize(&mut request, Action::RolesManage, Resource::tenant(tenant.clone()), RoleEndpointError::ModificationDenied)?;
+
+
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), tenant)?;
+
+    let mut tx = Transaction::start(&mut request);
+    let role_id = internal::create_role(tenant_id, role.0.clone(), tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::RoleCreated, Some(tenant_id), AuditTarget::Role(role_id)
+    ).with_change(&Value::Null, &json!(role.0)));
+    tx.commit();
+
+    Ok(Created(format!("/roles/{}", role_id), None))
+}
+
+#[get("/?<tenant>")]
+pub fn get_tenant_roles(
+    tenant: TenantRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Role>>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    authorize(&mut request, Action::RolesManage, Resource::tenant(tenant.clone()), RoleEndpointError::ReadingDenied)?;
+
+
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), tenant)?;
+    Ok(Json(internal::get_tenant_roles(tenant_id, request.db())))
+}
+
+#[get("/<role_id>")]
+pub fn get_role(
+    role_id: u64,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Role>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let (role, _) = internal::get_role(role_id, request.db())?;
+    authorize(&mut request, Action::RolesManage, Resource::tenant(role.tenant_ref.clone()), RoleEndpointError::ReadingDenied)?;
+
+
+    Ok(Json(role))
+}
+
+#[patch("/<role_id>", data = "<changes>")]
+pub fn patch_role(
+    role_id: u64,
+    changes: JsonBody<Value>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let (before, tenant_id) = get_administered_role(role_id, &mut request)?;
+
+
+    let mut tx = Transaction::start(&mut request);
+    internal::patch_role(role_id, changes.0, tx.db())?;
+    let (after, _) = internal::get_role(role_id, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::RoleUpdated, Some(tenant_id), AuditTarget::Role(role_id)
+    ).with_change(&before, &after));
+    tx.commit();
+
+    let user_ids = internal::get_role_user_ids(role_id, request.db())?;
+    refresh_users(user_ids, tenant_id, &mut request)?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/<role_id>")]
+pub fn delete_role(
+    role_id: u64,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let (before, tenant_id) = get_administered_role(role_id, &mut request)?;
+
+
+    let user_ids = internal::get_role_user_ids(role_id, request.db())?;
+
+    let mut tx = Transaction::start(&mut request);
+    internal::delete_role(role_id, tx.db());
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::RoleDeleted, Some(tenant_id), AuditTarget::Role(role_id)
+    ).with_change(&json!(before), &Value::Null));
+    tx.commit();
+
+    refresh_users(user_ids, tenant_id, &mut request)?;
+    Ok(Status::NoContent)
+}
+
+#[post("/<role_id>/groups/<group_ref>")]
+pub fn assign_role(
+    role_id: u64,
+    group_ref: GroupRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let (role, tenant_id) = get_administered_role(role_id, &mut request)?;
+    let group_id = groups::internal::decode_group_ref(request.db(), group_ref.clone())?;
+    let group = groups::internal::get_non_special_group(group_id, request.db())?;
+    if group.tenant != role.tenant_ref {
+        return err_response!(RoleEndpointError::GroupInOtherTenant);
+    }
+
+
+    let mut tx = Transaction::start(&mut request);
+    internal::assign_role(role_id, group_id, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::RoleAssigned, Some(tenant_id), AuditTarget::Role(role_id)
+    ).with_change(&Value::Null, &json!({ "group": group_ref })));
+    tx.commit();
+
+    let user_ids = groups::internal::get_member_ids(group_id, request.db())?;
+    refresh_users(user_ids, tenant_id, &mut request)?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/<role_id>/groups/<group_ref>")]
+pub fn unassign_role(
+    role_id: u64,
+    group_ref: GroupRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let (_, tenant_id) = get_administered_role(role_id, &mut request)?;
+    let group_id = groups::internal::decode_group_ref(request.db(), group_ref.clone())?;
+
+
+    let mut tx = Transaction::start(&mut request);
+    internal::unassign_role(role_id, group_id, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::RoleUnassigned, Some(tenant_id), AuditTarget::Role(role_id)
+    ).with_change(&json!({ "group": group_ref }), &Value::Null));
+    tx.commit();
+
+    let user_ids = groups::internal::get_member_ids(group_id, request.db())?;
+    refresh_users(user_ids, tenant_id, &mut request)?;
+    Ok(Status::NoContent)
+}
+
+/// Lists the built-in roles that may be delegated to users, along with what they grant
+#[get("/builtin")]
+pub fn get_builtin_roles() -> Json<Vec<BuiltinRoleInfo>> {
+    Json(BuiltinRole::all().into_iter().map(|role| role.into()).collect())
+}
+
+#[get("/delegated?<tenant>")]
+pub fn get_delegations(
+    tenant: TenantRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Delegation>>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    authorize(&mut request, Action::RolesManage, Resource::tenant(tenant.clone()), RoleEndpointError::ReadingDenied)?;
+
+
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), tenant)?;
+    Ok(Json(internal::get_tenant_delegations(tenant_id, request.db())))
+}
+
+#[post("/delegated?<tenant>", data = "<delegation>")]
+pub fn delegate_role(
+    tenant: TenantRef,
+    delegation: JsonBody<CreateDelegation>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Created<()>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    authorize(&mut request, Action::RolesManage, Resource::tenant(tenant.clone()), RoleEndpointError::ModificationDenied)?;
+
+
+    let delegation = delegation.0;
+    validate_create_delegation(&delegation)
+        .map_err(|e| UserAuthErrResponse::new(RoleEndpointError::InvalidField(e)))?;
+
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), tenant.clone())?;
+    let user_id = users::internal::decode_user_ref(request.db(), delegation.user.clone())?;
+    if !tenants::internal::get_user_tenant_ids(user_id, request.db()).contains(&tenant_id) {
+        return err_response!(RoleEndpointError::UserNotInTenant);
+    }
+    let group_id = match &delegation.group {
+        Some(group_ref) => {
+            let group_id = groups::internal::decode_group_ref(request.db(), group_ref.clone())?;
+            if groups::internal::get_non_special_group(group_id, request.db())?.tenant != tenant {
+                return err_response!(RoleEndpointError::GroupInOtherTenant);
+            }
+            Some(group_id)
+        },
+        None => None,
+    };
+
+    let mut tx = Transaction::start(&mut request);
+    let delegation_id = internal::delegate_role(tenant_id, user_id, delegation.role, group_id, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::RoleDelegated, Some(tenant_id), AuditTarget::User(delegation.user.clone())
+    ).with_change(&Value::Null, &json!({ "role": delegation.role, "group": delegation.group })));
+    tx.commit();
+
+    refresh_users(vec![user_id], tenant_id, &mut request)?;
+    Ok(Created(format!("/roles/delegated/{}", delegation_id), None))
+}
+
+#[delete("/delegated/<delegation_id>")]
+pub fn revoke_delegation(
+    delegation_id: u64,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let (delegation, tenant_id, user_id) = internal::get_delegation(delegation_id, request.db())?;
+    authorize(
+        &mut request, Action::RolesManage, Resource::tenant(delegation.tenant_ref.clone()),
+        RoleEndpointError::ModificationDenied
+    )?;
+
+
+    let mut tx = Transaction::start(&mut request);
+    internal::revoke_delegation(delegation_id, tx.db());
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::RoleDelegationRevoked, Some(tenant_id), AuditTarget::User(delegation.user_ref.clone())
+    ).with_change(&json!({ "role": delegation.role, "group": delegation.group }), &Value::Null));
+    tx.commit();
+
+    refresh_users(vec![user_id], tenant_id, &mut request)?;
+    Ok(Status::NoContent)
+}
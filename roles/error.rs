@@ -0,0 +1,86 @@
+use base::{Status, requests::response::MicroserviceError};
+
+#[derive(Debug)]
+pub enum RoleEndpointError {
+    NonExistentRole,
+    InvalidField(&'static str),
+    RoleNameTaken,
+    RoleAlreadyAssigned,
+    RoleNotAssigned,
+    GroupInOtherTenant,
+    ReadingDenied,
+    ModificationDenied,
+    NonExistentDelegation,
+    AlreadyDelegated,
+    UserNotInTenant,
+}
+
+impl MicroserviceError for RoleEndpointError {
+    fn err_code(&self) -> u16 {
+        match self {
+            RoleEndpointError::NonExistentRole       => 0x0500,
+            RoleEndpointError::InvalidField(_)       => 0x0501,
+            RoleEndpointError::RoleNameTaken         => 0x0502,
+            RoleEndpointError::RoleAlreadyAssigned   => 0x0503,
+            RoleEndpointError::RoleNotAssigned       => 0x0504,
+            RoleEndpointError::GroupInOtherTenant    => 0x0505,
+            RoleEndpointError::ReadingDenied         => 0x0506,
+            RoleEndpointError::ModificationDenied    => 0x0507,
+            RoleEndpointError::NonExistentDelegation => 0x0508,
+            RoleEndpointError::AlreadyDelegated      => 0x0509,
+            RoleEndpointError::UserNotInTenant       => 0x050A,
+        }
+    }
+
+    fn user_message(&self) -> String {
+        match self {
+            RoleEndpointError::NonExistentRole       => format!("Role does not exist"),
+            RoleEndpointError::InvalidField(msg)     => format!("Invalid field: {}", msg),
+            RoleEndpointError::RoleNameTaken         => format!("Role name already in use"),
+            RoleEndpointError::RoleAlreadyAssigned   => format!("Role already assigned to group"),
+            RoleEndpointError::RoleNotAssigned       => format!("Role not assigned to group"),
+            RoleEndpointError::GroupInOtherTenant    => format!("Group belongs to a different tenant"),
+            RoleEndpointError::ReadingDenied         => format!("Permission denied"),
+            RoleEndpointError::ModificationDenied    => format!("Permission denied"),
+            RoleEndpointError::NonExistentDelegation => format!("Delegated role does not exist"),
+            RoleEndpointError::AlreadyDelegated      => format!("Role already delegated to user"),
+            RoleEndpointError::UserNotInTenant       => format!("User is not a member of the tenant"),
+        }
+    }
+
+    fn detailed_message(&self) -> String {
+        match self {
+            RoleEndpointError::NonExistentRole       => format!("Role does not exist"),
+            RoleEndpointError::InvalidField(msg)     => format!("Invalid field provided: {}", msg),
+            RoleEndpointError::RoleNameTaken         => format!("A role with this name already exists in the tenant"),
+            RoleEndpointError::RoleAlreadyAssigned   => format!("Role already assigned to group"),
+            RoleEndpointError::RoleNotAssigned       => format!("Role not assigned to group"),
+            RoleEndpointError::GroupInOtherTenant    => format!("Roles can only be assigned to groups of their own tenant"),
+            RoleEndpointError::ReadingDenied         => format!("Permission denied: reading roles"),
+            RoleEndpointError::ModificationDenied    => format!("Permission denied: changing roles"),
+            RoleEndpointError::NonExistentDelegation => format!("Delegated role does not exist"),
+            RoleEndpointError::AlreadyDelegated      => format!("The user already holds this role in the tenant, for this group if any"),
+            RoleEndpointError::UserNotInTenant       => format!("Roles can only be delegated to members of the tenant"),
+        }
+    }
+
+    fn status(&self) -> base::Status {
+        match self {
+            RoleEndpointError::NonExistentRole       => Status::NotFound,
+            RoleEndpointError::InvalidField(_)       => Status::BadRequest,
+            RoleEndpointError::RoleNameTaken         => Status::BadRequest,
+            RoleEndpointError::RoleAlreadyAssigned   => Status::BadRequest,
+            RoleEndpointError::RoleNotAssigned       => Status::BadRequest,
+            RoleEndpointError::GroupInOtherTenant    => Status::BadRequest,
+            RoleEndpointError::ReadingDenied         => Status::Forbidden,
+            RoleEndpointError::ModificationDenied    => Status::Forbidden,
+            RoleEndpointError::NonExistentDelegation => Status::NotFound,
+            RoleEndpointError::AlreadyDelegated      => Status::BadRequest,
+            RoleEndpointError::UserNotInTenant       => Status::BadRequest,
+        }
+    }
+
+    fn err_prefix() -> u16 {
+        unimplemented!()
+    }
+}
@@ -0,0 +1,293 @@
+use std::str::FromStr;
+
+use base::{DbConn, db::error_handling::DatabaseErrHandler, err_response, references::InternalReference, replace_json, sql};
+use serde_json::Value;
+use user_auth_structs::{GroupRef, TenantRef, UserRef};
+
+use crate::{
+    UserAuthErrResponse, groups::{self, internal::GidInternal}, tenants::internal::TidInternal,
+    users::internal::UidInternal
+};
+use super::{error::RoleEndpointError, structures::*};
+
+fn is_role_name_taken(tenant_id: TidInternal, name: &str, except: Option<u64>, db: &mut DbConn) -> bool {
+    db.query_count(&sql!(
+        "SELECT COUNT(*) FROM auth_roles WHERE tenant_id {=} AND name {=} AND id != {}",
+        tenant_id, name, except.unwrap_or(0)
+    )) > 0
+}
+
+fn set_role_permissions(role_id: u64, permissions: &Vec<String>, db: &mut DbConn) {
+    db.query_drop(&sql!("DELETE FROM auth_role_permissions WHERE role_id {=}", role_id));
+    let mut permissions = permissions.clone();
+    permissions.sort();
+    permissions.dedup();
+    for permission in permissions {
+        db.query_drop(&sql!(
+            "INSERT INTO auth_role_permissions (role_id, permission) VALUES ({}, {})",
+            role_id, permission
+        ));
+    }
+}
+
+/// Creates a role in a tenant - doesn't check permissions. Call inside a transaction.
+pub fn create_role(tenant_id: TidInternal, role: CreateRole, db: &mut DbConn) -> Result<u64, UserAuthErrResponse> {
+    validate_create_role(&role)
+        .map_err(|e| UserAuthErrResponse::new(RoleEndpointError::InvalidField(e)))?;
+    if is_role_name_taken(tenant_id, &role.name, None, db) {
+        return err_response!(RoleEndpointError::RoleNameTaken);
+    }
+
+    let role_id = db.query_insert(&sql!(
+        "INSERT INTO auth_roles (tenant_id, name) VALUES ({}, {})",
+        tenant_id, role.name
+    ));
+    set_role_permissions(role_id, &role.permissions, db);
+    Ok(role_id)
+}
+
+fn roles_from_query(db: &mut DbConn, condition: String) -> Vec<(Role, TidInternal)> {
+    let roles: Vec<(u64, TidInternal, TenantRef, String)> = db.query_map(&format!("
+        SELECT auth_roles.id, auth_roles.tenant_id, tenant_ref, auth_roles.name
+        FROM auth_roles, auth_tenants
+        WHERE auth_roles.tenant_id = auth_tenants.id AND {}
+        ORDER BY auth_roles.name
+    ", condition),
+        |(role_id, tenant_id, tenant_ref, name): (u64, TidInternal, InternalReference<TenantRef>, String)|
+            (role_id, tenant_id, tenant_ref.inner(), name)
+    );
+
+    roles.into_iter().map(|(role_id, tenant_id, tenant_ref, name)| {
+        let permissions = db.query_map(&sql!(
+            "SELECT permission FROM auth_role_permissions WHERE role_id {=} ORDER BY permission", role_id
+        ), |(permission,): (String,)| permission);
+        let groups = db.query_map(&sql!("
+            SELECT group_ref
+            FROM auth_group_roles, auth_groups
+            WHERE auth_group_roles.group_id = auth_groups.id AND auth_group_roles.role_id {=}
+        ", role_id), |(group_ref,): (InternalReference<GroupRef>,)| group_ref.inner());
+
+        (Role { role_id, tenant_ref, name, permissions, groups }, tenant_id)
+    }).collect()
+}
+
+/// Retrieves a role along with the internal id of its tenant
+pub fn get_role(role_id: u64, db: &mut DbConn) -> Result<(Role, TidInternal), UserAuthErrResponse> {
+    match roles_from_query(db, sql!("auth_roles.id {=}", role_id)).into_iter().next() {
+        Some(role) => Ok(role),
+        None => err_response!(RoleEndpointError::NonExistentRole),
+    }
+}
+
+pub fn get_tenant_roles(tenant_id: TidInternal, db: &mut DbConn) -> Vec<Role> {
+    roles_from_query(db, sql!("auth_roles.tenant_id {=}", tenant_id))
+        .into_iter()
+        .map(|(role, _)| role)
+        .collect()
+}
+
+/// Updates a role's name and permissions - doesn't check permissions. Call inside a transaction.
+pub fn patch_role(role_id: u64, changes: Value, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    let (role, tenant_id) = get_role(role_id, db)?;
+    let role: CreateRole = role.into();
+
+    let new_role = replace_json::replace_existing(role, &changes);
+    validate_create_role(&new_role)
+        .map_err(|e| UserAuthErrResponse::new(RoleEndpointError::InvalidField(e)))?;
+    if is_role_name_taken(tenant_id, &new_role.name, Some(role_id), db) {
+        return err_response!(RoleEndpointError::RoleNameTaken);
+    }
+
+    db.query_drop(&sql!("UPDATE auth_roles SET name = {} WHERE id = {}", new_role.name, role_id));
+    set_role_permissions(role_id, &new_role.permissions, db);
+    Ok(())
+}
+
+/// Removes a role along with its assignments - call inside a transaction
+pub fn delete_role(role_id: u64, db: &mut DbConn) {
+    db.query_drop(&sql!("DELETE FROM auth_group_roles WHERE role_id {=}", role_id));
+    db.query_drop(&sql!("DELETE FROM auth_role_permissions WHERE role_id {=}", role_id));
+    db.query_drop(&sql!("DELETE FROM auth_roles WHERE id {=}", role_id));
+}
+
+fn is_role_assigned(role_id: u64, group_id: GidInternal, db: &mut DbConn) -> bool {
+    db.query_count(&sql!(
+        "SELECT COUNT(*) FROM auth_group_roles WHERE role_id {=} AND group_id {=}", role_id, group_id
+    )) > 0
+}
+
+/// Will check if the role is already assigned to the group
+pub fn assign_role(role_id: u64, group_id: GidInternal, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    if is_role_assigned(role_id, group_id, db) {
+        return err_response!(RoleEndpointError::RoleAlreadyAssigned);
+    }
+    db.query_drop(&sql!(
+        "INSERT INTO auth_group_roles (group_id, role_id) VALUES ({}, {})", group_id, role_id
+    ));
+    Ok(())
+}
+
+/// Will check if the role is assigned to the group
+pub fn unassign_role(role_id: u64, group_id: GidInternal, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    if !is_role_assigned(role_id, group_id, db) {
+        return err_response!(RoleEndpointError::RoleNotAssigned);
+    }
+    db.query_drop(&sql!(
+        "DELETE FROM auth_group_roles WHERE group_id {=} AND role_id {=}", group_id, role_id
+    ));
+    Ok(())
+}
+
+/// Retrieves the users granted a role through any of its groups, including members of groups
+/// nested in them, whose permissions change with it
+pub fn get_role_user_ids(role_id: u64, db: &mut DbConn) -> Result<Vec<UidInternal>, UserAuthErrResponse> {
+    let group_ids = db.query_map(&sql!(
+        "SELECT group_id FROM auth_group_roles WHERE role_id {=}", role_id
+    ), |(group_id,): (GidInternal,)| group_id);
+
+    let mut user_ids = Vec::new();
+    for group_id in group_ids {
+        for user_id in groups::internal::get_member_ids(group_id, db)? {
+            if !user_ids.contains(&user_id) {
+                user_ids.push(user_id);
+            }
+        }
+    }
+    Ok(user_ids)
+}
+
+fn is_delegated(
+    tenant_id: TidInternal,
+    user_id: UidInternal,
+    role: BuiltinRole,
+    group_id: Option<GidInternal>,
+    db: &mut DbConn,
+) -> bool {
+    db.query_count(&sql!(
+        "SELECT COUNT(*) FROM auth_delegated_roles WHERE tenant_id {=} AND user_id {=} AND role {=} AND group_id {=}",
+        tenant_id, user_id, role, group_id
+    )) > 0
+}
+
+/// Delegates a built-in role to a user in a tenant - doesn't check permissions, nor that the user
+/// and group belong to the tenant
+pub fn delegate_role(
+    tenant_id: TidInternal,
+    user_id: UidInternal,
+    role: BuiltinRole,
+    group_id: Option<GidInternal>,
+    db: &mut DbConn,
+) -> Result<u64, UserAuthErrResponse> {
+    if is_delegated(tenant_id, user_id, role, group_id, db) {
+        return err_response!(RoleEndpointError::AlreadyDelegated);
+    }
+    Ok(db.query_insert(&sql!(
+        "INSERT INTO auth_delegated_roles (tenant_id, user_id, role, group_id, created_at)
+        VALUES ({}, {}, {}, {}, UNIX_TIMESTAMP())",
+        tenant_id, user_id, role, group_id
+    )))
+}
+
+fn delegations_from_query(db: &mut DbConn, condition: String) -> Vec<(Delegation, TidInternal, UidInternal)> {
+    let db_err = db.err_handler();
+    db.query_map(&format!("
+        SELECT
+            auth_delegated_roles.id, auth_delegated_roles.tenant_id, tenant_ref,
+            auth_delegated_roles.user_id, user_ref, role, group_ref, auth_delegated_roles.created_at
+        FROM auth_delegated_roles
+            JOIN auth_tenants ON auth_delegated_roles.tenant_id = auth_tenants.id
+            JOIN auth_users ON auth_delegated_roles.user_id = auth_users.id
+            LEFT JOIN auth_groups ON auth_delegated_roles.group_id = auth_groups.id
+        WHERE {}
+        ORDER BY auth_delegated_roles.id
+    ", condition),
+        |(delegation_id, tenant_id, tenant_ref, user_id, user_ref, role, group_ref, created_at): (
+            u64, TidInternal, InternalReference<TenantRef>, UidInternal, InternalReference<UserRef>,
+            String, Option<InternalReference<GroupRef>>, u64
+        )| (
+            Delegation {
+                delegation_id,
+                tenant_ref: tenant_ref.inner(),
+                user_ref: user_ref.inner(),
+                role: BuiltinRole::from_str(&role).db_expect(&db_err),
+                group: group_ref.map(|g| g.inner()),
+                created_at,
+            },
+            tenant_id,
+            user_id
+        )
+    )
+}
+
+/// Retrieves a delegated role along with the internal ids of its tenant and user
+pub fn get_delegation(delegation_id: u64, db: &mut DbConn)
+-> Result<(Delegation, TidInternal, UidInternal), UserAuthErrResponse> {
+    match delegations_from_query(db, sql!("auth_delegated_roles.id {=}", delegation_id)).into_iter().next() {
+        Some(delegation) => Ok(delegation),
+        None => err_response!(RoleEndpointError::NonExistentDelegation),
+    }
+}
+
+pub fn get_tenant_delegations(tenant_id: TidInternal, db: &mut DbConn) -> Vec<Delegation> {
+    delegations_from_query(db, sql!("auth_delegated_roles.tenant_id {=}", tenant_id))
+        .into_iter()
+        .map(|(delegation, _, _)| delegation)
+        .collect()
+}
+
+pub fn revoke_delegation(delegation_id: u64, db: &mut DbConn) {
+    db.query_drop(&sql!("DELETE FROM auth_delegated_roles WHERE id {=}", delegation_id));
+}
+
+/// Drops the roles delegated to a user in a tenant, for when they leave it
+pub fn revoke_user_delegations(user_id: UidInternal, tenant_id: TidInternal, db: &mut DbConn) {
+    db.query_drop(&sql!(
+        "DELETE FROM auth_delegated_roles WHERE user_id {=} AND tenant_id {=}", user_id, tenant_id
+    ));
+}
+
+/// The permissions a user holds through built-in roles delegated to them in a tenant, those of
+/// group scoped roles limited to their group
+fn get_delegated_permissions(user_id: UidInternal, tenant_id: TidInternal, db: &mut DbConn) -> Vec<String> {
+    let delegations = delegations_from_query(db, sql!(
+        "auth_delegated_roles.user_id {=} AND auth_delegated_roles.tenant_id {=}", user_id, tenant_id
+    ));
+
+    let mut permissions = Vec::new();
+    for (delegation, _, _) in delegations {
+        for action in delegation.role.actions() {
+            permissions.push(match &delegation.group {
+                Some(group_ref) => format!("{}@{}", action.as_str(), group_ref),
+                None => action.as_str().to_string(),
+            });
+        }
+    }
+    permissions
+}
+
+/// Computes a user's effective permissions in a tenant: every permission of every role assigned
+/// to a group they are in, directly or through a nested group, along with those of the built-in
+/// roles delegated to them
+pub fn get_user_permissions(user_id: UidInternal, tenant_id: TidInternal, db: &mut DbConn) -> Vec<String> {
+    let group_ids = groups::internal::get_user_group_ids(user_id, tenant_id, db);
+
+    let mut permissions = match group_ids.is_empty() {
+        true  => Vec::new(),
+        false => db.query_map(&format!("
+            SELECT DISTINCT auth_role_permissions.permission
+            FROM auth_group_roles, auth_roles, auth_role_permissions
+            WHERE
+                auth_group_roles.group_id IN ({}) AND
+                auth_group_roles.role_id = auth_roles.id AND
+                {} AND
+                auth_role_permissions.role_id = auth_roles.id
+        ", groups::internal::id_list(&group_ids),
+            sql!("auth_roles.tenant_id {=}", tenant_id)
+        ), |(permission,): (String,)| permission),
+    };
+
+    permissions.append(&mut get_delegated_permissions(user_id, tenant_id, db));
+    permissions.sort();
+    permissions.dedup();
+    permissions
+}
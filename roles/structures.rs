@@ -0,0 +1,173 @@
+use std::str::FromStr;
+
+use base::db::to_sql::AsSql;
+use serde::{Deserialize, Serialize};
+use user_auth_structs::{GroupRef, TenantRef, UserRef};
+
+use crate::authz::structures::Action;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CreateRole {
+    pub name: String,
+    pub permissions: Vec<String>,
+}
+
+/// Permissions are dotted lowercase names such as `users.read` - other microservices define
+/// their own, so any name of that form is accepted
+pub fn validate_permission(permission: &str) -> Result<(), &'static str> {
+    if permission.len() > 64 {
+        return Err("Permission names cannot be longer than 64 characters");
+    }
+    let parts: Vec<&str> = permission.split(".").collect();
+    if parts.len() < 2 {
+        return Err("Permission names must be of the form <area>.<action>");
+    }
+    for part in parts {
+        if part.len() == 0 || !part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+            return Err("Permission names may only contain lowercase letters, digits, underscores and dots");
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_create_role(role: &CreateRole) -> Result<(), &'static str> {
+    if role.name.len() == 0 {
+        return Err("Role name cannot be empty");
+    }
+    else if role.name.len() > 45 {
+        return Err("Role name cannot be longer than 45 characters");
+    }
+    for permission in &role.permissions {
+        validate_permission(permission)?;
+    }
+    Ok(())
+}
+
+impl From<Role> for CreateRole {
+    fn from(r: Role) -> Self {
+        Self {
+            name: r.name,
+            permissions: r.permissions,
+        }
+    }
+}
+
+/// A named set of permissions, granted to the members of the groups it is assigned to
+#[derive(Serialize, Clone)]
+pub struct Role {
+    pub role_id: u64,
+    pub tenant_ref: TenantRef,
+    pub name: String,
+    pub permissions: Vec<String>,
+    pub groups: Vec<GroupRef>,
+}
+
+/// Roles every tenant has, granting part of what tenant admins may do. They are delegated to
+/// individual users rather than assigned to groups.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinRole {
+    /// Unlocks users and looks after their sessions, but can't change groups. It doesn't reset
+    /// passwords yet, as the password endpoints don't check `users.reset_password`.
+    Helpdesk,
+    /// Manages the membership of a single group
+    GroupManager,
+    /// Reads users, groups and the audit log
+    Auditor,
+}
+
+impl BuiltinRole {
+    pub fn all() -> Vec<Self> {
+        vec![Self::Helpdesk, Self::GroupManager, Self::Auditor]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Helpdesk     => "helpdesk",
+            Self::GroupManager => "group_manager",
+            Self::Auditor      => "auditor",
+        }
+    }
+
+    pub fn actions(&self) -> Vec<Action> {
+        match self {
+            Self::Helpdesk     => vec![Action::UsersRead, Action::UsersReactivate, Action::UsersSessions],
+            Self::GroupManager => vec![Action::GroupsRead, Action::GroupsManage],
+            Self::Auditor      => vec![
+                Action::UsersRead, Action::TenantsReadDetails, Action::GroupsRead, Action::AuditRead
+            ],
+        }
+    }
+
+    /// Whether the role is delegated for one group, its permissions only applying to that group
+    pub fn is_group_scoped(&self) -> bool {
+        *self == Self::GroupManager
+    }
+}
+
+impl FromStr for BuiltinRole {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "helpdesk"      => Ok(Self::Helpdesk),
+            "group_manager" => Ok(Self::GroupManager),
+            "auditor"       => Ok(Self::Auditor),
+            _ => Err("Invalid built-in role in database"),
+        }
+    }
+}
+
+impl AsSql for BuiltinRole {
+    fn as_sql(&self) -> String {
+        AsSql::as_sql(&self.as_str())
+    }
+
+    fn get_eq_operator(&self) -> &'static str {
+        "="
+    }
+}
+
+#[derive(Serialize)]
+pub struct BuiltinRoleInfo {
+    pub role: BuiltinRole,
+    pub permissions: Vec<Action>,
+    pub group_scoped: bool,
+}
+
+impl From<BuiltinRole> for BuiltinRoleInfo {
+    fn from(role: BuiltinRole) -> Self {
+        Self {
+            role,
+            permissions: role.actions(),
+            group_scoped: role.is_group_scoped(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateDelegation {
+    pub user: UserRef,
+    pub role: BuiltinRole,
+    #[serde(default)]
+    pub group: Option<GroupRef>,
+}
+
+pub fn validate_create_delegation(delegation: &CreateDelegation) -> Result<(), &'static str> {
+    match (delegation.role.is_group_scoped(), &delegation.group) {
+        (true, None)     => Err("This role must be delegated for a group"),
+        (false, Some(_)) => Err("This role cannot be delegated for a group"),
+        _ => Ok(()),
+    }
+}
+
+/// A built-in role delegated to a user in a tenant
+#[derive(Serialize, Clone)]
+pub struct Delegation {
+    pub delegation_id: u64,
+    pub tenant_ref: TenantRef,
+    pub user_ref: UserRef,
+    pub role: BuiltinRole,
+    pub group: Option<GroupRef>,
+    pub created_at: u64,
+}
@@ -1,14 +1,36 @@
-use crate::{UserAuthErrResponse, groups, tenants::{CreationError, TenantEndpointError}, users::internal::decode_user_ref, utils::cache_updater::update_user_info};
-use base::{Status, err_response, requests::{response::text_response::JsonBody, UserRequest}};
+use crate::{UserAuthErrResponse, audit::{self, structures::{AuditAction, AuditEvent, AuditTarget}}, groups, tenants::{CreationError, TenantEndpointError}, users::{self, internal::decode_user_ref}, utils::{cache_updater::{revoke_user_tokens, update_user_info}, transaction::Transaction}};
+use serde_json::Value;
+use base::{Status, err_response, log, requests::{response::text_response::JsonBody, UserRequest}};
 use rocket::{response::status::Created, Route};
 use rocket_contrib::json::Json;
 use user_auth_structs::{Group, Tenant, TenantRef, User, UserRef};
 
-use super::internal::{self, decode_tenant_ref};
+use super::internal::{self, decode_tenant_ref, TidInternal};
 use super::structures::*;
+use crate::users::structures::InactiveUser;
+use crate::authz::{policy::authorize, structures::{Action, Resource}};
+use crate::utils::pagination::{ListQuery, Page, SortKey};
 
 pub fn get_endpoints() -> Vec<Route> {
-    routes![create_tenant, get_tenant, get_tenant_users, get_tenant_admins, add_user_to_tenant, make_user_tenant_admin, delete_user_from_tenant, demote_tenant_admin, get_tenant_groups]
+    routes![create_tenant, get_tenants, get_tenant, patch_tenant, archive_tenant, unarchive_tenant, delete_tenant, get_tenant_users, search_tenant_users, get_inactive_tenant_users, get_tenant_admins, add_user_to_tenant, make_user_tenant_admin, delete_user_from_tenant, demote_tenant_admin, get_tenant_groups]
+}
+
+/// Lists every tenant a page at a time with their summary counts, sorted by `name` or `created` and
+/// filtered by name prefix. Only for superusers.
+#[get("/?<cursor>&<limit>&<sort>&<name>")]
+pub fn get_tenants(
+    cursor: Option<String>,
+    limit: Option<u64>,
+    sort: Option<String>,
+    name: Option<String>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Page<TenantSummary>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::TenantsList, Resource::none(), TenantEndpointError::ReadingDenied)?;
+
+    let query = ListQuery::parse(cursor, limit, sort, name, None, None, &[SortKey::Name, SortKey::Created])
+        .map_err(|e| UserAuthErrResponse::new(TenantEndpointError::InvalidQuery(e)))?;
+    Ok(Json(internal::get_tenant_page(&query, request.db())))
 }
 
 #[get("/<tenant_ref>")]
@@ -17,56 +39,237 @@ pub fn get_tenant(
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Json<Tenant>, UserAuthErrResponse> {
     //==PERMISSION CHECK==
-    if !request.user().is_superuser && !request.user_login_info().is_in_tenant(&tenant_ref){
-        return err_response!(TenantEndpointError::ReadingDenied);
-    }
+    authorize(&mut request, Action::TenantsRead, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ReadingDenied)?;
     let tenant_id = decode_tenant_ref(request.db(), tenant_ref)?;
     Ok(Json(
         internal::get_tenant(tenant_id, request.db())
     ))
 }
 
-#[get("/<tenant_ref>/users")]
+/// Parses the query parameters of the lists of users
+fn user_list_query(
+    cursor: Option<String>,
+    limit: Option<u64>,
+    sort: Option<String>,
+    name: Option<String>,
+    email_domain: Option<String>,
+    status: Option<String>,
+) -> Result<ListQuery, UserAuthErrResponse> {
+    ListQuery::parse(
+        cursor, limit, sort, name, email_domain, status, &[SortKey::Username, SortKey::Lastname, SortKey::Created]
+    ).map_err(|e| UserAuthErrResponse::new(TenantEndpointError::InvalidQuery(e)))
+}
+
+/// Lists the tenant's users a page at a time, sorted by `username`, `lastname` or `created` (prefix
+/// with `-` for descending) and filtered by name prefix, email domain or status
+#[get("/<tenant_ref>/users?<cursor>&<limit>&<sort>&<name>&<email_domain>&<status>")]
 pub fn get_tenant_users(
     tenant_ref: TenantRef,
+    cursor: Option<String>,
+    limit: Option<u64>,
+    sort: Option<String>,
+    name: Option<String>,
+    email_domain: Option<String>,
+    status: Option<String>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Page<User>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::TenantsRead, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ReadingDenied)?;
+
+    let query = user_list_query(cursor, limit, sort, name, email_domain, status)?;
+    let tenant_id = decode_tenant_ref(request.db(), tenant_ref)?;
+    internal::get_tenant_users(tenant_id, &query, request.db()).map(|u| Json(u))
+}
+
+/// Changes a tenant's name or other details, recording the change in the audit log
+#[patch("/<tenant_ref>", data = "<changes>")]
+pub fn patch_tenant(
+    tenant_ref: TenantRef,
+    changes: JsonBody<Value>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::TenantsUpdate, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ModificationDenied)?;
+
+    let tenant_id = decode_tenant_ref(request.db(), tenant_ref.clone())?;
+    let before = internal::get_tenant(tenant_id, request.db());
+    let mut tx = Transaction::start(&mut request);
+    internal::patch_tenant(tenant_id, changes.0, tx.db())?;
+    let after = internal::get_tenant(tenant_id, tx.db());
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::TenantUpdated, Some(tenant_id), AuditTarget::Tenant(tenant_ref)
+    ).with_change(&before, &after));
+    tx.commit();
+    Ok(Status::NoContent)
+}
+
+/// Logs every member of the tenant out of it. A member whose tokens can't be revoked doesn't stop
+/// the others being logged out; the first failure is returned once everyone has been tried.
+fn log_out_tenant_users(
+    tenant_id: TidInternal,
+    tenant_ref: &TenantRef,
+    request: &mut UserRequest<crate::ConfigType>,
+) -> Result<(), UserAuthErrResponse> {
+    let client = request.create_http_client();
+    let supergroup = internal::get_tenant_supergroup(tenant_id, request.db())?;
+    let mut result = Ok(());
+    for user_id in groups::internal::get_user_ids_in_group(supergroup, request.db())? {
+        let user_ref = users::internal::encode_user_ref(request.db(), user_id);
+        match revoke_user_tokens(&client, user_ref, Some(tenant_ref.clone())) {
+            Ok(()) => users::internal::delete_sessions(user_id, Some(tenant_id), request.db()),
+            Err(e) => if result.is_ok() {
+                result = Err(e);
+            },
+        }
+    }
+    result
+}
+
+/// Archives a tenant: its members are logged out, nobody can log in to it and it is left out of
+/// users' tenants until it is unarchived. Archiving an archived tenant logs out whoever is still
+/// logged in, so a logout that failed part way can be retried.
+#[post("/<tenant_ref>/archive")]
+pub fn archive_tenant(
+    tenant_ref: TenantRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::TenantsArchive, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ModificationDenied)?;
+
+    let tenant_id = decode_tenant_ref(request.db(), tenant_ref.clone())?;
+    if !internal::is_tenant_archived(tenant_id, request.db()) {
+        let mut tx = Transaction::start(&mut request);
+        internal::set_tenant_archived(tenant_id, true, tx.db())?;
+        audit::internal::record(&mut tx, AuditEvent::new(
+            AuditAction::TenantArchived, Some(tenant_id), AuditTarget::Tenant(tenant_ref.clone())
+        ));
+        tx.commit();
+    }
+
+    // archived first, so nobody can log back in while the members are logged out
+    log_out_tenant_users(tenant_id, &tenant_ref, &mut request)?;
+    Ok(Status::NoContent)
+}
+
+#[post("/<tenant_ref>/unarchive")]
+pub fn unarchive_tenant(
+    tenant_ref: TenantRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::TenantsArchive, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ModificationDenied)?;
+
+    let tenant_id = decode_tenant_ref(request.db(), tenant_ref.clone())?;
+    let mut tx = Transaction::start(&mut request);
+    internal::set_tenant_archived(tenant_id, false, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::TenantUnarchived, Some(tenant_id), AuditTarget::Tenant(tenant_ref)
+    ));
+    tx.commit();
+    Ok(Status::NoContent)
+}
+
+/// Deletes a tenant with its groups and memberships. This cannot be undone - archive the tenant
+/// instead to keep it around.
+#[delete("/<tenant_ref>")]
+pub fn delete_tenant(
+    tenant_ref: TenantRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::TenantsDelete, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ModificationDenied)?;
+
+    let tenant_id = decode_tenant_ref(request.db(), tenant_ref.clone())?;
+    let before = internal::get_tenant(tenant_id, request.db());
+
+    log_out_tenant_users(tenant_id, &tenant_ref, &mut request)?;
+    let mut tx = Transaction::start(&mut request);
+    let group_refs = internal::delete_tenant(tenant_id, tx.db());
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::TenantDeleted, Some(tenant_id), AuditTarget::Tenant(tenant_ref.clone())
+    ).with_change(&before, &Value::Null));
+    tx.commit();
+
+    internal::forget_tenant_ref(tenant_ref);
+    group_refs.into_iter().for_each(groups::internal::forget_group_ref);
+    Ok(Status::NoContent)
+}
+
+const DEFAULT_SEARCH_RESULTS: u64 = 20;
+const MAX_SEARCH_RESULTS: u64 = 100;
+
+/// Finds the tenant's users whose username, name or email contain words starting with those of
+/// `q`, best matches first
+#[get("/<tenant_ref>/users/search?<q>&<limit>")]
+pub fn search_tenant_users(
+    tenant_ref: TenantRef,
+    q: String,
+    limit: Option<u64>,
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Json<Vec<User>>, UserAuthErrResponse> {
     //==PERMISSION CHECK==
-    if !request.user().is_superuser && !request.user_login_info().is_in_tenant(&tenant_ref){
-        return err_response!(TenantEndpointError::ReadingDenied);
+    authorize(&mut request, Action::TenantsRead, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ReadingDenied)?;
+
+    let limit = limit.unwrap_or(DEFAULT_SEARCH_RESULTS);
+    if limit == 0 || limit > MAX_SEARCH_RESULTS {
+        return err_response!(TenantEndpointError::InvalidQuery("limit must be between 1 and 100"));
     }
+    if q.len() > 100 {
+        return err_response!(TenantEndpointError::InvalidQuery("Search query cannot be longer than 100 characters"));
+    }
+    let tenant_id = decode_tenant_ref(request.db(), tenant_ref)?;
+    internal::search_users(tenant_id, &q, limit, request.db()).map(|u| Json(u))
+}
+
+#[get("/<tenant_ref>/users/inactive?<days>")]
+pub fn get_inactive_tenant_users(
+    tenant_ref: TenantRef,
+    days: u64,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<InactiveUser>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::TenantsReadDetails, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ReadingDenied)?;
 
     let tenant_id = decode_tenant_ref(request.db(), tenant_ref)?;
-    internal::get_tenant_users(tenant_id, request.db()).map(|u| Json(u))
+    internal::get_inactive_users(tenant_id, days, request.db()).map(|u| Json(u))
 }
 
-#[get("/<tenant_ref>/admins")]
+#[get("/<tenant_ref>/admins?<cursor>&<limit>&<sort>&<name>&<email_domain>&<status>")]
 pub fn get_tenant_admins(
     tenant_ref: TenantRef,
+    cursor: Option<String>,
+    limit: Option<u64>,
+    sort: Option<String>,
+    name: Option<String>,
+    email_domain: Option<String>,
+    status: Option<String>,
     mut request: UserRequest<crate::ConfigType>,
-) -> Result<Json<Vec<User>>, UserAuthErrResponse> {
+) -> Result<Json<Page<User>>, UserAuthErrResponse> {
     //==PERMISSION CHECK==
-    if !request.user().is_superuser && !request.user_login_info().is_admin_in_tenant(&tenant_ref){
-        return err_response!(TenantEndpointError::ReadingDenied);
-    }
+    authorize(&mut request, Action::TenantsReadDetails, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ReadingDenied)?;
 
+    let query = user_list_query(cursor, limit, sort, name, email_domain, status)?;
     let tenant_id = decode_tenant_ref(request.db(), tenant_ref)?;
-    internal::get_tenant_admins(tenant_id, request.db()).map(|u| Json(u))
+    internal::get_tenant_admins(tenant_id, &query, request.db()).map(|u| Json(u))
 }
 
-#[get("/<tenant_ref>/groups")]
+/// Lists the tenant's groups a page at a time, sorted by `name` or `created` and filtered by name prefix
+#[get("/<tenant_ref>/groups?<cursor>&<limit>&<sort>&<name>")]
 pub fn get_tenant_groups(
     tenant_ref: TenantRef,
+    cursor: Option<String>,
+    limit: Option<u64>,
+    sort: Option<String>,
+    name: Option<String>,
     mut request: UserRequest<crate::ConfigType>,
-) -> Result<Json<Vec<Group>>, UserAuthErrResponse> {
+) -> Result<Json<Page<Group>>, UserAuthErrResponse> {
     //==PERMISSION CHECK==
-    if !request.user().is_superuser && !request.user_login_info().is_admin_in_tenant(&tenant_ref){
-        return err_response!(TenantEndpointError::ReadingDenied);
-    }
+    authorize(&mut request, Action::TenantsReadDetails, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ReadingDenied)?;
 
+    let query = ListQuery::parse(cursor, limit, sort, name, None, None, &[SortKey::Name, SortKey::Created])
+        .map_err(|e| UserAuthErrResponse::new(TenantEndpointError::InvalidQuery(e)))?;
     let tenant_id = decode_tenant_ref(request.db(), tenant_ref.clone())?;
-    internal::get_tenant_non_special_groups(tenant_id, &tenant_ref,request.db())
-        .map(|u| Json(u))
+    Ok(Json(internal::get_tenant_group_page(tenant_id, &tenant_ref, &query, request.db())))
 }
 
 #[post("/", data = "<tenant>")]
@@ -74,27 +277,36 @@ pub fn create_tenant(
     tenant: JsonBody<CreateTenant>,
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Created<()>, UserAuthErrResponse> {
-    if !request.user().is_superuser {
-        return err_response!(TenantEndpointError::Creation(CreationError::Denied));
-    }
-    internal::create_tenant(tenant.0, &mut request)
-        .map(|id| Created(format!("/tenants/{}", id.0), None))
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::TenantsCreate, Resource::none(), TenantEndpointError::Creation(CreationError::Denied))?;
+
+    let mut tx = Transaction::start(&mut request);
+    let (tenant_ref, tenant_id, _) = internal::create_tenant(tenant.0, &mut tx)?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::TenantCreated, Some(tenant_id), AuditTarget::Tenant(tenant_ref.clone())
+    ));
+    tx.commit();
+
+    Ok(Created(format!("/tenants/{}", tenant_ref), None))
 }
 
 #[post("/<tenant_ref>/users/<user_ref>")]
 pub fn add_user_to_tenant(tenant_ref: TenantRef, user_ref: UserRef, mut request: UserRequest<crate::ConfigType>)
     -> Result<Status, UserAuthErrResponse>{
     //==PERMISSION CHECK==
-    if !request.user().is_superuser {
-        return err_response!(TenantEndpointError::ModificationDenied);
-    }
+    authorize(&mut request, Action::TenantsManageMembers, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ModificationDenied)?;
 
 
     let tenant_id = decode_tenant_ref(request.db(), tenant_ref)?;
-    let user_id = decode_user_ref(request.db(), user_ref)?;
+    let user_id = decode_user_ref(request.db(), user_ref.clone())?;
 
     let supergroup = internal::get_tenant_supergroup(tenant_id, request.db())?;;
-    groups::internal::add_user_to_group(supergroup, user_id, request.db())?;
+    let mut tx = Transaction::start(&mut request);
+    groups::internal::add_user_to_group(supergroup, user_id, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserAddedToTenant, Some(tenant_id), AuditTarget::User(user_ref.clone())
+    ));
+    tx.commit();
 
     Ok(Status::NoContent)
 }
@@ -104,17 +316,29 @@ pub fn delete_user_from_tenant(tenant_ref: TenantRef, user_ref: UserRef, mut req
     -> Result<Status, UserAuthErrResponse>
 {
     //==PERMISSION CHECK==
-    if !request.user().is_superuser {
-        return err_response!(TenantEndpointError::ModificationDenied);
-    }
+    authorize(&mut request, Action::TenantsManageMembers, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ModificationDenied)?;
 
-    let tenant_id = decode_tenant_ref(request.db(), tenant_ref)?;
-    let user_id = decode_user_ref(request.db(), user_ref)?;
+    let tenant_id = decode_tenant_ref(request.db(), tenant_ref.clone())?;
+    let user_id = decode_user_ref(request.db(), user_ref.clone())?;
 
     let supergroup = internal::get_tenant_supergroup(tenant_id, request.db())?;;
-    groups::internal::remove_user_from_group(supergroup, user_id, request.db())?;
+    let mut tx = Transaction::start(&mut request);
+    groups::internal::remove_user_from_group(supergroup, user_id, tx.db())?;
+    users::internal::delete_sessions(user_id, Some(tenant_id), tx.db());
+    users::internal::queue_token_revocation(&user_ref, &tenant_ref, tx.db());
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserRemovedFromTenant, Some(tenant_id), AuditTarget::User(user_ref.clone())
+    ));
+    tx.commit();
 
-    // TODO: log out user
+    // the user is only logged out once the removal has gone through, and outside its transaction
+    // so that the token server isn't waited on with rows locked - a failed revocation stays
+    // queued and is retried in the background
+    let logger = request.logger();
+    match revoke_user_tokens(&request.create_http_client(), user_ref.clone(), Some(tenant_ref.clone())) {
+        Ok(()) => users::internal::clear_token_revocation(&user_ref, &tenant_ref, request.db()),
+        Err(_) => log!("Token revocation for user [{}] failed, queued to be retried", user_ref),
+    }
 
     Ok(Status::NoContent)
 }
@@ -124,16 +348,19 @@ pub fn make_user_tenant_admin(tenant_ref: TenantRef, user_ref: UserRef, mut requ
     -> Result<Status, UserAuthErrResponse>
 {
     //==PERMISSION CHECK==
-    if !request.user().is_superuser && !request.user_login_info().is_admin_in_tenant(&tenant_ref){
-        return err_response!(TenantEndpointError::ModificationDenied);
-    }
+    authorize(&mut request, Action::TenantsManageAdmins, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ModificationDenied)?;
 
     let tenant_id = decode_tenant_ref(request.db(), tenant_ref)?;
-    let user_id= decode_user_ref(request.db(), user_ref)?;
+    let user_id= decode_user_ref(request.db(), user_ref.clone())?;
 
 
     let supergroup = internal::get_tenant_admingroup(tenant_id, request.db())?;;
-    groups::internal::add_user_to_group(supergroup, user_id, request.db())?;
+    let mut tx = Transaction::start(&mut request);
+    groups::internal::add_user_to_group(supergroup, user_id, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::AdminPromoted, Some(tenant_id), AuditTarget::User(user_ref.clone())
+    ));
+    tx.commit();
 
     update_user_info(&request.create_http_client(), request.user_login_info().clone(), request.db())?;
 
@@ -145,15 +372,18 @@ pub fn demote_tenant_admin(tenant_ref: TenantRef, user_ref: UserRef, mut request
     -> Result<Status, UserAuthErrResponse>
 {
     //==PERMISSION CHECK==
-    if !request.user().is_superuser && !request.user_login_info().is_admin_in_tenant(&tenant_ref){
-        return err_response!(TenantEndpointError::ModificationDenied);
-    }
+    authorize(&mut request, Action::TenantsManageAdmins, Resource::tenant(tenant_ref.clone()), TenantEndpointError::ModificationDenied)?;
 
     let tenant_id = decode_tenant_ref(request.db(), tenant_ref)?;
-    let user_id = decode_user_ref(request.db(), user_ref)?;
+    let user_id = decode_user_ref(request.db(), user_ref.clone())?;
 
     let supergroup = internal::get_tenant_admingroup(tenant_id, request.db())?;;
-    groups::internal::remove_user_from_group(supergroup, user_id, request.db())?;
+    let mut tx = Transaction::start(&mut request);
+    groups::internal::remove_user_from_group(supergroup, user_id, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::AdminDemoted, Some(tenant_id), AuditTarget::User(user_ref.clone())
+    ));
+    tx.commit();
 
     update_user_info(&request.create_http_client(), request.user_login_info().clone(), request.db())?;
 
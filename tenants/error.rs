@@ -14,6 +14,11 @@ pub enum TenantEndpointError {
     AdminGroupNotFound(TidInternal),
     ReadingDenied,
     ModificationDenied,
+    InvalidQuery(&'static str),
+    InvalidField(&'static str),
+    TenantArchived(TenantRef),
+    AlreadyArchived,
+    NotArchived,
 }
 
 impl MicroserviceError for TenantEndpointError {
@@ -28,6 +33,11 @@ impl MicroserviceError for TenantEndpointError {
             TenantEndpointError::AdminGroupNotFound(_)  => 0x0115,
             TenantEndpointError::ReadingDenied          => 0x0116,
             TenantEndpointError::ModificationDenied     => 0x0117,
+            TenantEndpointError::InvalidQuery(_)        => 0x0118,
+            TenantEndpointError::InvalidField(_)        => 0x0119,
+            TenantEndpointError::TenantArchived(_)      => 0x011A,
+            TenantEndpointError::AlreadyArchived        => 0x011B,
+            TenantEndpointError::NotArchived            => 0x011C,
         }
     }
 
@@ -44,6 +54,11 @@ impl MicroserviceError for TenantEndpointError {
             TenantEndpointError::AdminGroupNotFound(_)  => String::new(),
             TenantEndpointError::ReadingDenied          => format!("Permission denied"),
             TenantEndpointError::ModificationDenied     => format!("Permission denied"),
+            TenantEndpointError::InvalidQuery(msg)      => format!("Invalid query: {}", msg),
+            TenantEndpointError::InvalidField(msg)      => format!("Invalid field: {}", msg),
+            TenantEndpointError::TenantArchived(_)      => format!("Tenant is archived"),
+            TenantEndpointError::AlreadyArchived        => format!("Tenant is already archived"),
+            TenantEndpointError::NotArchived            => format!("Tenant is not archived"),
         }
     }
 
@@ -66,6 +81,11 @@ impl MicroserviceError for TenantEndpointError {
             TenantEndpointError::AdminGroupNotFound(uid) => format!("Admingroup not found for tenant [{}]", uid),
             TenantEndpointError::ReadingDenied => format!("Permission denied: reading tenant information"),
             TenantEndpointError::ModificationDenied => format!("Permission denied: changing tenant information"),
+            TenantEndpointError::InvalidQuery(msg) => format!("Invalid query parameters: {}", msg),
+            TenantEndpointError::InvalidField(msg) => format!("Invalid field provided: {}", msg),
+            TenantEndpointError::TenantArchived(tr) => format!("Tenant [{}] is archived - nobody can log in to it", tr),
+            TenantEndpointError::AlreadyArchived => format!("Tenant is already archived"),
+            TenantEndpointError::NotArchived => format!("Tenant is not archived"),
         }
     }
 
@@ -80,6 +100,11 @@ impl MicroserviceError for TenantEndpointError {
             TenantEndpointError::AdminGroupNotFound(_)  => Status::InternalServerError,
             TenantEndpointError::ReadingDenied          => Status::Forbidden,
             TenantEndpointError::ModificationDenied     => Status::Forbidden,
+            TenantEndpointError::InvalidQuery(_)        => Status::BadRequest,
+            TenantEndpointError::InvalidField(_)        => Status::BadRequest,
+            TenantEndpointError::TenantArchived(_)      => Status::Forbidden,
+            TenantEndpointError::AlreadyArchived        => Status::BadRequest,
+            TenantEndpointError::NotArchived            => Status::BadRequest,
         }
     }
 
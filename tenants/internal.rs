@@ -1,10 +1,13 @@
 use std::str::FromStr;
 
 use super::structures::*;
-use crate::groups::internal::GidInternal;
+use crate::groups::internal::{id_list, GidInternal};
 use crate::groups::structures::RawGroup;
 use crate::users::internal::UidInternal;
+use crate::users::structures::{InactiveUser, LoginOutcome};
+use crate::utils::{pagination::{like_prefix, ListQuery, Page, SortKey}, time};
 use crate::{
+    events::{self, structures::EventType},
     groups::{self, structures::GroupType},
     tenants::{CreationError, TenantEndpointError},
     users, UserAuthErrResponse,
@@ -13,10 +16,11 @@ use base::db::error_handling::DatabaseErrHandler;
 use base::references::InternalReference;
 use base::{
     err_response, log, log_important,
-    requests::UserRequest, sql, DbConn,
+    replace_json, requests::UserRequest, sql, DbConn,
 };
 
-use cached::proc_macro::cached;
+use cached::{proc_macro::cached, Cached};
+use serde_json::{json, Value};
 use user_auth_structs::{Group, GroupRef, Tenant, TenantRef, User, UserRef};
 
 
@@ -35,6 +39,12 @@ pub fn decode_tenant_ref(db: &mut DbConn, reference: TenantRef) -> Result<TidInt
         .ok_or(UserAuthErrResponse::new(TenantEndpointError::TenantNonExistent(reference.clone())))
 }
 
+/// Drops a deleted tenant's reference from the cache. Call once the deletion is committed, or the
+/// reference could be cached again in between.
+pub fn forget_tenant_ref(reference: TenantRef) {
+    DECODE_TENANT_REF_CACHED.lock().unwrap().cache_remove(&InternalReference::new(reference));
+}
+
 #[cached(size=512, convert="{internal}", key="TidInternal")]
 /// Converts an external user id to an internal user id
 pub fn encode_tenant_ref(db: &mut DbConn, internal: TidInternal) -> TenantRef{
@@ -138,13 +148,100 @@ pub fn get_tenant_groups(
     Ok(results)
 }
 
-pub fn get_tenant_non_special_groups(tenant_id: TidInternal, tenant_ref: &TenantRef, db: &mut DbConn) -> Result<Vec<Group>, UserAuthErrResponse>{
-    Ok(
-        get_tenant_groups(tenant_id, GroupType::Normal, tenant_ref, db)?
-            .into_iter()
-            .map(|g|g.into())
-            .collect()
-    )
+/// Retrieves a page of the tenant's normal and dynamic groups
+pub fn get_tenant_group_page(tenant_id: TidInternal, tenant_ref: &TenantRef, query: &ListQuery, db: &mut DbConn) -> Page<Group> {
+    let mut conditions = vec![sql!(
+        "tenant_id {=} AND group_type IN ({}, {})", tenant_id, GroupType::Normal, GroupType::Dynamic
+    )];
+    if let Some(name) = &query.name {
+        conditions.push(sql!("name LIKE {}", like_prefix(name)));
+    }
+    let total = db.query_count(&format!(
+        "SELECT COUNT(*) FROM auth_groups WHERE {}", conditions.join(" AND ")
+    )) as u64;
+
+    let column = match query.sort.key {
+        SortKey::Created => "id",
+        _ => "name",
+    };
+    conditions.push(query.after_condition(column, "id"));
+    let rows = db.query_map(&format!("
+        SELECT CAST({} AS CHAR), id, group_ref, name
+        FROM auth_groups
+        WHERE {}
+        {}
+        LIMIT {}
+    ", column, conditions.join(" AND "), query.order_by(column, "id"), query.limit + 1),
+        |(value, group_id, group_ref, name): (String, GidInternal, InternalReference<GroupRef>, String)|
+        (value, group_id, Group { group_ref: group_ref.inner(), name, tenant: tenant_ref.clone() })
+    );
+    query.page(rows, total)
+}
+
+/// Retrieves a page of every tenant, with their member, admin and group counts and when a user
+/// last logged in to them
+pub fn get_tenant_page(query: &ListQuery, db: &mut DbConn) -> Page<TenantSummary> {
+    let mut conditions = vec![String::from("1 = 1")];
+    if let Some(name) = &query.name {
+        conditions.push(sql!("auth_tenants.name LIKE {}", like_prefix(name)));
+    }
+    let total = db.query_count(&format!(
+        "SELECT COUNT(*) FROM auth_tenants WHERE {}", conditions.join(" AND ")
+    )) as u64;
+
+    let column = match query.sort.key {
+        SortKey::Created => "auth_tenants.id",
+        _ => "auth_tenants.name",
+    };
+    conditions.push(query.after_condition(column, "auth_tenants.id"));
+    let member_count = |group_type: GroupType| sql!("(
+        SELECT COUNT(*) FROM auth_usergroups, auth_groups, auth_users
+        WHERE
+            auth_usergroups.group_id = auth_groups.id AND
+            auth_usergroups.user_id = auth_users.id AND
+            auth_users.is_deleted = 0 AND
+            auth_groups.tenant_id = auth_tenants.id AND
+            auth_groups.group_type {=}
+    )", group_type);
+    let rows = db.query_map(&format!("
+        SELECT
+            CAST({} AS CHAR),
+            auth_tenants.id,
+            tenant_ref,
+            auth_tenants.name,
+            {},
+            {},
+            {},
+            {},
+            auth_tenants.archived_at
+        FROM auth_tenants
+        WHERE {}
+        {}
+        LIMIT {}
+    ",
+        column,
+        member_count(GroupType::SuperGroup),
+        member_count(GroupType::AdminGroup),
+        sql!("(
+            SELECT COUNT(*) FROM auth_groups
+            WHERE auth_groups.tenant_id = auth_tenants.id AND auth_groups.group_type IN ({}, {})
+        )", GroupType::Normal, GroupType::Dynamic),
+        sql!("(
+            SELECT MAX(created_at) FROM auth_login_history
+            WHERE auth_login_history.tenant_id = auth_tenants.id AND outcome {=}
+        )", LoginOutcome::Success),
+        conditions.join(" AND "),
+        query.order_by(column, "auth_tenants.id"),
+        query.limit + 1
+    ),
+        |(value, tenant_id, tenant_ref, name, users, admins, groups, last_activity, archived_at):
+        (String, TidInternal, InternalReference<TenantRef>, String, u64, u64, u64, Option<u64>, Option<u64>)|
+        (value, tenant_id, TenantSummary {
+            tenant: Tenant { tenant_ref: tenant_ref.inner(), name },
+            users, admins, groups, last_activity, archived_at
+        })
+    );
+    query.page(rows, total)
 }
 
 pub fn get_tenant(id: TidInternal, db: &mut DbConn) -> Tenant {
@@ -186,21 +283,105 @@ pub fn get_tenant_admingroup_ref(id: TidInternal, db: &mut DbConn) -> Result<Gro
 }
 
 
-pub fn get_tenant_users(id: TidInternal, db: &mut DbConn) -> Result<Vec<User>, UserAuthErrResponse> {
+/// Retrieves a page of the tenant's users
+pub fn get_tenant_users(id: TidInternal, query: &ListQuery, db: &mut DbConn) -> Result<Page<User>, UserAuthErrResponse> {
     let supergroup = get_tenant_supergroup(id, db)?;
-    groups::internal::get_users_in_group(supergroup, db)
+    Ok(groups::internal::get_group_member_page(supergroup, false, query, db)?.map(|member| member.user))
 }
 
-pub fn get_tenant_admins(id: TidInternal, db: &mut DbConn) -> Result<Vec<User>, UserAuthErrResponse> {
-    match get_tenant_group_ids(id, GroupType::AdminGroup, db)?
-        .into_iter()
-        .next()
-    {
-        Some(supergroup) => groups::internal::get_users_in_group(supergroup, db),
-        None => err_response!(TenantEndpointError::SupergroupNotFound(id)),
+/// Retrieves a page of the tenant's admins
+pub fn get_tenant_admins(id: TidInternal, query: &ListQuery, db: &mut DbConn) -> Result<Page<User>, UserAuthErrResponse> {
+    let admingroup = get_tenant_admingroup(id, db)?;
+    Ok(groups::internal::get_group_member_page(admingroup, false, query, db)?.map(|member| member.user))
+}
+
+/// Turns a search query into a boolean mode full-text query: every word has to appear, possibly
+/// as the start of a longer word. Anything but letters and digits separates words, as it does in
+/// the index.
+fn fulltext_query(q: &str) -> String {
+    q.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() > 0)
+        .map(|word| format!("+{}*", word))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Searches the tenant's users by name, username and email, best matches first. Matching is as
+/// case and accent insensitive as the columns' collation, and uses the full-text index over them.
+pub fn search_users(id: TidInternal, q: &str, limit: u64, db: &mut DbConn) -> Result<Vec<User>, UserAuthErrResponse> {
+    let query = fulltext_query(q);
+    if query.len() == 0 {
+        return err_response!(TenantEndpointError::InvalidQuery("Search query must contain a letter or digit"));
     }
+    let supergroup = get_tenant_supergroup(id, db)?;
+
+    Ok(db.query_map(&sql!("
+        SELECT
+            auth_users.user_ref,
+            username,
+            firstname,
+            lastname,
+            email,
+            timezone,
+            is_superuser,
+            MATCH (username, firstname, lastname, email) AGAINST ({} IN BOOLEAN MODE) AS score
+        FROM auth_users, auth_usergroups
+        WHERE
+            is_deleted = 0 AND
+            auth_usergroups.user_id = auth_users.id AND
+            auth_usergroups.group_id {=} AND
+            MATCH (username, firstname, lastname, email) AGAINST ({} IN BOOLEAN MODE)
+        ORDER BY score DESC, username ASC
+        LIMIT {}
+    ", query, supergroup, query, limit),
+        |(user_ref, username, firstname, lastname, email, timezone, is_superuser, _score):
+        (InternalReference<UserRef>, String, String, String, Option<String>, String, bool, f64)| User {
+            user_ref: user_ref.inner(),
+            username, firstname, lastname, email, timezone, is_superuser
+        }
+    ))
+}
+
+/// Retrieves the tenant's users who have not successfully logged in to it for the given number of days
+pub fn get_inactive_users(id: TidInternal, days: u64, db: &mut DbConn) -> Result<Vec<InactiveUser>, UserAuthErrResponse> {
+    let supergroup = get_tenant_supergroup(id, db)?;
+
+    Ok(db.query_map(&sql!("
+        SELECT
+            auth_users.user_ref,
+            username,
+            firstname,
+            lastname,
+            email,
+            timezone,
+            is_superuser,
+            MAX(auth_login_history.created_at) AS last_login_at
+        FROM auth_users
+            JOIN auth_usergroups ON
+                auth_usergroups.user_id = auth_users.id AND
+                auth_usergroups.group_id = {}
+            LEFT JOIN auth_login_history ON
+                auth_login_history.user_id = auth_users.id AND
+                auth_login_history.tenant_id = {} AND
+                auth_login_history.outcome = 'success'
+        WHERE is_deleted = 0
+        GROUP BY auth_users.id
+        HAVING last_login_at IS NULL OR last_login_at < UNIX_TIMESTAMP() - {}
+        ORDER BY last_login_at ASC
+    ", supergroup, id, days * 24 * 60 * 60),
+        |(user_ref, username, firstname, lastname, email, timezone, is_superuser, last_login_at): (
+            InternalReference<UserRef>, String, String, String, Option<String>, String, bool, Option<u64>
+        )| InactiveUser {
+            user: User {
+                user_ref: user_ref.inner(),
+                username, firstname, lastname, email, timezone, is_superuser
+            },
+            last_login_at
+        }
+    ))
 }
 
+/// Retrieves the tenants the user is a member of and can log in to - archived tenants are left out
 pub fn get_user_tenant_refs(user_id: UidInternal, db: &mut DbConn) -> Vec<TenantRef>{
     let results = db.query_map(&sql!("
         SELECT tenant_ref
@@ -209,6 +390,7 @@ pub fn get_user_tenant_refs(user_id: UidInternal, db: &mut DbConn) -> Vec<Tenant
             auth_groups.tenant_id = auth_tenants.id and 
             auth_usergroups.group_id = auth_groups.id and 
             auth_groups.group_type = 's' and 
+            auth_tenants.archived_at IS NULL and 
             auth_usergroups.user_id {=};
     ",user_id),
     |(tenant_ref,):(InternalReference<TenantRef>,)|{
@@ -218,6 +400,49 @@ pub fn get_user_tenant_refs(user_id: UidInternal, db: &mut DbConn) -> Vec<Tenant
     results
 }
 
+/// Retrieves the ids of every tenant the user is a member of, except archived ones
+pub fn get_user_tenant_ids(user_id: UidInternal, db: &mut DbConn) -> Vec<TidInternal>{
+    db.query_map(&sql!("
+        SELECT auth_groups.tenant_id
+        FROM auth_usergroups, auth_groups, auth_tenants
+        WHERE 
+            auth_usergroups.group_id = auth_groups.id and 
+            auth_groups.tenant_id = auth_tenants.id and 
+            auth_tenants.archived_at IS NULL and 
+            auth_groups.group_type = 's' and 
+            auth_usergroups.user_id {=};
+    ",user_id),
+    |(tenant_id,): (TidInternal,)| tenant_id
+    )
+}
+
+/// Retrieves every tenant the user is a member of, except archived ones
+pub fn get_user_tenants(user_id: UidInternal, db: &mut DbConn) -> Vec<Tenant>{
+    query_user_tenants(user_id, "auth_tenants.archived_at IS NULL", db)
+}
+
+/// Retrieves every tenant the user is a member of, archived ones included, as a data export must
+pub fn get_all_user_tenants(user_id: UidInternal, db: &mut DbConn) -> Vec<Tenant>{
+    query_user_tenants(user_id, "TRUE", db)
+}
+
+fn query_user_tenants(user_id: UidInternal, condition: &str, db: &mut DbConn) -> Vec<Tenant>{
+    db.query_map(&format!("
+        SELECT tenant_ref, auth_tenants.name
+        FROM auth_tenants, auth_usergroups, auth_groups
+        WHERE 
+            auth_groups.tenant_id = auth_tenants.id and 
+            auth_usergroups.group_id = auth_groups.id and 
+            auth_groups.group_type = 's' and 
+            {} and 
+            auth_usergroups.user_id = {};
+    ", condition, user_id),
+    |(tenant_ref, name): (InternalReference<TenantRef>, String)|
+        Tenant { tenant_ref: tenant_ref.inner(), name }
+    )
+}
+
+/// Call inside a transaction, as the tenant is created in several steps
 pub fn create_tenant(
     tenant: CreateTenant,
     request: &mut UserRequest<crate::ConfigType>,
@@ -230,8 +455,6 @@ pub fn create_tenant(
         ));
     }
 
-    request.db().start_transaction();
-
     log_important!("Creating tenant {}...", tenant.name);
     log!("  Creating in tenant table...");
 
@@ -250,6 +473,10 @@ pub fn create_tenant(
 
     log!("  ...Success [id={}]", tenant_id);
 
+    events::internal::publish(request.db(), Some(tenant_id), EventType::TenantCreated, json!({
+        "tenant": Tenant { tenant_ref: tenant_ref.clone().inner(), name: tenant.name.clone() }
+    }));
+
     log!("  Creating supergroup...");
 
     let supergroup_ref = InternalReference::<GroupRef>::gen_unique_rand(|suggested| {
@@ -293,7 +520,7 @@ pub fn create_tenant(
         }
         None => {
             let new_user = tenant.superuser.unwrap();
-            let new_user = users::internal::create_user(new_user, request)?;
+            let new_user = users::internal::create_user(new_user, Some(tenant_id), request)?;
             (new_user.0.user_ref, new_user.1)
         }
     };
@@ -314,6 +541,101 @@ pub fn create_tenant(
         admingroup_id
     ));
 
-    request.db().commit();
     Ok((tenant_ref.inner(), tenant_id, superuser_ref))
 }
+
+pub fn is_tenant_archived(id: TidInternal, db: &mut DbConn) -> bool {
+    db.query_count(&sql!(
+        "SELECT COUNT(*) FROM auth_tenants WHERE id {=} AND archived_at IS NOT NULL", id
+    )) > 0
+}
+
+/// Call inside a transaction, as this also publishes an event
+pub fn patch_tenant(id: TidInternal, changes: Value, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    let tenant: UpdateTenant = get_tenant(id, db).into();
+
+    if changes.get("tenant_ref").is_some() {
+        return err_response!(TenantEndpointError::InvalidField("Cannot change tenant reference!"));
+    }
+
+    let new_tenant = replace_json::replace_existing(tenant, &changes);
+    validate_update_tenant(&new_tenant).map_err(|e| UserAuthErrResponse::new(TenantEndpointError::InvalidField(e)))?;
+
+    db.query_drop(&sql!(
+        "UPDATE auth_tenants SET
+            name = {}
+        WHERE id = {}",
+        new_tenant.name,
+        id
+    ));
+
+    let tenant = get_tenant(id, db);
+    events::internal::publish(db, Some(id), EventType::TenantUpdated, json!({ "tenant": tenant }));
+    Ok(())
+}
+
+/// Archives a tenant, or brings it back - doesn't log anybody out. Call inside a transaction, as
+/// this also publishes an event.
+pub fn set_tenant_archived(id: TidInternal, archived: bool, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    match (archived, is_tenant_archived(id, db)) {
+        (true, true)   => return err_response!(TenantEndpointError::AlreadyArchived),
+        (false, false) => return err_response!(TenantEndpointError::NotArchived),
+        _ => (),
+    }
+
+    let (archived_at, event) = match archived {
+        true  => (Some(time::now()), EventType::TenantArchived),
+        false => (None, EventType::TenantUnarchived),
+    };
+    db.query_drop(&sql!(
+        "UPDATE auth_tenants SET archived_at = {} WHERE id = {}", archived_at, id
+    ));
+
+    let tenant = get_tenant(id, db);
+    events::internal::publish(db, Some(id), event, json!({ "tenant": tenant }));
+    Ok(())
+}
+
+/// Deletes a tenant for good, with its groups, memberships, roles, webhooks and sessions. The audit
+/// log and login history are kept. Call inside a transaction, as this also publishes an event.
+/// Returns the references of the deleted groups, to be forgotten once the transaction is committed.
+pub fn delete_tenant(id: TidInternal, db: &mut DbConn) -> Vec<GroupRef> {
+    let tenant = get_tenant(id, db);
+    let groups = db.query_map(&sql!(
+        "SELECT id, group_ref FROM auth_groups WHERE tenant_id {=}", id
+    ), |(group_id, group_ref): (GidInternal, InternalReference<GroupRef>)| (group_id, group_ref.inner()));
+    let group_ids = id_list(&groups.iter().map(|(group_id, _)| *group_id).collect::<Vec<_>>());
+    let role_ids = id_list(&db.query_map(&sql!(
+        "SELECT id FROM auth_roles WHERE tenant_id {=}", id
+    ), |(role_id,): (u64,)| role_id));
+
+    events::internal::publish(db, Some(id), EventType::TenantDeleted, json!({ "tenant": tenant }));
+
+    if group_ids.len() > 0 {
+        db.query_drop(&format!("DELETE FROM auth_usergroups WHERE group_id IN ({})", group_ids));
+        db.query_drop(&format!("DELETE FROM auth_group_roles WHERE group_id IN ({})", group_ids));
+        db.query_drop(&format!(
+            "DELETE FROM auth_group_owners WHERE group_id IN ({}) OR owner_group_id IN ({})", group_ids, group_ids
+        ));
+        db.query_drop(&format!(
+            "DELETE FROM auth_group_children WHERE parent_group_id IN ({}) OR child_group_id IN ({})", group_ids, group_ids
+        ));
+    }
+    if role_ids.len() > 0 {
+        db.query_drop(&format!("DELETE FROM auth_role_permissions WHERE role_id IN ({})", role_ids));
+    }
+    db.query_drop(&sql!("
+        DELETE auth_webhook_deliveries
+        FROM auth_webhook_deliveries, auth_webhooks
+        WHERE auth_webhook_deliveries.webhook_id = auth_webhooks.id AND auth_webhooks.tenant_id {=}
+    ", id));
+    db.query_drop(&sql!("DELETE FROM auth_webhooks WHERE tenant_id {=}", id));
+    db.query_drop(&sql!("DELETE FROM auth_roles WHERE tenant_id {=}", id));
+    db.query_drop(&sql!("DELETE FROM auth_delegated_roles WHERE tenant_id {=}", id));
+    db.query_drop(&sql!("DELETE FROM auth_tenant_suspensions WHERE tenant_id {=}", id));
+    db.query_drop(&sql!("DELETE FROM auth_sessions WHERE tenant_id {=}", id));
+    db.query_drop(&sql!("DELETE FROM auth_groups WHERE tenant_id {=}", id));
+    db.query_drop(&sql!("DELETE FROM auth_tenants WHERE id {=}", id));
+
+    groups.into_iter().map(|(_, group_ref)| group_ref).collect()
+}
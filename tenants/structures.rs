@@ -1,6 +1,6 @@
 use crate::users::structures::CreateUser;
-use serde::Deserialize;
-use user_auth_structs::UserRef;
+use serde::{Deserialize, Serialize};
+use user_auth_structs::{Tenant, UserRef};
 
 
 #[derive(Deserialize)]
@@ -9,3 +9,40 @@ pub struct CreateTenant {
     pub superuser: Option<CreateUser>,
     pub superuser_id: Option<UserRef>,
 }
+
+/// A tenant with the counts shown when listing every tenant
+#[derive(Serialize)]
+pub struct TenantSummary {
+    #[serde(flatten)]
+    pub tenant: Tenant,
+    pub users: u64,
+    pub admins: u64,
+    pub groups: u64,
+    /// When a user last logged in to the tenant
+    pub last_activity: Option<u64>,
+    pub archived_at: Option<u64>,
+}
+
+/// The parts of a tenant that can be changed after it is created
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UpdateTenant {
+    pub name: String,
+}
+
+impl From<Tenant> for UpdateTenant {
+    fn from(t: Tenant) -> Self {
+        Self {
+            name: t.name,
+        }
+    }
+}
+
+pub fn validate_update_tenant(tenant: &UpdateTenant) -> Result<(), &'static str> {
+    if tenant.name.len() == 0 {
+        return Err("Tenant name cannot be empty");
+    }
+    else if tenant.name.len() > 45 {
+        return Err("Tenant name cannot be longer than 45 characters");
+    }
+    Ok(())
+}
@@ -11,9 +11,10 @@ use base::{
     requests::{response::text_response::JsonBody, OpenRequest}
 };
 use crate::{
-    UserAuthErrResponse, groups,
+    UserAuthErrResponse, groups, roles,
     tenants::{self, TenantEndpointError}, 
-    users::{self, endpoints::password}
+    users::{self, endpoints::password, structures::LoginOutcome, UserEndpointError},
+    utils::client_info::ClientInfo
 };
 
 #[derive(Deserialize)]
@@ -26,6 +27,7 @@ pub fn login(
     long_username: String,
     login_data:    JsonBody<LoginData>,
     tenant:        Option<TenantRef>,
+    client:        ClientInfo,
     mut request:   OpenRequest<crate::ConfigType>
 ) -> Result<JsonValue, UserAuthErrResponse> {
     let logger = request.logger();
@@ -52,14 +54,34 @@ pub fn login(
         (long_username, tenant)
     };
 
+    // look up the user id separately, so failed attempts against a real user can be attributed
+    let known_user_id = internal::get_user_sec_info(&username, &mut db).ok().map(|(id, _, _)| id);
+
     // check password and get user id
-    let user_id = password::check_password(
+    let user_id = match password::check_password(
         &username, &login_data.password, &config, &logger, &mut db
-    )?;
+    ) {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            let outcome = match known_user_id {
+                Some(_) => LoginOutcome::WrongPassword,
+                None    => LoginOutcome::UnknownUser,
+            };
+            internal::record_login(known_user_id, &username, None, outcome, &client, &mut db);
+            return Err(e);
+        }
+    };
 
     // get full user from database (no security information included)
     let user = internal::get_user(user_id, &mut db)?;
 
+    // suspended accounts may not log in at all
+    if let Some(suspension) = internal::get_suspension(user_id, None, &mut db) {
+        log_important!("{f:red}Login refused, account suspended: {}", suspension.reason);
+        internal::record_login(Some(user_id), &username, None, LoginOutcome::Locked, &client, &mut db);
+        return Err(UserAuthErrResponse::new(UserEndpointError::UserSuspended));
+    }
+
     // store user reference in the request store for logging purposes
     request.get_request_storage().update_user_ref(&user.user_ref);
 
@@ -69,6 +91,10 @@ pub fn login(
         let user_tenants = tenants::internal::get_user_tenant_refs(user_id, &mut db);
         if let Some(t_ref) = &tenant_ref {
             if !user_tenants.contains(&t_ref) {
+                let tenant_id = tenants::internal::decode_tenant_ref(&mut db, t_ref.clone()).ok();
+                internal::record_login(
+                    Some(user_id), &username, tenant_id, LoginOutcome::TenantNotAuthorized, &client, &mut db
+                );
                 return Err(UserAuthErrResponse::new(
                     TenantEndpointError::TenantNotAuthorized(t_ref.clone())
                 ));
@@ -77,6 +103,9 @@ pub fn login(
         else {
             // if there is more than one tenant then require a tenant ref
             if user_tenants.len() > 1 {
+                internal::record_login(
+                    Some(user_id), &username, None, LoginOutcome::TenantRequired, &client, &mut db
+                );
                 return Err(UserAuthErrResponse::new(
                     TenantEndpointError::TenantRequired
                 ));
@@ -86,13 +115,36 @@ pub fn login(
     }
 
     // now unwrap the tenant_ref, failing if we don't have one
-    let tenant_ref = tenant_ref.ok_or(
-        UserAuthErrResponse::new(TenantEndpointError::TenantRequired)
-    )?;
+    let tenant_ref = match tenant_ref {
+        Some(tenant_ref) => tenant_ref,
+        None => {
+            internal::record_login(
+                Some(user_id), &username, None, LoginOutcome::TenantRequired, &client, &mut db
+            );
+            return Err(UserAuthErrResponse::new(TenantEndpointError::TenantRequired));
+        }
+    };
 
     // map the tenant reference to a tenant id
     let tenant_id = tenants::internal::decode_tenant_ref(&mut db, tenant_ref.clone())?;
 
+    // nobody logs in to an archived tenant, superusers included
+    if tenants::internal::is_tenant_archived(tenant_id, &mut db) {
+        internal::record_login(
+            Some(user_id), &username, Some(tenant_id), LoginOutcome::TenantNotAuthorized, &client, &mut db
+        );
+        return Err(UserAuthErrResponse::new(TenantEndpointError::TenantArchived(tenant_ref)));
+    }
+
+    // the user may also be suspended from just this tenant
+    if let Some(suspension) = internal::get_suspension(user_id, Some(tenant_id), &mut db) {
+        log_important!("{f:red}Login refused, suspended from tenant: {}", suspension.reason);
+        internal::record_login(
+            Some(user_id), &username, Some(tenant_id), LoginOutcome::Locked, &client, &mut db
+        );
+        return Err(UserAuthErrResponse::new(UserEndpointError::UserSuspended));
+    }
+
     // load up user groups and tenant admin group
     let mut user_groups = groups::internal::get_user_group_refs(user_id, tenant_id, &mut db);
     let tenant_admingroup = tenants::internal::get_tenant_admingroup_ref(tenant_id, &mut db)?;
@@ -111,6 +163,9 @@ pub fn login(
     // determine if this user is a tenant admin
     let is_tenant_admin = user_groups.contains(&tenant_admingroup);
 
+    // effective permissions granted through the roles of the user's groups
+    let permissions = roles::internal::get_user_permissions(user_id, tenant_id, &mut db);
+
     // JAHS added - temporary solution to get the tenant name and store in TenantLoginInfo
     let tenant_name = tenants::internal::get_tenant(tenant_id, &mut db).name;
 
@@ -121,6 +176,7 @@ pub fn login(
             tenant_name,
             is_tenant_admin,
             groups: user_groups,
+            permissions,
         }
     };
 
@@ -131,6 +187,16 @@ pub fn login(
     let token = token_server_sdk::create_token(&http_client, user_info)??;
     log!("...token received from token server.");
 
+    // record the session so the user can see and revoke it later
+    let lifetime_secs = config.token_lifetime_secs.unwrap_or(crate::DEFAULT_TOKEN_LIFETIME_SECS);
+    let session_id = internal::create_session(
+        user_id, tenant_id, &token, lifetime_secs, &client, &mut db
+    );
+    log!("Recorded session [id={}].", session_id);
+    internal::record_login(
+        Some(user_id), &username, Some(tenant_id), LoginOutcome::Success, &client, &mut db
+    );
+
     log_important!("{f:green}Login successful, responding with token.");
 
     Ok(json!({
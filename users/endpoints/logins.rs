@@ -0,0 +1,47 @@
+use base::{err_response, requests::UserRequest};
+use rocket_contrib::json::Json;
+use user_auth_structs::UserRef;
+
+use crate::{
+    UserAuthErrResponse,
+    users::{internal, structures::LoginAttempt, InvalidField, UserEndpointError},
+};
+use super::sessions::session_scope;
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 500;
+
+#[get("/self/logins?<before>&<limit>")]
+pub fn get_self_logins(
+    before: Option<u64>,
+    limit: Option<u64>,
+    request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<LoginAttempt>>, UserAuthErrResponse> {
+    get_user_logins(request.user_ref(), before, limit, request)
+}
+
+/// Lists a user's login attempts, newest first. Pass the id of the last attempt received as
+/// `before` to get the next page.
+#[get("/<user_ref>/logins?<before>&<limit>")]
+pub fn get_user_logins(
+    user_ref: UserRef,
+    before: Option<u64>,
+    limit: Option<u64>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<LoginAttempt>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let user_id = internal::decode_user_ref(request.db(), user_ref)?;
+    let scope = session_scope(&mut request, user_id)?;
+
+
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit < 1 || limit > MAX_PAGE_SIZE {
+        return err_response!(UserEndpointError::InvalidField(InvalidField::OutOfRange {
+            field: "limit",
+            min: 1,
+            max: MAX_PAGE_SIZE,
+        }));
+    }
+
+    Ok(Json(internal::get_login_history(user_id, scope, before, limit, request.db())))
+}
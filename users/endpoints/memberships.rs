@@ -0,0 +1,49 @@
+use base::requests::UserRequest;
+use rocket_contrib::json::Json;
+use user_auth_structs::{Group, Tenant, TenantRef, UserRef};
+
+use crate::{
+    UserAuthErrResponse, groups, tenants,
+    authz::{policy::authorize, structures::{Action, Resource}},
+    users::{internal, UserEndpointError},
+};
+
+/// Lists the groups a user is a member of in a tenant - the caller's tenant if none is given
+#[get("/<user_ref>/groups?<tenant>")]
+pub fn get_user_groups(
+    user_ref: UserRef,
+    tenant: Option<TenantRef>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Group>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let user_id = internal::decode_user_ref(request.db(), user_ref.clone())?;
+    authorize(
+        &mut request, Action::UsersRead, Resource::user(user_ref).with_tenant(tenant.clone()),
+        UserEndpointError::ReadingDenied
+    )?;
+
+
+    let tenant_ref = tenant.unwrap_or(request.user_login_info().tenant_info.tenant_ref.clone());
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), tenant_ref)?;
+    Ok(Json(groups::internal::get_user_tenant_groups(user_id, tenant_id, request.db())))
+}
+
+/// Lists the tenants a user is a member of. Only superusers and the user themselves see all of
+/// them - anyone else only sees the tenant they are logged in to.
+#[get("/<user_ref>/tenants")]
+pub fn get_user_tenants(
+    user_ref: UserRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Tenant>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let user_id = internal::decode_user_ref(request.db(), user_ref.clone())?;
+    authorize(&mut request, Action::UsersRead, Resource::user(user_ref.clone()), UserEndpointError::ReadingDenied)?;
+
+
+    let login_info = request.user_login_info().clone();
+    let mut tenants = tenants::internal::get_user_tenants(user_id, request.db());
+    if !login_info.user.is_superuser && login_info.user.user_ref != user_ref {
+        tenants.retain(|tenant| tenant.tenant_ref == login_info.tenant_info.tenant_ref);
+    }
+    Ok(Json(tenants))
+}
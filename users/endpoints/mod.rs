@@ -1,5 +1,5 @@
 use base::requests::response::text_response::JsonBody;
-use base::{err_response, requests::UserRequest, Status};
+use base::{requests::UserRequest, Status};
 use rocket::{response::status::Created, Route};
 use rocket_contrib::json::Json;
 use serde_json::Value;
@@ -9,11 +9,18 @@ use super::internal::decode_user_ref;
 use super::structures::*;
 use super::*;
 use crate::UserAuthErrResponse;
+use crate::authz::{policy::authorize, structures::{Action, Resource}};
+use crate::audit::{self, structures::{AuditAction, AuditEvent, AuditTarget}};
 use crate::groups;
 use crate::tenants;
-use crate::utils::cache_updater::update_user_info;
+use crate::utils::{cache_updater::{revoke_user_tokens, update_user_info}, transaction::Transaction};
 
 mod login;
+mod logins;
+mod memberships;
+mod privacy;
+mod sessions;
+mod suspension;
 
 pub fn get_endpoints() -> Vec<Route> {
     routes![
@@ -23,10 +30,27 @@ pub fn get_endpoints() -> Vec<Route> {
         patch_user,
         patch_self,
         delete_user,
+        get_deleted_users,
+        restore_user,
         login::login,
         password::reset_request,
         password::reset_action,
-        password::change_password
+        password::change_password,
+        sessions::get_self_sessions,
+        sessions::get_user_sessions,
+        sessions::revoke_self_session,
+        sessions::revoke_user_session,
+        sessions::revoke_other_self_sessions,
+        sessions::revoke_user_sessions,
+        suspension::get_suspension,
+        suspension::suspend_user,
+        suspension::reactivate_user,
+        privacy::export_user_data,
+        privacy::erase_user,
+        logins::get_self_logins,
+        logins::get_user_logins,
+        memberships::get_user_groups,
+        memberships::get_user_tenants
     ]
 }
 
@@ -36,9 +60,8 @@ pub fn get_user(
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Json<User>, UserAuthErrResponse> {
     //==PERMISSION CHECK==
-    let user_id= internal::decode_user_ref(request.db(), user_ref)?;
-    let login_info = request.user_login_info().clone();
-    internal::has_read_perm(request.db(), &login_info, user_id)?;
+    let user_id= internal::decode_user_ref(request.db(), user_ref.clone())?;
+    authorize(&mut request, Action::UsersRead, Resource::user(user_ref), UserEndpointError::ReadingDenied)?;
 
     
     internal::get_user(user_id, request.db()).map(|u| Json(u))
@@ -69,17 +92,9 @@ pub fn create_user(
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Created<Json<User>>, UserAuthErrResponse> {
     //==PERMISSION CHECK==
-    if !request.user().is_superuser{
-        let info = request.user_login_info();
-        if let Some(requested_tenant) = &tenant{
-            if !(info.tenant_info.is_tenant_admin && info.tenant_info.tenant_ref == *requested_tenant){
-                return err_response!(UserEndpointError::CreationDenied);
-            }
-        }
-        else{
-            return err_response!(UserEndpointError::CreationDenied);
-        }
-    }
+    authorize(
+        &mut request, Action::UsersCreate, Resource::none().with_tenant(tenant.clone()), UserEndpointError::CreationDenied
+    )?;
 
 
     let user = user_from_json(user.0)
@@ -87,28 +102,30 @@ pub fn create_user(
     
     match tenant{
         Some(tenant_ref) => {
-            if !request.user().is_superuser {
-                return err_response!(UserEndpointError::CreationDenied);
-            }
-            request.db().start_transaction();
+            let mut tx = Transaction::start(&mut request);
 
-            let tenant_id = tenants::internal::decode_tenant_ref(request.db(), tenant_ref)?;
-            let supergroup_id = tenants::internal::get_tenant_supergroup(tenant_id, request.db())?;
+            let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), tenant_ref)?;
+            let supergroup_id = tenants::internal::get_tenant_supergroup(tenant_id, tx.db())?;
 
-            let (user, user_id) = internal::create_user(user, &mut request)?;
+            let (user, user_id) = internal::create_user(user, Some(tenant_id), &mut tx)?;
 
-            groups::internal::add_user_to_group(supergroup_id, user_id, request.db())?;
+            groups::internal::add_user_to_group(supergroup_id, user_id, tx.db())?;
 
-            request.db().commit();
+            audit::internal::record(&mut tx, AuditEvent::new(
+                AuditAction::UserCreated, Some(tenant_id), AuditTarget::User(user.user_ref.clone())
+            ));
+            tx.commit();
             Ok(Created(format!("/users/{}", user.user_ref), Some(Json(user))))
         },
         None => {
-            if !request.user().is_superuser {
-                return err_response!(UserEndpointError::CreationDenied);
-            }
-        
-            internal::create_user(user, &mut request)
-                .map(|(u,_)| Created(format!("/users/{}", u.user_ref), Some(Json(u))))
+            let mut tx = Transaction::start(&mut request);
+            let (user, _) = internal::create_user(user, None, &mut tx)?;
+            audit::internal::record(&mut tx, AuditEvent::new(
+                AuditAction::UserCreated, None, AuditTarget::User(user.user_ref.clone())
+            ));
+            tx.commit();
+
+            Ok(Created(format!("/users/{}", user.user_ref), Some(Json(user))))
         },
     }
     
@@ -119,15 +136,52 @@ pub fn delete_user(
     mut request: UserRequest<crate::ConfigType>
 ) -> Result<Status, UserAuthErrResponse>{
     //==PERMISSION CHECK==
-    if !request.user().is_superuser{
-        return err_response!(UserEndpointError::DeletionDenied);
-    }
+    authorize(&mut request, Action::UsersDelete, Resource::user(user_ref.clone()), UserEndpointError::DeletionDenied)?;
 
 
-    let user_id = decode_user_ref(request.db(), user_ref)?;
-    internal::delete_user(request.db(), user_id);
+    let user_id = decode_user_ref(request.db(), user_ref.clone())?;
 
-    // TODO: need to remove user tokens
+    // log the user out everywhere before deleting, so a failure leaves the user untouched
+    revoke_user_tokens(&request.create_http_client(), user_ref.clone(), None)?;
+    let mut tx = Transaction::start(&mut request);
+    internal::delete_sessions(user_id, None, tx.db());
+    internal::delete_user(tx.db(), user_id);
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserDeleted, None, AuditTarget::User(user_ref.clone())
+    ));
+    tx.commit();
+
+    Ok(Status::NoContent)
+}
+
+#[get("/deleted")]
+pub fn get_deleted_users(
+    mut request: UserRequest<crate::ConfigType>
+) -> Result<Json<Vec<DeletedUser>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::UsersDelete, Resource::none(), UserEndpointError::ReadingDenied)?;
+
+
+    Ok(Json(internal::get_deleted_users(request.db())))
+}
+
+#[post("/<user_ref>/restore")]
+pub fn restore_user(
+    user_ref: UserRef,
+    mut request: UserRequest<crate::ConfigType>
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    // deleted users cannot be named as the resource, as they no longer resolve
+    authorize(&mut request, Action::UsersDelete, Resource::none(), UserEndpointError::ModificationDenied)?;
+
+
+    let user_id = internal::decode_deleted_user_ref(request.db(), user_ref.clone())?;
+    let mut tx = Transaction::start(&mut request);
+    internal::restore_user(user_id, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserRestored, None, AuditTarget::User(user_ref)
+    ));
+    tx.commit();
 
     Ok(Status::NoContent)
 }
@@ -139,13 +193,24 @@ pub fn patch_user(
     mut request: UserRequest<crate::ConfigType>,
 ) -> Result<Status, UserAuthErrResponse> {
     //==PERMISSION CHECK==
-    let user_id= internal::decode_user_ref(request.db(), user_ref)?;
+    let user_id= internal::decode_user_ref(request.db(), user_ref.clone())?;
+    authorize(&mut request, Action::UsersUpdate, Resource::user(user_ref.clone()), UserEndpointError::ModificationDenied)?;
     let login_info = request.user_login_info().clone();
-    internal::has_write_perm(request.db(),&login_info, user_id)?;
 
-    let e = internal::patch_user(user_id, changes.0, request.db()).map(|_| Status::NoContent);
+    let before = internal::get_user(user_id, request.db())?;
+    let mut tx = Transaction::start(&mut request);
+    internal::patch_user(user_id, changes.0, tx.db())?;
+    let after = internal::get_user(user_id, tx.db())?;
+    let tenant_id = tenants::internal::decode_tenant_ref(
+        tx.db(), login_info.tenant_info.tenant_ref.clone()
+    ).ok();
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserUpdated, tenant_id, AuditTarget::User(user_ref)
+    ).with_change(&before, &after));
+    tx.commit();
+
     update_user_info(&request.create_http_client(), request.user_login_info().clone(), request.db())?;
-    e
+    Ok(Status::NoContent)
 }
 
 #[patch("/self", data = "<changes>")]
@@ -0,0 +1,53 @@
+use base::{log_important, requests::UserRequest, Status};
+use rocket_contrib::json::Json;
+use user_auth_structs::UserRef;
+
+use crate::{
+    UserAuthErrResponse,
+    audit::{self, structures::{AuditAction, AuditEvent, AuditTarget}},
+    users::{internal, structures::UserDataExport, UserEndpointError},
+    utils::{cache_updater::revoke_user_tokens, transaction::Transaction}
+};
+use crate::authz::{policy::authorize, structures::{Action, Resource}};
+
+#[get("/<user_ref>/export")]
+pub fn export_user_data(
+    user_ref: UserRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<UserDataExport>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::UsersPrivacy, Resource::none(), UserEndpointError::ReadingDenied)?;
+
+
+    let logger = request.logger();
+    let user_id = internal::decode_any_user_ref(request.db(), user_ref.clone())?;
+
+    log_important!("{f:yellow}Exporting all data held about user [{}]", user_ref);
+    internal::export_user_data(user_id, request.db()).map(|e| Json(e))
+}
+
+/// Irreversibly anonymizes a user to honour a right-to-erasure request
+#[post("/<user_ref>/erase")]
+pub fn erase_user(
+    user_ref: UserRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    authorize(&mut request, Action::UsersPrivacy, Resource::none(), UserEndpointError::DeletionDenied)?;
+
+
+    let logger = request.logger();
+    let user_id = internal::decode_any_user_ref(request.db(), user_ref.clone())?;
+
+    // the user may still be active, so log them out before anonymizing
+    revoke_user_tokens(&request.create_http_client(), user_ref.clone(), None)?;
+    let mut tx = Transaction::start(&mut request);
+    internal::anonymize_user(user_id, tx.db());
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserErased, None, AuditTarget::User(user_ref.clone())
+    ));
+    tx.commit();
+
+    log_important!("{f:yellow}Erased personal data of user [{}]", user_ref);
+    Ok(Status::NoContent)
+}
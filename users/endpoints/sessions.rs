@@ -0,0 +1,134 @@
+use base::{requests::UserRequest, Status};
+use rocket_contrib::json::Json;
+use user_auth_structs::UserRef;
+
+use crate::{
+    UserAuthErrResponse, tenants::{self, internal::TidInternal},
+    audit::{self, structures::{AuditAction, AuditEvent, AuditTarget}},
+    users::{internal::{self, UidInternal}, structures::Session, UserEndpointError},
+    utils::{cache_updater::revoke_token, client_info::ClientInfo, transaction::Transaction}
+};
+use crate::authz::{policy::authorize, structures::{Action, Resource}};
+
+/// Checks the caller may manage the user's sessions and login history and works out which tenant
+/// they are limited to: the user themselves and superusers see everything, tenant admins only what
+/// happened in their tenant
+pub(super) fn session_scope(request: &mut UserRequest<crate::ConfigType>, user_id: UidInternal)
+-> Result<Option<TidInternal>, UserAuthErrResponse> {
+    let user_ref = internal::encode_user_ref(request.db(), user_id);
+    authorize(request, Action::UsersSessions, Resource::user(user_ref), UserEndpointError::ModificationDenied)?;
+
+    let login_info = request.user_login_info().clone();
+
+    let caller_id = internal::decode_user_ref(request.db(), login_info.user.user_ref)?;
+    if login_info.user.is_superuser || caller_id == user_id {
+        Ok(None)
+    }
+    else {
+        tenants::internal::decode_tenant_ref(request.db(), login_info.tenant_info.tenant_ref)
+            .map(|tenant_id| Some(tenant_id))
+    }
+}
+
+#[get("/self/sessions")]
+pub fn get_self_sessions(
+    client: ClientInfo,
+    request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Session>>, UserAuthErrResponse> {
+    get_user_sessions(request.user_ref(), client, request)
+}
+
+#[get("/<user_ref>/sessions")]
+pub fn get_user_sessions(
+    user_ref: UserRef,
+    client: ClientInfo,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Session>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let user_id = internal::decode_user_ref(request.db(), user_ref)?;
+    let scope = session_scope(&mut request, user_id)?;
+
+
+    Ok(Json(
+        internal::get_sessions(user_id, scope, request.db())
+            .into_iter()
+            .map(|s| s.into_session(&client.token))
+            .collect()
+    ))
+}
+
+#[delete("/self/sessions/<session_id>")]
+pub fn revoke_self_session(
+    session_id: u64,
+    request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    revoke_user_session(request.user_ref(), session_id, request)
+}
+
+#[delete("/<user_ref>/sessions/<session_id>")]
+pub fn revoke_user_session(
+    user_ref: UserRef,
+    session_id: u64,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let user_id = internal::decode_user_ref(request.db(), user_ref)?;
+    let scope = session_scope(&mut request, user_id)?;
+
+
+    let session = internal::get_sessions(user_id, scope, request.db())
+        .into_iter()
+        .find(|s| s.id == session_id)
+        .ok_or(UserAuthErrResponse::new(UserEndpointError::SessionNonExistent))?;
+
+    revoke_token(&request.create_http_client(), session.token)?;
+
+    let mut tx = Transaction::start(&mut request);
+    internal::delete_session(session.id, tx.db());
+    let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), session.tenant_ref).ok();
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::SessionRevoked, tenant_id, AuditTarget::Session(session.id)
+    ));
+    tx.commit();
+
+    Ok(Status::NoContent)
+}
+
+/// Signs the caller out of every session except the one making this request
+#[delete("/self/sessions")]
+pub fn revoke_other_self_sessions(
+    client: ClientInfo,
+    request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    revoke_user_sessions(request.user_ref(), client, request)
+}
+
+#[delete("/<user_ref>/sessions")]
+pub fn revoke_user_sessions(
+    user_ref: UserRef,
+    client: ClientInfo,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let user_id = internal::decode_user_ref(request.db(), user_ref)?;
+    let scope = session_scope(&mut request, user_id)?;
+
+
+    let http_client = request.create_http_client();
+    for session in internal::get_sessions(user_id, scope, request.db()) {
+        if client.token.as_ref() == Some(&session.token) {
+            continue;
+        }
+        revoke_token(&http_client, session.token)?;
+
+        let mut tx = Transaction::start(&mut request);
+        internal::delete_session(session.id, tx.db());
+        let tenant_id = tenants::internal::decode_tenant_ref(tx.db(), session.tenant_ref).ok();
+        audit::internal::record(&mut tx, AuditEvent::new(
+            AuditAction::SessionRevoked, tenant_id, AuditTarget::Session(session.id)
+        ));
+        tx.commit();
+    }
+
+    Ok(Status::NoContent)
+}
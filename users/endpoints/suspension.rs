@@ -0,0 +1,107 @@
+use base::{requests::{response::text_response::JsonBody, UserRequest}, Status};
+use rocket_contrib::json::Json;
+use user_auth_structs::{TenantRef, UserRef};
+
+use crate::{
+    UserAuthErrResponse, tenants::{self, internal::TidInternal},
+    audit::{self, structures::{AuditAction, AuditEvent, AuditTarget}},
+    users::{
+        internal::{self, UidInternal},
+        structures::{validate_suspend_user, SuspendUser, Suspension},
+        UserEndpointError
+    },
+    utils::{cache_updater::revoke_user_tokens, transaction::Transaction}
+};
+use crate::authz::{policy::authorize, structures::{Action, Resource}};
+
+/// Checks the caller may suspend or reactivate the user, returning the internal tenant id if the
+/// suspension only concerns one tenant
+fn check_suspension_perm(
+    request: &mut UserRequest<crate::ConfigType>,
+    action: Action,
+    user_id: UidInternal,
+    tenant: &Option<TenantRef>,
+) -> Result<Option<TidInternal>, UserAuthErrResponse> {
+    let user_ref = internal::encode_user_ref(request.db(), user_id);
+    authorize(
+        request, action, Resource::user(user_ref).with_tenant(tenant.clone()),
+        UserEndpointError::SuspensionDenied
+    )?;
+
+    match tenant {
+        Some(tenant_ref) => tenants::internal::decode_tenant_ref(request.db(), tenant_ref.clone())
+            .map(|tenant_id| Some(tenant_id)),
+        None => Ok(None),
+    }
+}
+
+#[get("/<user_ref>/suspension?<tenant>")]
+pub fn get_suspension(
+    user_ref: UserRef,
+    tenant: Option<TenantRef>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Option<Suspension>>, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let user_id = internal::decode_user_ref(request.db(), user_ref.clone())?;
+    authorize(&mut request, Action::UsersRead, Resource::user(user_ref), UserEndpointError::ReadingDenied)?;
+
+
+    let tenant_id = match tenant {
+        Some(tenant_ref) => Some(tenants::internal::decode_tenant_ref(request.db(), tenant_ref)?),
+        None => None,
+    };
+    Ok(Json(internal::get_suspension(user_id, tenant_id, request.db())))
+}
+
+#[post("/<user_ref>/suspend?<tenant>", data = "<suspension>")]
+pub fn suspend_user(
+    user_ref: UserRef,
+    tenant: Option<TenantRef>,
+    suspension: JsonBody<SuspendUser>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let user_id = internal::decode_user_ref(request.db(), user_ref.clone())?;
+    let tenant_id = check_suspension_perm(&mut request, Action::UsersSuspend, user_id, &tenant)?;
+
+
+    let suspension = suspension.0;
+    validate_suspend_user(&suspension)
+        .map_err(|e| UserAuthErrResponse::new(UserEndpointError::InvalidField(e)))?;
+
+    // log the user out first so a failure leaves them able to carry on as before
+    revoke_user_tokens(&request.create_http_client(), user_ref.clone(), tenant)?;
+    let mut tx = Transaction::start(&mut request);
+    internal::delete_sessions(user_id, tenant_id, tx.db());
+    internal::suspend_user(user_id, tenant_id, &suspension, tx.db());
+
+    let after = internal::get_suspension(user_id, tenant_id, tx.db());
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserSuspended, tenant_id, AuditTarget::User(user_ref)
+    ).with_change(&None, &after));
+    tx.commit();
+
+    Ok(Status::NoContent)
+}
+
+#[post("/<user_ref>/reactivate?<tenant>")]
+pub fn reactivate_user(
+    user_ref: UserRef,
+    tenant: Option<TenantRef>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==PERMISSION CHECK==
+    let user_id = internal::decode_user_ref(request.db(), user_ref.clone())?;
+    let tenant_id = check_suspension_perm(&mut request, Action::UsersReactivate, user_id, &tenant)?;
+
+
+    let before = internal::get_suspension(user_id, tenant_id, request.db());
+    let mut tx = Transaction::start(&mut request);
+    internal::reactivate_user(user_id, tenant_id, tx.db())?;
+    audit::internal::record(&mut tx, AuditEvent::new(
+        AuditAction::UserReactivated, tenant_id, AuditTarget::User(user_ref)
+    ).with_change(&before, &None));
+    tx.commit();
+
+    Ok(Status::NoContent)
+}
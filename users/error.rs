@@ -15,6 +15,10 @@ pub enum UserEndpointError {
     FailedToSendEmail,
     InvalidOrExpiredPasswordResetToken(String),
     UseOtherEndpoint(&'static str),
+    SessionNonExistent,
+    UserSuspended,
+    SuspensionDenied,
+    UserNotSuspended,
 }
 
 #[derive(Debug)]
@@ -24,6 +28,8 @@ pub enum InvalidField {
     TooShort { field: &'static str, min: usize },
     Empty(&'static str),
     InvalidTimezone,
+    NotInFuture(&'static str),
+    OutOfRange { field: &'static str, min: u64, max: u64 },
 }
 
 impl Display for InvalidField {
@@ -38,6 +44,10 @@ impl Display for InvalidField {
             }
             InvalidField::Empty(field) => write!(f, "Field cannot be empty: {}", field),
             InvalidField::InvalidTimezone => write!(f, "Invalid timezone"),
+            InvalidField::NotInFuture(field) => write!(f, "Field {} must be in the future", field),
+            InvalidField::OutOfRange { field, min, max } => {
+                write!(f, "Field {} is out of range (min = {}, max = {})", field, min, max)
+            }
         }
     }
 }
@@ -65,6 +75,10 @@ impl Display for UserEndpointError {
                 "Please use the other endpoint: {}", endpoint
             ),
             Self::DeletionDenied => write!(f, "You do not have permission to delete that user!"),
+            Self::SessionNonExistent => write!(f, "Session does not exist"),
+            Self::UserSuspended => write!(f, "User account is suspended"),
+            Self::SuspensionDenied => write!(f, "You do not have permission to suspend that user"),
+            Self::UserNotSuspended => write!(f, "User is not suspended"),
         }
     }
 }
@@ -84,6 +98,10 @@ impl MicroserviceError for UserEndpointError {
             Self::InvalidOrExpiredPasswordResetToken(_) => 0x0009,
             Self::UseOtherEndpoint(_)                   => 0x000A,
             Self::DeletionDenied                        => 0x000B,
+            Self::SessionNonExistent                    => 0x000C,
+            Self::UserSuspended                         => 0x000D,
+            Self::SuspensionDenied                      => 0x000E,
+            Self::UserNotSuspended                      => 0x000F,
         }
     }
 
@@ -105,6 +123,10 @@ impl MicroserviceError for UserEndpointError {
                 format!("Please use {} instead", endpoint)
             }
             Self::DeletionDenied => format!("Permission denied"),
+            Self::SessionNonExistent => format!("Session does not exist"),
+            Self::UserSuspended => format!("Account suspended"),
+            Self::SuspensionDenied => format!("Permission denied"),
+            Self::UserNotSuspended => format!("User is not suspended"),
         }
     }
 
@@ -124,6 +146,10 @@ impl MicroserviceError for UserEndpointError {
             ),
             Self::UseOtherEndpoint(endpoint) => format!("Use {} instead", endpoint),
             Self::DeletionDenied => format!("Deleting user denied"),
+            Self::SessionNonExistent => format!("Session does not exist"),
+            Self::UserSuspended => format!("User is suspended, in general or for this tenant"),
+            Self::SuspensionDenied => format!("Suspending or reactivating user denied"),
+            Self::UserNotSuspended => format!("User is not suspended"),
         }
     }
 
@@ -141,6 +167,10 @@ impl MicroserviceError for UserEndpointError {
             Self::InvalidOrExpiredPasswordResetToken(_) => Status::BadRequest,
             Self::UseOtherEndpoint(_)                   => Status::BadRequest,
             Self::DeletionDenied                        => Status::Forbidden,
+            Self::SessionNonExistent                    => Status::NotFound,
+            Self::UserSuspended                         => Status::Forbidden,
+            Self::SuspensionDenied                      => Status::Forbidden,
+            Self::UserNotSuspended                      => Status::BadRequest,
         }
     }
 
@@ -1,15 +1,23 @@
+use std::str::FromStr;
+use base::db::error_handling::DatabaseErrHandler;
 use base::logger::LogError;
 use base::references::InternalReference;
 use base::{err_response, requests::UserRequest, sql, DbConn};
-use serde_json::Value;
+use sdk_base::Client;
+use serde_json::{json, Value};
 use cached::proc_macro::cached;
-use user_auth_structs::UserRef;
+use user_auth_structs::{TenantRef, UserRef};
 use crate::{
-    UserAuthError, UserAuthErrResponse, tenants,
-    users::structures::user_from_json,
-    utils::hashing
+    UserAuthError, UserAuthErrResponse, groups, tenants,
+    audit::{self, structures::{AuditAction, AuditEvent, AuditTarget}},
+    events::{self, structures::EventType},
+    tenants::internal::TidInternal,
+    users::structures::{user_from_json, DeletedUser, LoginAttempt, LoginOutcome, RawSession, SuspendUser, Suspension, UserDataExport},
+    utils::{
+        cache_updater::revoke_user_tokens, client_info::ClientInfo, hashing, time,
+        pagination::{like_prefix, like_suffix, ListQuery, Page, SortKey, StatusFilter}, transaction::Transaction
+    }
 };
-use token_auth_structs::LoggedInUser;
 use super::{structures::CreateUser, User, UserEndpointError};
 
 pub type UidInternal = u64;
@@ -30,50 +38,129 @@ pub fn decode_user_ref(db: &mut DbConn, reference: UserRef) -> Result<UidInterna
         .ok_or(UserAuthErrResponse::new(UserEndpointError::UserNonExistent))
 }
 
-/// Checks if user has the read permission over another
-pub fn has_read_perm(db: &mut DbConn, u1: &LoggedInUser, u2_id: UidInternal) -> Result<(), UserAuthErrResponse> {
-    let u1_id = decode_user_ref(db, u1.user.user_ref.clone()).unwrap();
-    if u1_id == u2_id{
-        return Ok(());
-    }
-    if u1.user.is_superuser {
-        return Ok(());
-    }
+#[cached(size=512, convert="{internal}", key="UidInternal")]
+/// Converts an internal user id to an external user id
+pub fn encode_user_ref(db: &mut DbConn, internal: UidInternal) -> UserRef {
+    let ret: (InternalReference<UserRef>,) = db.query_first(&sql!("SELECT user_ref FROM auth_users WHERE id {=}", internal)).ok_or("Unknown user internal ID").db_expect(&db.err_handler());
+    ret.0.inner()
+}
 
+/// Hash id given to erased users - no hash algorithm uses it, so their password can never match
+pub const ERASED_PASSWORD_HASH_ID: u16 = u16::MAX;
 
-    let u2_tenants = tenants::internal::get_user_tenant_refs(u2_id, db);
-    if u2_tenants.contains(&u1.tenant_info.tenant_ref){
-        Ok(())
-    }
-    else{
-        err_response!(UserEndpointError::ReadingDenied)
-    }
+/// Soft deletes a user - call inside a transaction, as this also publishes an event
+pub fn delete_user(db: &mut DbConn, user_id: UidInternal){
+    let user_ref = encode_user_ref(db, user_id);
+    events::internal::publish_for_user(db, user_id, EventType::UserDeleted, json!({ "user": user_ref }));
+    db.query_drop(
+        &sql!("UPDATE auth_users SET is_deleted = 1, deleted_at = UNIX_TIMESTAMP() WHERE id = {}", user_id)
+    );
+}
 
+/// Converts an external user id to an internal user id for a deleted user that has not yet been erased
+pub fn decode_deleted_user_ref(db: &mut DbConn, reference: UserRef) -> Result<UidInternal, UserAuthErrResponse> {
+    db.query_first(&sql!("
+        SELECT id FROM auth_users
+        WHERE user_ref {=} AND is_deleted = 1 AND erased_at IS NULL", InternalReference::new(reference)
+    )).map(|(id,): (UidInternal,)| id)
+        .ok_or(UserAuthErrResponse::new(UserEndpointError::UserNonExistent))
 }
 
-/// Checks if user has the write permission over another
-pub fn has_write_perm(db: &mut DbConn, u1: &LoggedInUser, u2_id: UidInternal) -> Result<(), UserAuthErrResponse> {
-    let u1_id = decode_user_ref(db, u1.user.user_ref.clone()).unwrap();
-    if u1_id == u2_id{
-        return Ok(());
-    }
-    if u1.user.is_superuser {
-        return Ok(());
-    }
+/// Retrieves all deleted users that have not yet been erased
+pub fn get_deleted_users(db: &mut DbConn) -> Vec<DeletedUser> {
+    db.query_map(&sql!("
+        SELECT user_ref, username, firstname, lastname, email, timezone, is_superuser, deleted_at
+        FROM auth_users
+        WHERE is_deleted = 1 AND erased_at IS NULL
+        ORDER BY deleted_at DESC
+    "),
+        |(user_ref, username, firstname, lastname, email, timezone, is_superuser, deleted_at):
+        (InternalReference<UserRef>, String, String, String, Option<String>, String, bool, u64)| DeletedUser {
+            user: User {
+                user_ref: user_ref.inner(),
+                username, firstname, lastname, email, timezone, is_superuser
+            },
+            deleted_at
+        }
+    )
+}
 
-    let u2_tenants = tenants::internal::get_user_tenant_refs(u2_id, db);
-    if u1.tenant_info.is_tenant_admin && u2_tenants.contains(&u1.tenant_info.tenant_ref){
-        Ok(())
-    }
-    else{
-        err_response!(UserEndpointError::ModificationDenied)
+/// Undeletes a user, as long as nobody else has taken their username in the meantime
+pub fn restore_user(user_id: UidInternal, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    let (username,): (String,) = db.query_first(&sql!(
+        "SELECT username FROM auth_users WHERE id {=}", user_id
+    )).ok_or(UserAuthErrResponse::new(UserEndpointError::UserNonExistent))?;
+
+    let taken = db.query_count(&sql!(
+        "SELECT COUNT(*) FROM auth_users WHERE username {=} AND is_deleted = 0 AND id != {}",
+        username, user_id
+    )) > 0;
+    if taken {
+        return err_response!(UserEndpointError::UsernameTaken);
     }
+
+    db.query_drop(&sql!(
+        "UPDATE auth_users SET is_deleted = 0, deleted_at = NULL WHERE id = {}", user_id
+    ));
+    Ok(())
 }
 
-pub fn delete_user(db: &mut DbConn, user_id: UidInternal){
-    db.query_drop(
-        &sql!("UPDATE auth_users SET is_deleted = 1 WHERE id = {}", user_id)
+/// Irreversibly removes a user's personal data, keeping the row itself so that anything
+/// referring to the user id stays valid. Also removes their memberships and sessions, and strips
+/// their login history down to when and how each attempt went. Call inside a transaction, along
+/// with the audit entry recording the erasure.
+pub fn anonymize_user(user_id: UidInternal, db: &mut DbConn) {
+    // attempts that could not be tied to a user carry only the username, so match on that as well
+    db.query_drop(&sql!("
+        UPDATE auth_login_history SET
+            username = CONCAT('erased-', {}),
+            client_ip = NULL,
+            user_agent = NULL
+        WHERE
+            user_id = {} OR
+            (user_id IS NULL AND username = (SELECT username FROM auth_users WHERE id = {}))
+    ", user_id, user_id, user_id));
+    db.query_drop(&sql!("
+        UPDATE auth_users SET
+            username = CONCAT('erased-', id),
+            password = '',
+            password_hash_id = {},
+            firstname = '',
+            lastname = '',
+            email = NULL,
+            suspension_reason = NULL,
+            is_deleted = 1,
+            deleted_at = COALESCE(deleted_at, UNIX_TIMESTAMP()),
+            erased_at = UNIX_TIMESTAMP()
+        WHERE id = {}
+    ", ERASED_PASSWORD_HASH_ID, user_id));
+    db.query_drop(&sql!("DELETE FROM auth_usergroups WHERE user_id {=}", user_id));
+    db.query_drop(&sql!("DELETE FROM auth_tenant_suspensions WHERE user_id {=}", user_id));
+    delete_sessions(user_id, None, db);
+}
+
+/// Anonymizes every user that has been deleted for longer than the retention period, returning
+/// how many were purged
+pub fn purge_deleted_users(retention_days: u64, db: &mut DbConn) -> usize {
+    let users = db.query_map(&sql!("
+        SELECT id, user_ref FROM auth_users
+        WHERE
+            is_deleted = 1 AND
+            erased_at IS NULL AND
+            deleted_at < UNIX_TIMESTAMP() - {}
+    ", retention_days * 24 * 60 * 60),
+        |(id, user_ref): (UidInternal, InternalReference<UserRef>)| (id, user_ref.inner())
     );
+
+    for (user_id, user_ref) in &users {
+        let mut tx = Transaction::start(db);
+        anonymize_user(*user_id, &mut tx);
+        audit::internal::insert_entry(&mut tx, None, None, AuditEvent::new(
+            AuditAction::UserErased, None, AuditTarget::User(user_ref.clone())
+        ));
+        tx.commit();
+    }
+    users.len()
 }
 
 /// Checks if a username is taken
@@ -114,19 +201,28 @@ pub fn get_user_sec_info(username: &String, db: &mut DbConn)
     )
 }
 
-/// Update a user's hashed password and hash id
+/// Update a user's hashed password and hash id, auditing the change as made by the user - they
+/// are the only one who can change it, knowing either the old password or a reset token. Call
+/// inside a transaction, along with the audit entry.
 pub fn update_password(user_id: u64, new_hash: &String, hash_id: u16, db: &mut DbConn) {
     db.query_drop(&sql!("
         UPDATE auth_users
         SET password = {}, password_hash_id = {} 
         WHERE id {=}",
         new_hash, hash_id, user_id
-    ))
+    ));
+
+    let user_ref = encode_user_ref(db, user_id);
+    audit::internal::insert_entry(db, Some(user_id), None, AuditEvent::new(
+        AuditAction::PasswordChanged, None, AuditTarget::User(user_ref)
+    ));
 }
 
-/// Creates a user - doesn't check permissions
+/// Creates a user - doesn't check permissions. The event published is attributed to the tenant
+/// the user is being created in, so call inside the transaction that adds them to it.
 pub fn create_user(
     user: CreateUser,
+    tenant_id: Option<TidInternal>,
     request: &mut UserRequest<crate::ConfigType>,
 ) -> Result<(User, UidInternal), UserAuthErrResponse> {
     let hash_id = request.specific_config().default_password_hash_id;
@@ -147,7 +243,7 @@ pub fn create_user(
             user_ref, user.username, hashed_password, hash_id, user.firstname, user.lastname, user.email, user.timezone, 0
         ));
 
-        Ok((User {
+        let user = User {
             user_ref: user_ref.inner(),
             firstname: user.firstname.clone(),
             lastname: user.lastname.clone(),
@@ -155,13 +251,17 @@ pub fn create_user(
             email: user.email.clone(),
             timezone: user.timezone.clone(),
             is_superuser: false,
-        }, user_id))
+        };
+        events::internal::publish(request.db(), tenant_id, EventType::UserCreated, json!({ "user": user }));
+
+        Ok((user, user_id))
     } else {
         err_response!(UserEndpointError::UsernameTaken)
     }
 }
 
-/// Updates user values - doesn't check permissions
+/// Updates user values - doesn't check permissions. Call inside a transaction, as this also
+/// publishes an event
 pub fn patch_user(id: UidInternal, changes: Value, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
     let user = get_user(id, db)?;
 
@@ -203,5 +303,399 @@ pub fn patch_user(id: UidInternal, changes: Value, db: &mut DbConn) -> Result<()
         id
     ));
 
+    let user = get_user(id, db)?;
+    events::internal::publish_for_user(db, id, EventType::UserUpdated, json!({ "user": user }));
+
+    Ok(())
+}
+
+/// Records a newly created token as a session of the user, expiring along with the token. The
+/// token is kept as the token server revokes tokens by the token itself.
+pub fn create_session(
+    user_id: UidInternal,
+    tenant_id: TidInternal,
+    token: &String,
+    lifetime_secs: u64,
+    client: &ClientInfo,
+    db: &mut DbConn
+) -> u64 {
+    db.query_insert(&sql!(
+        "INSERT INTO auth_sessions
+            (user_id, tenant_id, token, created_at, expires_at, client_ip, user_agent)
+        VALUES ({}, {}, {}, UNIX_TIMESTAMP(), UNIX_TIMESTAMP() + {}, {}, {})",
+        user_id, tenant_id, token, lifetime_secs, client.ip, client.user_agent
+    ))
+}
+
+/// Retrieves a user's sessions that have not yet expired, optionally only those for one tenant
+pub fn get_sessions(user_id: UidInternal, tenant_id: Option<TidInternal>, db: &mut DbConn)
+-> Vec<RawSession> {
+    let query = match tenant_id {
+        Some(tenant_id) => sql!("
+            SELECT auth_sessions.id, tenant_ref, token, created_at, client_ip, user_agent
+            FROM auth_sessions, auth_tenants
+            WHERE
+                auth_sessions.tenant_id = auth_tenants.id AND
+                auth_sessions.user_id {=} AND
+                auth_sessions.tenant_id {=} AND
+                auth_sessions.expires_at > UNIX_TIMESTAMP()
+            ORDER BY created_at DESC
+        ", user_id, tenant_id),
+        None => sql!("
+            SELECT auth_sessions.id, tenant_ref, token, created_at, client_ip, user_agent
+            FROM auth_sessions, auth_tenants
+            WHERE
+                auth_sessions.tenant_id = auth_tenants.id AND
+                auth_sessions.user_id {=} AND
+                auth_sessions.expires_at > UNIX_TIMESTAMP()
+            ORDER BY created_at DESC
+        ", user_id),
+    };
+
+    db.query_map(&query,
+        |(id, tenant_ref, token, created_at, client_ip, user_agent):
+        (u64, InternalReference<TenantRef>, String, u64, Option<String>, Option<String>)| RawSession {
+            id,
+            tenant_ref: tenant_ref.inner(),
+            token, created_at, client_ip, user_agent
+        }
+    )
+}
+
+/// Removes the records of sessions whose token has expired, returning how many were removed
+pub fn purge_expired_sessions(db: &mut DbConn) -> usize {
+    // counted and removed against the same cutoff so the count matches what was removed
+    let now = time::now();
+    let expired = db.query_count(&sql!("SELECT COUNT(*) FROM auth_sessions WHERE expires_at <= {}", now));
+    db.query_drop(&sql!("DELETE FROM auth_sessions WHERE expires_at <= {}", now));
+    expired as usize
+}
+
+/// Removes a single session record
+pub fn delete_session(session_id: u64, db: &mut DbConn) {
+    db.query_drop(&sql!("DELETE FROM auth_sessions WHERE id {=}", session_id));
+}
+
+/// Removes a user's session records, optionally only those for one tenant
+pub fn delete_sessions(user_id: UidInternal, tenant_id: Option<TidInternal>, db: &mut DbConn) {
+    match tenant_id {
+        Some(tenant_id) => db.query_drop(&sql!(
+            "DELETE FROM auth_sessions WHERE user_id {=} AND tenant_id {=}", user_id, tenant_id
+        )),
+        None => db.query_drop(&sql!(
+            "DELETE FROM auth_sessions WHERE user_id {=}", user_id
+        )),
+    }
+}
+
+/// Records that a user's tokens for a tenant are to be revoked, so that the revocation is retried
+/// if it fails. Call inside the transaction of the change that requires it.
+pub fn queue_token_revocation(user_ref: &UserRef, tenant_ref: &TenantRef, db: &mut DbConn) {
+    db.query_drop(&sql!(
+        "INSERT IGNORE INTO auth_pending_revocations (user_ref, tenant_ref, queued_at) VALUES ({}, {}, UNIX_TIMESTAMP())",
+        InternalReference::new(user_ref.clone()), InternalReference::new(tenant_ref.clone())
+    ));
+}
+
+pub fn clear_token_revocation(user_ref: &UserRef, tenant_ref: &TenantRef, db: &mut DbConn) {
+    db.query_drop(&sql!(
+        "DELETE FROM auth_pending_revocations WHERE user_ref {=} AND tenant_ref {=}",
+        InternalReference::new(user_ref.clone()), InternalReference::new(tenant_ref.clone())
+    ));
+}
+
+/// Tries the queued token revocations again, returning how many went through
+pub fn retry_token_revocations(client: &Client, db: &mut DbConn) -> usize {
+    let queued = db.query_map(
+        "SELECT user_ref, tenant_ref FROM auth_pending_revocations ORDER BY queued_at",
+        |(user_ref, tenant_ref): (InternalReference<UserRef>, InternalReference<TenantRef>)|
+            (user_ref.inner(), tenant_ref.inner())
+    );
+
+    let mut revoked = 0;
+    for (user_ref, tenant_ref) in queued {
+        if revoke_user_tokens(client, user_ref.clone(), Some(tenant_ref.clone())).is_ok() {
+            clear_token_revocation(&user_ref, &tenant_ref, db);
+            revoked += 1;
+        }
+    }
+    revoked
+}
+
+/// Retrieves a user's active suspension - of the whole account if no tenant is given, otherwise
+/// of their membership of that tenant. Suspensions past their expiry are ignored.
+pub fn get_suspension(user_id: UidInternal, tenant_id: Option<TidInternal>, db: &mut DbConn)
+-> Option<Suspension> {
+    match tenant_id {
+        Some(tenant_id) => db.query_first(&sql!("
+            SELECT tenant_ref, reason, suspended_at, suspended_until
+            FROM auth_tenant_suspensions, auth_tenants
+            WHERE
+                auth_tenant_suspensions.tenant_id = auth_tenants.id AND
+                user_id {=} AND
+                tenant_id {=} AND
+                (suspended_until IS NULL OR suspended_until > UNIX_TIMESTAMP())
+        ", user_id, tenant_id)).map(|(tenant_ref, reason, suspended_at, suspended_until):
+            (InternalReference<TenantRef>, String, u64, Option<u64>)| Suspension {
+                tenant_ref: Some(tenant_ref.inner()),
+                reason, suspended_at, suspended_until
+            }
+        ),
+        None => db.query_first(&sql!("
+            SELECT suspension_reason, suspended_at, suspended_until
+            FROM auth_users
+            WHERE
+                id {=} AND
+                suspended_at IS NOT NULL AND
+                (suspended_until IS NULL OR suspended_until > UNIX_TIMESTAMP())
+        ", user_id)).map(|(reason, suspended_at, suspended_until): (String, u64, Option<u64>)|
+            Suspension {
+                tenant_ref: None,
+                reason, suspended_at, suspended_until
+            }
+        ),
+    }
+}
+
+/// Suspends a user's account, or only their membership of a tenant - doesn't check permissions
+pub fn suspend_user(
+    user_id: UidInternal,
+    tenant_id: Option<TidInternal>,
+    suspension: &SuspendUser,
+    db: &mut DbConn
+) {
+    match tenant_id {
+        Some(tenant_id) => {
+            db.query_drop(&sql!(
+                "DELETE FROM auth_tenant_suspensions WHERE user_id {=} AND tenant_id {=}",
+                user_id, tenant_id
+            ));
+            db.query_drop(&sql!(
+                "INSERT INTO auth_tenant_suspensions (user_id, tenant_id, reason, suspended_at, suspended_until)
+                VALUES ({}, {}, {}, UNIX_TIMESTAMP(), {})",
+                user_id, tenant_id, suspension.reason, suspension.until
+            ));
+        },
+        None => db.query_drop(&sql!("
+            UPDATE auth_users SET
+                suspension_reason = {},
+                suspended_at = UNIX_TIMESTAMP(),
+                suspended_until = {}
+            WHERE id = {}
+        ", suspension.reason, suspension.until, user_id)),
+    }
+}
+
+/// Lifts a user's suspension - doesn't check permissions
+pub fn reactivate_user(user_id: UidInternal, tenant_id: Option<TidInternal>, db: &mut DbConn)
+-> Result<(), UserAuthErrResponse> {
+    if get_suspension(user_id, tenant_id, db).is_none() {
+        return err_response!(UserEndpointError::UserNotSuspended);
+    }
+
+    match tenant_id {
+        Some(tenant_id) => db.query_drop(&sql!(
+            "DELETE FROM auth_tenant_suspensions WHERE user_id {=} AND tenant_id {=}",
+            user_id, tenant_id
+        )),
+        None => db.query_drop(&sql!("
+            UPDATE auth_users SET
+                suspension_reason = NULL,
+                suspended_at = NULL,
+                suspended_until = NULL
+            WHERE id = {}
+        ", user_id)),
+    }
+
     Ok(())
 }
+
+/// Converts an external user id to an internal user id, whether or not the user is deleted, as
+/// long as they have not been erased
+pub fn decode_any_user_ref(db: &mut DbConn, reference: UserRef) -> Result<UidInternal, UserAuthErrResponse> {
+    decode_user_ref(db, reference.clone())
+        .or_else(|_| decode_deleted_user_ref(db, reference))
+}
+
+/// Retrieves every suspension recorded against a user, including expired ones
+pub fn get_suspensions(user_id: UidInternal, db: &mut DbConn) -> Vec<Suspension> {
+    let mut suspensions: Vec<Suspension> = db.query_first(&sql!("
+        SELECT suspension_reason, suspended_at, suspended_until
+        FROM auth_users
+        WHERE id {=} AND suspended_at IS NOT NULL
+    ", user_id)).map(|(reason, suspended_at, suspended_until): (String, u64, Option<u64>)|
+        Suspension {
+            tenant_ref: None,
+            reason, suspended_at, suspended_until
+        }
+    ).into_iter().collect();
+
+    suspensions.extend(db.query_map(&sql!("
+        SELECT tenant_ref, reason, suspended_at, suspended_until
+        FROM auth_tenant_suspensions, auth_tenants
+        WHERE
+            auth_tenant_suspensions.tenant_id = auth_tenants.id AND
+            user_id {=}
+    ", user_id), |(tenant_ref, reason, suspended_at, suspended_until):
+        (InternalReference<TenantRef>, String, u64, Option<u64>)| Suspension {
+            tenant_ref: Some(tenant_ref.inner()),
+            reason, suspended_at, suspended_until
+        }
+    ));
+
+    suspensions
+}
+
+/// Gathers everything held about a user, deleted or not - doesn't check permissions
+pub fn export_user_data(user_id: UidInternal, db: &mut DbConn) -> Result<UserDataExport, UserAuthErrResponse> {
+    let (profile, deleted_at) = db.query_first(&sql!("
+        SELECT user_ref, username, firstname, lastname, email, timezone, is_superuser, deleted_at
+        FROM auth_users
+        WHERE id {=} AND erased_at IS NULL", user_id
+    )).map(|(user_ref, username, firstname, lastname, email, timezone, is_superuser, deleted_at):
+        (InternalReference<UserRef>, String, String, String, Option<String>, String, bool, Option<u64>)| (
+            User {
+                user_ref: user_ref.inner(),
+                username, firstname, lastname, email, timezone, is_superuser
+            },
+            deleted_at
+        )
+    ).ok_or(UserAuthErrResponse::new(UserEndpointError::UserNonExistent))?;
+
+    let audit_entries = audit::internal::get_user_entries(db, user_id, &profile.user_ref);
+
+    Ok(UserDataExport {
+        profile,
+        deleted_at,
+        tenants: tenants::internal::get_all_user_tenants(user_id, db),
+        groups: groups::internal::get_user_groups(user_id, db),
+        suspensions: get_suspensions(user_id, db),
+        sessions: get_sessions(user_id, None, db)
+            .into_iter()
+            .map(|s| s.into_session(&None))
+            .collect(),
+        login_history: get_login_history(user_id, None, None, u64::MAX, db),
+        audit_entries,
+    })
+}
+
+/// Records a login attempt. `user_id` is only known if the username exists, and `tenant_id` only
+/// if the attempt got as far as choosing a tenant.
+pub fn record_login(
+    user_id: Option<UidInternal>,
+    username: &String,
+    tenant_id: Option<TidInternal>,
+    outcome: LoginOutcome,
+    client: &ClientInfo,
+    db: &mut DbConn
+) {
+    db.query_drop(&sql!(
+        "INSERT INTO auth_login_history (user_id, username, tenant_id, outcome, client_ip, user_agent, created_at)
+        VALUES ({}, {}, {}, {}, {}, {}, UNIX_TIMESTAMP())",
+        user_id, username, tenant_id, outcome, client.ip, client.user_agent
+    ));
+
+    if let (Some(user_id), LoginOutcome::Success) = (user_id, outcome) {
+        db.query_drop(&sql!(
+            "UPDATE auth_users SET last_login_at = UNIX_TIMESTAMP() WHERE id = {}", user_id
+        ));
+    }
+}
+
+/// Retrieves a page of a user's login attempts, newest first, optionally only for one tenant.
+/// Pass the id of the last attempt received as `before` to get the next page.
+pub fn get_login_history(
+    user_id: UidInternal,
+    tenant_id: Option<TidInternal>,
+    before: Option<u64>,
+    limit: u64,
+    db: &mut DbConn
+) -> Vec<LoginAttempt> {
+    let mut conditions = vec![sql!("auth_login_history.user_id {=}", user_id)];
+    if let Some(tenant_id) = tenant_id {
+        conditions.push(sql!("auth_login_history.tenant_id {=}", tenant_id));
+    }
+    if let Some(before) = before {
+        conditions.push(sql!("auth_login_history.id < {}", before));
+    }
+
+    let db_err = db.err_handler();
+    db.query_map(&format!("
+        SELECT auth_login_history.id, tenant_ref, outcome, created_at, client_ip, user_agent
+        FROM auth_login_history
+            LEFT JOIN auth_tenants ON auth_tenants.id = auth_login_history.tenant_id
+        WHERE {}
+        ORDER BY auth_login_history.id DESC
+        LIMIT {}
+    ", conditions.join(" AND "), limit),
+        |(id, tenant_ref, outcome, created_at, client_ip, user_agent):
+        (u64, Option<InternalReference<TenantRef>>, String, u64, Option<String>, Option<String>)| LoginAttempt {
+            id,
+            tenant_ref: tenant_ref.map(|t| t.inner()),
+            outcome: LoginOutcome::from_str(&outcome).db_expect(&db_err),
+            created_at, client_ip, user_agent
+        }
+    )
+}
+
+/// Users whose account, or membership of the tenant, is currently suspended
+fn suspended_condition(tenant_id: TidInternal) -> String {
+    sql!("(
+        (auth_users.suspended_at IS NOT NULL AND
+            (auth_users.suspended_until IS NULL OR auth_users.suspended_until > UNIX_TIMESTAMP())) OR
+        EXISTS (
+            SELECT 1 FROM auth_tenant_suspensions
+            WHERE
+                auth_tenant_suspensions.user_id = auth_users.id AND
+                auth_tenant_suspensions.tenant_id {=} AND
+                (auth_tenant_suspensions.suspended_until IS NULL OR auth_tenant_suspensions.suspended_until > UNIX_TIMESTAMP())
+        )
+    )", tenant_id)
+}
+
+/// Retrieves a page of the users matching `membership`, a condition on `auth_users`, and the
+/// query's filters. Suspensions count if they are of the account or of the tenant's membership.
+pub fn get_user_page(membership: String, tenant_id: TidInternal, query: &ListQuery, db: &mut DbConn)
+-> Page<(UidInternal, User)> {
+    let mut conditions = vec![String::from("auth_users.is_deleted = 0"), membership];
+    if let Some(name) = &query.name {
+        let pattern = like_prefix(name);
+        conditions.push(sql!(
+            "(auth_users.username LIKE {} OR auth_users.firstname LIKE {} OR auth_users.lastname LIKE {})",
+            pattern, pattern, pattern
+        ));
+    }
+    if let Some(domain) = &query.email_domain {
+        conditions.push(sql!("auth_users.email LIKE {}", like_suffix(&format!("@{}", domain))));
+    }
+    match query.status {
+        Some(StatusFilter::Suspended) => conditions.push(suspended_condition(tenant_id)),
+        Some(StatusFilter::Active) => conditions.push(format!("NOT {}", suspended_condition(tenant_id))),
+        None => (),
+    }
+    let total = db.query_count(&format!(
+        "SELECT COUNT(*) FROM auth_users WHERE {}", conditions.join(" AND ")
+    )) as u64;
+
+    let column = match query.sort.key {
+        SortKey::Lastname => "auth_users.lastname",
+        SortKey::Created  => "auth_users.id",
+        _ => "auth_users.username",
+    };
+    conditions.push(query.after_condition(column, "auth_users.id"));
+    let rows = db.query_map(&format!("
+        SELECT CAST({} AS CHAR), id, user_ref, username, firstname, lastname, email, timezone, is_superuser
+        FROM auth_users
+        WHERE {}
+        {}
+        LIMIT {}
+    ", column, conditions.join(" AND "), query.order_by(column, "auth_users.id"), query.limit + 1),
+        |(value, user_id, user_ref, username, firstname, lastname, email, timezone, is_superuser):
+        (String, UidInternal, InternalReference<UserRef>, String, String, String, Option<String>, String, bool)|
+        (value, user_id, (user_id, User {
+            user_ref: user_ref.inner(),
+            username, firstname, lastname, email, timezone, is_superuser
+        }))
+    );
+    query.page(rows, total)
+}
@@ -1,8 +1,11 @@
 use super::InvalidField;
-use crate::utils::timezone::is_valid_timezone;
+use crate::audit::structures::AuditEntry;
+use crate::utils::{time, timezone::is_valid_timezone};
+use base::db::to_sql::AsSql;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use serde_json::Value;
-use user_auth_structs::User;
+use user_auth_structs::{Group, Tenant, TenantRef, User};
 
 
 pub fn user_from_json(json: Value) -> Result<CreateUser, InvalidField> {
@@ -85,3 +88,154 @@ pub struct RawUser {
     pub is_deleted: u8,
     pub is_superuser: u8,
 }
+
+/// An active login session, as recorded when the token was created
+#[derive(Serialize, Debug, Clone)]
+pub struct Session {
+    pub session_id: u64,
+    pub tenant_ref: TenantRef,
+    pub created_at: u64,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub current: bool,
+}
+
+pub struct RawSession {
+    pub id: u64,
+    pub tenant_ref: TenantRef,
+    pub token: String,
+    pub created_at: u64,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl RawSession {
+    /// Converts to the public form, marking it as current if it holds the caller's token
+    pub fn into_session(self, current_token: &Option<String>) -> Session {
+        Session {
+            current: current_token.as_ref() == Some(&self.token),
+            session_id: self.id,
+            tenant_ref: self.tenant_ref,
+            created_at: self.created_at,
+            client_ip: self.client_ip,
+            user_agent: self.user_agent,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SuspendUser {
+    pub reason: String,
+    pub until: Option<u64>,
+}
+
+pub fn validate_suspend_user(suspension: &SuspendUser) -> Result<(), InvalidField> {
+    if suspension.reason.len() == 0 {
+        return Err(InvalidField::Empty("reason"));
+    }
+    if suspension.reason.len() > 255 {
+        return Err(InvalidField::TooLong {
+            field: "reason",
+            max: 255,
+        });
+    }
+    if let Some(until) = suspension.until {
+        if until <= time::now() {
+            return Err(InvalidField::NotInFuture("until"));
+        }
+    }
+    Ok(())
+}
+
+/// An active suspension, either of the whole account (no tenant) or of one tenant membership
+#[derive(Serialize, Debug, Clone)]
+pub struct Suspension {
+    pub tenant_ref: Option<TenantRef>,
+    pub reason: String,
+    pub suspended_at: u64,
+    pub suspended_until: Option<u64>,
+}
+
+/// A soft-deleted user that can still be restored
+#[derive(Serialize, Debug, Clone)]
+pub struct DeletedUser {
+    #[serde(flatten)]
+    pub user: User,
+    pub deleted_at: u64,
+}
+
+/// Everything held about a user, as handed over for a subject access request
+#[derive(Serialize, Debug, Clone)]
+pub struct UserDataExport {
+    pub profile: User,
+    pub deleted_at: Option<u64>,
+    pub tenants: Vec<Tenant>,
+    pub groups: Vec<Group>,
+    pub suspensions: Vec<Suspension>,
+    pub sessions: Vec<Session>,
+    pub login_history: Vec<LoginAttempt>,
+    pub audit_entries: Vec<AuditEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginOutcome {
+    Success,
+    UnknownUser,
+    WrongPassword,
+    Locked,
+    TenantNotAuthorized,
+    TenantRequired,
+}
+
+impl FromStr for LoginOutcome {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success"               => Ok(Self::Success),
+            "unknown_user"          => Ok(Self::UnknownUser),
+            "wrong_password"        => Ok(Self::WrongPassword),
+            "locked"                => Ok(Self::Locked),
+            "tenant_not_authorized" => Ok(Self::TenantNotAuthorized),
+            "tenant_required"       => Ok(Self::TenantRequired),
+            _ => Err("Invalid login outcome in database"),
+        }
+    }
+}
+
+impl AsSql for LoginOutcome {
+    fn as_sql(&self) -> String {
+        AsSql::as_sql(match self {
+            Self::Success             => &"success",
+            Self::UnknownUser         => &"unknown_user",
+            Self::WrongPassword       => &"wrong_password",
+            Self::Locked              => &"locked",
+            Self::TenantNotAuthorized => &"tenant_not_authorized",
+            Self::TenantRequired      => &"tenant_required",
+        })
+    }
+
+    fn get_eq_operator(&self) -> &'static str {
+        "="
+    }
+}
+
+/// A recorded login attempt
+#[derive(Serialize, Debug, Clone)]
+pub struct LoginAttempt {
+    pub id: u64,
+    pub tenant_ref: Option<TenantRef>,
+    pub outcome: LoginOutcome,
+    pub created_at: u64,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// A tenant member together with their most recent successful login to that tenant
+#[derive(Serialize, Debug, Clone)]
+pub struct InactiveUser {
+    #[serde(flatten)]
+    pub user: User,
+    pub last_login_at: Option<u64>,
+}
@@ -1,23 +1,33 @@
 use base::DbConn;
 use sdk_base::Client;
 use token_auth_structs::{LoggedInUser, TenantLoginInfo};
+use user_auth_structs::{TenantRef, UserRef};
 
-use crate::{UserAuthErrResponse, groups, tenants, users};
+use crate::{
+    UserAuthErrResponse, groups, roles, tenants::{self, internal::TidInternal},
+    users::{self, internal::UidInternal}
+};
 
 
 pub fn update_user_info(client: &Client, current_info: LoggedInUser, db: &mut DbConn) -> Result<(), UserAuthErrResponse>{
-    let tenant_ref = current_info.tenant_info.tenant_ref;
-    let tenant_id = tenants::internal::decode_tenant_ref(db, tenant_ref.clone())?;
+    let tenant_id = tenants::internal::decode_tenant_ref(db, current_info.tenant_info.tenant_ref)?;
+    let user_id = users::internal::decode_user_ref(db, current_info.user.user_ref)?;
 
-    let user_ref = current_info.user.user_ref;
-    let user_id = users::internal::decode_user_ref(db, user_ref.clone())?;
+    refresh_user_info(client, user_id, tenant_id, db)
+}
 
+/// Pushes a user's current details, groups and permissions in a tenant to the token server
+pub fn refresh_user_info(client: &Client, user_id: UidInternal, tenant_id: TidInternal, db: &mut DbConn)
+-> Result<(), UserAuthErrResponse> {
+    let tenant_ref = tenants::internal::encode_tenant_ref(db, tenant_id);
 
     let user = users::internal::get_user(user_id, db)?;
+    let user_ref = user.user_ref.clone();
     let user_groups = groups::internal::get_user_group_refs(user_id, tenant_id, db);
     let tenant_admingroup = tenants::internal::get_tenant_admingroup_ref(tenant_id, db)?;
 
     let is_tenant_admin = user_groups.contains(&tenant_admingroup);
+    let permissions = roles::internal::get_user_permissions(user_id, tenant_id, db);
 
     // JAHS added - temporary solution to get the tenant name and store in TenantLoginInfo
     let tenant_name = tenants::internal::get_tenant(tenant_id, db).name;
@@ -29,10 +39,75 @@ pub fn update_user_info(client: &Client, current_info: LoggedInUser, db: &mut Db
             tenant_name,
             is_tenant_admin,
             groups: user_groups,
+            permissions,
         }
     };
 
     token_server_sdk::update_user(client, user_ref, user_info)??;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Revokes a user's tokens at the token server - all of them, or only those issued for one tenant.
+/// Fails if the token server cannot be reached, so call this before committing the change that
+/// requires the user to be logged out, or queue the revocation with the change (see
+/// `users::internal::queue_token_revocation`).
+pub fn revoke_user_tokens(client: &Client, user_ref: UserRef, tenant_ref: Option<TenantRef>)
+-> Result<(), UserAuthErrResponse> {
+    token_server_sdk::revoke_user_tokens(client, user_ref, tenant_ref)??;
+
+    Ok(())
+}
+
+/// Revokes a single token at the token server
+pub fn revoke_token(client: &Client, token: String) -> Result<(), UserAuthErrResponse> {
+    token_server_sdk::revoke_token(client, token)??;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use sdk_base::Client;
+    use serde_json::json;
+
+    use crate::utils::test_server::{fake_server, tenant_ref, user_ref};
+    use super::revoke_user_tokens;
+
+    fn client_for(token_server_url: &str) -> Client {
+        let locations = serde_json::from_value(json!({ "token_server": token_server_url })).unwrap();
+        Client::new(String::new(), String::from("test"), locations)
+    }
+
+    #[test]
+    fn revokes_the_users_tokens_for_the_tenant() {
+        let (url, server) = fake_server(200, "");
+        let (user, tenant) = (user_ref(), tenant_ref());
+
+        assert!(revoke_user_tokens(&client_for(&url), user.clone(), Some(tenant.clone())).is_ok());
+
+        let request = server.join().unwrap();
+        assert!(request.contains(&user.to_string()));
+        assert!(request.contains(&tenant.to_string()));
+    }
+
+    #[test]
+    fn fails_when_the_token_server_refuses() {
+        let (url, server) = fake_server(500, "");
+
+        assert!(revoke_user_tokens(&client_for(&url), user_ref(), None).is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn fails_when_the_token_server_is_unreachable() {
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        assert!(revoke_user_tokens(&client_for(&url), user_ref(), None).is_err());
+    }
+}
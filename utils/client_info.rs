@@ -0,0 +1,24 @@
+use rocket::{Outcome, Request, request::{self, FromRequest}};
+
+/// Information about the client making a request, recorded against sessions
+pub struct ClientInfo {
+    pub ip:         Option<String>,
+    pub user_agent: Option<String>,
+    pub token:      Option<String>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ClientInfo {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let token = request.headers().get_one("Authorization").map(|header|
+            header.trim_start_matches("Bearer ").to_string()
+        );
+
+        Outcome::Success(ClientInfo {
+            ip:         request.client_ip().map(|ip| ip.to_string()),
+            user_agent: request.headers().get_one("User-Agent").map(String::from),
+            token,
+        })
+    }
+}
@@ -1,3 +1,10 @@
 pub mod hashing;
 pub mod timezone;
-pub mod cache_updater;
\ No newline at end of file
+pub mod cache_updater;
+pub mod client_info;
+pub mod time;
+pub mod scheduler;
+pub mod pagination;
+pub mod transaction;
+#[cfg(test)]
+pub mod test_server;
@@ -0,0 +1,217 @@
+use base::sql;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 500;
+
+/// The envelope every paginated list is returned in. `total` counts every item matching the
+/// filters, and `next_cursor` is only set if there are more items after this page.
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn map<U, F>(self, f: F) -> Page<U>
+    where
+        F: FnMut(T) -> U
+    {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Username,
+    Lastname,
+    Name,
+    /// Creation order, which is the order of the internal ids
+    Created,
+}
+
+impl SortKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Username => "username",
+            Self::Lastname => "lastname",
+            Self::Name     => "name",
+            Self::Created  => "created",
+        }
+    }
+}
+
+/// A sort key, prefixed with `-` in queries to sort in descending order
+#[derive(Clone, Copy)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Sort {
+    fn parse(sort: &str, allowed: &[SortKey]) -> Result<Self, &'static str> {
+        let (key, descending) = match sort.strip_prefix("-") {
+            Some(key) => (key, true),
+            None => (sort, false),
+        };
+        allowed.iter()
+            .find(|allowed| allowed.as_str() == key)
+            .map(|key| Sort { key: *key, descending })
+            .ok_or("Unsupported sort key")
+    }
+
+    fn as_string(&self) -> String {
+        match self.descending {
+            true  => format!("-{}", self.key.as_str()),
+            false => self.key.as_str().to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StatusFilter {
+    Active,
+    Suspended,
+}
+
+/// Where the previous page ended: the sort value and id of its last item. Cursors are handed out
+/// hex-encoded, so they can be passed back as they are.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort: String,
+    value: String,
+    id: u64,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        if cursor.len() % 2 != 0 {
+            return None;
+        }
+        let bytes = (0..cursor.len()).step_by(2)
+            .map(|i| u8::from_str_radix(cursor.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/// The parsed pagination, sorting and filtering parameters of a list endpoint
+pub struct ListQuery {
+    pub limit: u64,
+    pub sort: Sort,
+    after: Option<Cursor>,
+    pub name: Option<String>,
+    pub email_domain: Option<String>,
+    pub status: Option<StatusFilter>,
+}
+
+impl ListQuery {
+    /// Parses a list endpoint's query parameters - the first of the allowed sort keys is the default
+    pub fn parse(
+        cursor: Option<String>,
+        limit: Option<u64>,
+        sort: Option<String>,
+        name: Option<String>,
+        email_domain: Option<String>,
+        status: Option<String>,
+        allowed: &[SortKey],
+    ) -> Result<Self, &'static str> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit == 0 || limit > MAX_PAGE_SIZE {
+            return Err("limit must be between 1 and 500");
+        }
+        let sort = match sort {
+            Some(sort) => Sort::parse(&sort, allowed)?,
+            None => Sort { key: allowed[0], descending: false },
+        };
+        let after = match cursor {
+            Some(cursor) => Some(Cursor::decode(&cursor).ok_or("Invalid cursor")?),
+            None => None,
+        };
+        if after.as_ref().map(|after| after.sort != sort.as_string()).unwrap_or(false) {
+            return Err("Cursor was issued for a different sort order");
+        }
+        let status = match status.as_deref() {
+            Some("active")    => Some(StatusFilter::Active),
+            Some("suspended") => Some(StatusFilter::Suspended),
+            Some(_) => return Err("status must be either active or suspended"),
+            None => None,
+        };
+        if email_domain.as_ref().map(|domain| domain.len() == 0 || domain.contains("@")).unwrap_or(false) {
+            return Err("Email domain must be a domain name, without the @");
+        }
+
+        Ok(Self { limit, sort, after, name, email_domain, status })
+    }
+
+    /// The condition selecting the rows after the cursor, sorting by `column` with `id_column`
+    /// breaking ties
+    pub fn after_condition(&self, column: &str, id_column: &str) -> String {
+        let after = match &self.after {
+            Some(after) => after,
+            None => return String::from("1 = 1"),
+        };
+        let op = if self.sort.descending { "<" } else { ">" };
+        match self.sort.key {
+            SortKey::Created => format!("{} {} {}", id_column, op, after.id),
+            _ => format!(
+                "({col} {op} {value} OR ({col} = {value} AND {id_col} {op} {id}))",
+                col = column, op = op, value = sql!("{}", after.value), id_col = id_column, id = after.id
+            ),
+        }
+    }
+
+    pub fn order_by(&self, column: &str, id_column: &str) -> String {
+        let direction = if self.sort.descending { "DESC" } else { "ASC" };
+        match self.sort.key {
+            SortKey::Created => format!("ORDER BY {} {}", id_column, direction),
+            _ => format!("ORDER BY {} {}, {} {}", column, direction, id_column, direction),
+        }
+    }
+
+    /// Turns rows fetched with `LIMIT limit + 1` - each with its sort value and id - into a page
+    pub fn page<T>(&self, mut rows: Vec<(String, u64, T)>, total: u64) -> Page<T> {
+        let next_cursor = match rows.len() as u64 > self.limit {
+            true => {
+                rows.truncate(self.limit as usize);
+                rows.last().map(|(value, id, _)| Cursor {
+                    sort: self.sort.as_string(),
+                    value: value.clone(),
+                    id: *id,
+                }.encode())
+            },
+            false => None,
+        };
+        Page {
+            items: rows.into_iter().map(|(_, _, item)| item).collect(),
+            total,
+            next_cursor,
+        }
+    }
+}
+
+fn escape_like(value: &str) -> String {
+    value.replace("\\", "\\\\").replace("%", "\\%").replace("_", "\\_")
+}
+
+/// A `LIKE` pattern matching anything starting with the value
+pub fn like_prefix(prefix: &str) -> String {
+    format!("{}%", escape_like(prefix))
+}
+
+/// A `LIKE` pattern matching anything ending with the value
+pub fn like_suffix(suffix: &str) -> String {
+    format!("%{}", escape_like(suffix))
+}
@@ -0,0 +1,34 @@
+use std::{thread, time::Duration};
+use base::{log, log_important, requests::RequestLogger, DbConn, DbPool};
+use rocket::fairing::AdHoc;
+
+/// Creates a fairing that, once the server has launched, runs `job` every `interval` on its own
+/// thread. Each run takes a fresh connection from the pool and gives it back when done, so a
+/// dropped connection only costs one run. Whatever the job reports is logged, with the job's name
+/// standing in for a request id.
+pub fn schedule<F>(name: &'static str, interval: Duration, job: F) -> AdHoc
+where
+    F: Fn(&mut DbConn) -> Option<String> + Send + Sync + 'static
+{
+    AdHoc::on_launch(name, move |rocket| {
+        let pool = rocket.state::<DbPool>()
+            .expect("Database pool unavailable for scheduled job")
+            .clone();
+
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let logger = RequestLogger::new(name.to_string());
+                loop {
+                    thread::sleep(interval);
+                    match pool.get_one() {
+                        Some(mut db) => if let Some(outcome) = job(&mut db) {
+                            log!("{}", outcome);
+                        },
+                        None => log_important!("{f:red}Skipped, no database connection available"),
+                    }
+                }
+            })
+            .expect("Unable to start scheduled job thread");
+    })
+}
@@ -0,0 +1,65 @@
+//! A stand-in HTTP server for tests of code that calls out to other services, and random
+//! references to stand in for users, groups and tenants
+
+use std::{io::{Read, Write}, net::TcpListener, thread::{self, JoinHandle}, time::Duration};
+
+use base::references::InternalReference;
+use user_auth_structs::{GroupRef, TenantRef, UserRef};
+
+pub fn user_ref() -> UserRef {
+    InternalReference::<UserRef>::gen_unique_rand(|_| true).inner()
+}
+
+pub fn group_ref() -> GroupRef {
+    InternalReference::<GroupRef>::gen_unique_rand(|_| true).inner()
+}
+
+pub fn tenant_ref() -> TenantRef {
+    InternalReference::<TenantRef>::gen_unique_rand(|_| true).inner()
+}
+
+fn is_complete(request: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(request);
+    let header_end = match text.find("\r\n\r\n") {
+        Some(end) => end + 4,
+        None => return false,
+    };
+    let content_length = text[..header_end].lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            match name.eq_ignore_ascii_case("content-length") {
+                true  => value.trim().parse::<usize>().ok(),
+                false => None,
+            }
+        })
+        .unwrap_or(0);
+    request.len() >= header_end + content_length
+}
+
+/// A server that answers a single request with the given status and extra headers, handing back
+/// the request it received
+pub fn fake_server(status: u16, headers: &str) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let headers = headers.to_string();
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        while !is_complete(&request) {
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(count) => request.extend_from_slice(&buffer[..count]),
+            }
+        }
+        write!(
+            stream,
+            "HTTP/1.1 {} Fake\r\n{}Content-Type: application/json\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnull",
+            status, headers
+        ).unwrap();
+        String::from_utf8_lossy(&request).to_string()
+    });
+    (url, handle)
+}
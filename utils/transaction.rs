@@ -0,0 +1,62 @@
+use std::ops::{Deref, DerefMut};
+
+use base::{DbConn, requests::UserRequest};
+
+/// Something holding the database connection a transaction runs on
+pub trait HasDb {
+    fn conn(&mut self) -> &mut DbConn;
+}
+
+impl HasDb for DbConn {
+    fn conn(&mut self) -> &mut DbConn {
+        self
+    }
+}
+
+impl HasDb for UserRequest<crate::ConfigType> {
+    fn conn(&mut self) -> &mut DbConn {
+        self.db()
+    }
+}
+
+/// A transaction that is rolled back when dropped without being committed, so that returning
+/// early with `?` never leaves half a change - or the events and audit entries written with it -
+/// behind. Derefs to whatever it was started on, which is used as usual until `commit`.
+pub struct Transaction<'a, T: HasDb> {
+    inner: &'a mut T,
+    committed: bool,
+}
+
+impl<'a, T: HasDb> Transaction<'a, T> {
+    pub fn start(inner: &'a mut T) -> Self {
+        inner.conn().start_transaction();
+        Self { inner, committed: false }
+    }
+
+    pub fn commit(mut self) {
+        self.inner.conn().commit();
+        self.committed = true;
+    }
+}
+
+impl<'a, T: HasDb> Drop for Transaction<'a, T> {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.inner.conn().query_drop("ROLLBACK");
+        }
+    }
+}
+
+impl<'a, T: HasDb> Deref for Transaction<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.inner
+    }
+}
+
+impl<'a, T: HasDb> DerefMut for Transaction<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.inner
+    }
+}
@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use base::{DbConn, sql};
+use hmac::{Hmac, Mac, NewMac};
+use reqwest::{blocking::{Client, ClientBuilder}, redirect::Policy};
+use sha2::Sha256;
+
+use crate::utils::time;
+use super::{structures::DeliveryStatus, target::{self, Target}};
+
+/// Deliveries are given up on after this many failed attempts
+pub const MAX_ATTEMPTS: u32 = 8;
+/// Delay before the first retry, doubled after every further failure
+pub const BASE_RETRY_DELAY_SECS: u64 = 30;
+const BATCH_SIZE: u64 = 100;
+
+/// Signs a payload along with the time it is sent, so receivers can check it came from us and
+/// turn away old deliveries replayed at them. Sent as `X-Webhook-Signature`, over
+/// `<X-Webhook-Timestamp>.<body>`.
+pub fn sign_payload(secret: &str, timestamp: u64, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// Seconds to wait before the next attempt, after the given number of failed attempts
+pub fn retry_delay(attempts: u32) -> u64 {
+    BASE_RETRY_DELAY_SECS * 2u64.pow(attempts.saturating_sub(1))
+}
+
+/// Redirects are not followed, as they could lead anywhere
+fn client_builder() -> ClientBuilder {
+    Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(Policy::none())
+}
+
+/// A client that only connects to the addresses checked for the target
+fn client_for(target: &Target) -> Result<Client, String> {
+    target.addrs.iter()
+        .fold(client_builder(), |builder, addr| builder.resolve(&target.host, *addr))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn send(client: &Client, url: &str, secret: &str, delivery_id: u64, event: &str, payload: &str)
+-> Result<(), String> {
+    let timestamp = time::now();
+    let response = client.post(url)
+        .header("Content-Type", "application/json")
+        .header("X-Webhook-Event", event)
+        .header("X-Webhook-Delivery", delivery_id.to_string())
+        .header("X-Webhook-Timestamp", timestamp.to_string())
+        .header("X-Webhook-Signature", sign_payload(secret, timestamp, payload))
+        .body(payload.to_string())
+        .send()
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    }
+    else {
+        Err(format!("Receiver responded with {}", response.status()))
+    }
+}
+
+/// Checks the webhook's url still points somewhere public and sends the delivery there
+fn deliver(url: &str, secret: &str, delivery_id: u64, event: &str, payload: &str)
+-> Result<(), String> {
+    let target = target::resolve(url)?;
+    send(&client_for(&target)?, url, secret, delivery_id, event, payload)
+}
+
+/// Attempts every delivery that is due, scheduling a retry with exponential back-off for those
+/// that fail, and giving up on them after `MAX_ATTEMPTS`
+pub fn deliver_pending(db: &mut DbConn) {
+    let due: Vec<(u64, String, String, String, String, u32)> = db.query_map(&sql!("
+        SELECT auth_webhook_deliveries.id, url, secret, event, payload, attempts
+        FROM auth_webhook_deliveries, auth_webhooks
+        WHERE
+            auth_webhook_deliveries.webhook_id = auth_webhooks.id AND
+            status {=} AND
+            next_attempt_at <= UNIX_TIMESTAMP()
+        ORDER BY next_attempt_at
+        LIMIT {}
+    ", DeliveryStatus::Pending, BATCH_SIZE), |row| row);
+
+    for (delivery_id, url, secret, event, payload, attempts) in due {
+        match deliver(&url, &secret, delivery_id, &event, &payload) {
+            Ok(()) => db.query_drop(&sql!("
+                UPDATE auth_webhook_deliveries SET
+                    status = {},
+                    attempts = {},
+                    next_attempt_at = NULL,
+                    last_error = NULL,
+                    delivered_at = UNIX_TIMESTAMP()
+                WHERE id = {}
+            ", DeliveryStatus::Delivered, attempts + 1, delivery_id)),
+            Err(error) if attempts + 1 >= MAX_ATTEMPTS => db.query_drop(&sql!("
+                UPDATE auth_webhook_deliveries SET
+                    status = {},
+                    attempts = {},
+                    next_attempt_at = NULL,
+                    last_error = {}
+                WHERE id = {}
+            ", DeliveryStatus::Failed, attempts + 1, error, delivery_id)),
+            Err(error) => db.query_drop(&sql!("
+                UPDATE auth_webhook_deliveries SET
+                    attempts = {},
+                    next_attempt_at = UNIX_TIMESTAMP() + {},
+                    last_error = {}
+                WHERE id = {}
+            ", attempts + 1, retry_delay(attempts + 1), error, delivery_id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::test_server::fake_server;
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef";
+    const PAYLOAD: &str = r#"{"event":"user.updated"}"#;
+
+    fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+        request.lines()
+            .find_map(|line| {
+                let (line_name, value) = line.split_once(':')?;
+                match line_name.eq_ignore_ascii_case(name) {
+                    true  => Some(value.trim()),
+                    false => None,
+                }
+            })
+    }
+
+    #[test]
+    fn sends_a_signed_and_timestamped_delivery() {
+        let (url, receiver) = fake_server(200, "");
+        let client = client_builder().build().unwrap();
+
+        let before = time::now();
+        assert!(send(&client, &url, SECRET, 7, "user.updated", PAYLOAD).is_ok());
+
+        let request = receiver.join().unwrap();
+        let timestamp: u64 = header(&request, "X-Webhook-Timestamp").unwrap().parse().unwrap();
+        assert!(timestamp >= before && timestamp <= time::now());
+        assert_eq!(header(&request, "X-Webhook-Event"), Some("user.updated"));
+        assert_eq!(header(&request, "X-Webhook-Delivery"), Some("7"));
+        assert_eq!(
+            header(&request, "X-Webhook-Signature"),
+            Some(sign_payload(SECRET, timestamp, PAYLOAD).as_str())
+        );
+        assert!(request.ends_with(PAYLOAD));
+    }
+
+    #[test]
+    fn signature_covers_the_timestamp() {
+        assert_ne!(sign_payload(SECRET, 1000, PAYLOAD), sign_payload(SECRET, 1001, PAYLOAD));
+    }
+
+    #[test]
+    fn fails_when_the_receiver_refuses() {
+        let (url, receiver) = fake_server(500, "");
+        let client = client_builder().build().unwrap();
+
+        assert!(send(&client, &url, SECRET, 1, "user.updated", PAYLOAD).is_err());
+        receiver.join().unwrap();
+    }
+
+    #[test]
+    fn does_not_follow_redirects() {
+        let (url, receiver) = fake_server(302, "Location: http://169.254.169.254/\r\n");
+        let client = client_builder().build().unwrap();
+
+        assert!(send(&client, &url, SECRET, 1, "user.updated", PAYLOAD).is_err());
+        receiver.join().unwrap();
+    }
+
+    #[test]
+    fn refuses_to_deliver_to_a_local_receiver() {
+        let (url, _receiver) = fake_server(200, "");
+
+        assert!(deliver(&url, SECRET, 1, "user.updated", PAYLOAD).is_err());
+        assert!(deliver(&url.replace("http://", "https://"), SECRET, 1, "user.updated", PAYLOAD).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use base::{Status, err_response, requests::{response::text_response::JsonBody, UserRequest}};
+use rocket::{response::status::Created, Route};
+use rocket_contrib::json::Json;
+use user_auth_structs::TenantRef;
+
+use crate::{
+    UserAuthErrResponse, tenants::{self, internal::TidInternal},
+    authz::{policy::authorize, structures::{Action, Resource}}
+};
+use super::{error::WebhookEndpointError, internal, structures::*};
+
+pub fn get_endpoints() -> Vec<Route> {
+    routes![create_webhook, get_tenant_webhooks, get_webhook, delete_webhook, get_deliveries, redeliver]
+}
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 500;
+
+/// Retrieves a webhook, checking the caller administers its tenant
+fn get_administered_webhook(
+    webhook_id: u64,
+    request: &mut UserRequest<crate::ConfigType>,
+) -> Result<(Webhook, TidInternal), UserAuthErrResponse> {
+    let (webhook, tenant_id) = internal::get_webhook(webhook_id, request.db())?;
+    authorize(request, Action::WebhooksManage, Resource::tenant(webhook.tenant_ref.clone()), WebhookEndpointError::ReadingDenied)?;
+    Ok((webhook, tenant_id))
+}
+
+#[post("/?<tenant>", data = "<webhook>")]
+pub fn create_webhook(
+    tenant: TenantRef,
+    webhook: JsonBody<CreateWebhook>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Created<()>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    authorize(&mut request, Action::WebhooksManage, Resource::tenant(tenant.clone()), WebhookEndpointError::ModificationDenied)?;
+
+
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), tenant)?;
+    let webhook_id = internal::create_webhook(tenant_id, webhook.0, request.db())?;
+
+    Ok(Created(format!("/webhooks/{}", webhook_id), None))
+}
+
+#[get("/?<tenant>")]
+pub fn get_tenant_webhooks(
+    tenant: TenantRef,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Webhook>>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    authorize(&mut request, Action::WebhooksManage, Resource::tenant(tenant.clone()), WebhookEndpointError::ReadingDenied)?;
+
+
+    let tenant_id = tenants::internal::decode_tenant_ref(request.db(), tenant)?;
+    Ok(Json(internal::get_tenant_webhooks(tenant_id, request.db())))
+}
+
+#[get("/<webhook_id>")]
+pub fn get_webhook(
+    webhook_id: u64,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Webhook>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    let (webhook, _) = get_administered_webhook(webhook_id, &mut request)?;
+
+
+    Ok(Json(webhook))
+}
+
+#[delete("/<webhook_id>")]
+pub fn delete_webhook(
+    webhook_id: u64,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    get_administered_webhook(webhook_id, &mut request)?;
+
+
+    internal::delete_webhook(webhook_id, request.db());
+    Ok(Status::NoContent)
+}
+
+/// Lists a webhook's deliveries, newest first. Pass the id of the last delivery received as
+/// `before` to get the next page.
+#[get("/<webhook_id>/deliveries?<before>&<limit>")]
+pub fn get_deliveries(
+    webhook_id: u64,
+    before: Option<u64>,
+    limit: Option<u64>,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Json<Vec<Delivery>>, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    get_administered_webhook(webhook_id, &mut request)?;
+
+
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return err_response!(WebhookEndpointError::InvalidField("limit must be between 1 and 500"));
+    }
+    Ok(Json(internal::get_deliveries(webhook_id, before, limit, request.db())))
+}
+
+#[post("/<webhook_id>/deliveries/<delivery_id>/redeliver")]
+pub fn redeliver(
+    webhook_id: u64,
+    delivery_id: u64,
+    mut request: UserRequest<crate::ConfigType>,
+) -> Result<Status, UserAuthErrResponse> {
+    //==CHECK PERMISSIONS==
+    get_administered_webhook(webhook_id, &mut request)?;
+
+
+    internal::redeliver(webhook_id, delivery_id, request.db())?;
+    Ok(Status::Accepted)
+}
@@ -0,0 +1,56 @@
+use base::{Status, requests::response::MicroserviceError};
+
+#[derive(Debug)]
+pub enum WebhookEndpointError {
+    NonExistentWebhook,
+    NonExistentDelivery,
+    InvalidField(&'static str),
+    ReadingDenied,
+    ModificationDenied,
+}
+
+impl MicroserviceError for WebhookEndpointError {
+    fn err_code(&self) -> u16 {
+        match self {
+            WebhookEndpointError::NonExistentWebhook  => 0x0400,
+            WebhookEndpointError::NonExistentDelivery => 0x0401,
+            WebhookEndpointError::InvalidField(_)     => 0x0402,
+            WebhookEndpointError::ReadingDenied       => 0x0403,
+            WebhookEndpointError::ModificationDenied  => 0x0404,
+        }
+    }
+
+    fn user_message(&self) -> String {
+        match self {
+            WebhookEndpointError::NonExistentWebhook  => format!("Webhook does not exist"),
+            WebhookEndpointError::NonExistentDelivery => format!("Webhook delivery does not exist"),
+            WebhookEndpointError::InvalidField(msg)   => format!("Invalid field: {}", msg),
+            WebhookEndpointError::ReadingDenied       => format!("Permission denied"),
+            WebhookEndpointError::ModificationDenied  => format!("Permission denied"),
+        }
+    }
+
+    fn detailed_message(&self) -> String {
+        match self {
+            WebhookEndpointError::NonExistentWebhook  => format!("Webhook does not exist"),
+            WebhookEndpointError::NonExistentDelivery => format!("Webhook delivery does not exist"),
+            WebhookEndpointError::InvalidField(msg)   => format!("Invalid field provided: {}", msg),
+            WebhookEndpointError::ReadingDenied       => format!("Permission denied: reading webhooks"),
+            WebhookEndpointError::ModificationDenied  => format!("Permission denied: changing webhooks"),
+        }
+    }
+
+    fn status(&self) -> base::Status {
+        match self {
+            WebhookEndpointError::NonExistentWebhook  => Status::NotFound,
+            WebhookEndpointError::NonExistentDelivery => Status::NotFound,
+            WebhookEndpointError::InvalidField(_)     => Status::BadRequest,
+            WebhookEndpointError::ReadingDenied       => Status::Forbidden,
+            WebhookEndpointError::ModificationDenied  => Status::Forbidden,
+        }
+    }
+
+    fn err_prefix() -> u16 {
+        unimplemented!()
+    }
+}
@@ -0,0 +1,146 @@
+use std::str::FromStr;
+
+use base::{DbConn, db::error_handling::DatabaseErrHandler, err_response, references::InternalReference, sql};
+use serde_json::{json, Value};
+use user_auth_structs::TenantRef;
+
+use crate::{UserAuthErrResponse, events::structures::{EventType, OutboxEvent}, tenants::internal::TidInternal};
+use super::{error::WebhookEndpointError, structures::*};
+
+/// Creates a webhook subscription for a tenant - doesn't check permissions
+pub fn create_webhook(tenant_id: TidInternal, webhook: CreateWebhook, db: &mut DbConn)
+-> Result<u64, UserAuthErrResponse> {
+    validate_create_webhook(&webhook)
+        .map_err(|e| UserAuthErrResponse::new(WebhookEndpointError::InvalidField(e)))?;
+
+    Ok(db.query_insert(&sql!(
+        "INSERT INTO auth_webhooks (tenant_id, url, events, secret, created_at)
+        VALUES ({}, {}, {}, {}, UNIX_TIMESTAMP())",
+        tenant_id, webhook.url, events_to_db(&webhook.events), webhook.secret
+    )))
+}
+
+fn webhooks_from_query(db: &mut DbConn, condition: String) -> Vec<(Webhook, TidInternal)> {
+    db.query_map(&format!("
+        SELECT auth_webhooks.id, auth_webhooks.tenant_id, tenant_ref, url, events, created_at
+        FROM auth_webhooks, auth_tenants
+        WHERE auth_webhooks.tenant_id = auth_tenants.id AND {}
+        ORDER BY auth_webhooks.id
+    ", condition),
+        |(webhook_id, tenant_id, tenant_ref, url, events, created_at):
+        (u64, TidInternal, InternalReference<TenantRef>, String, String, u64)| (
+            Webhook {
+                webhook_id,
+                tenant_ref: tenant_ref.inner(),
+                url,
+                events: events_from_db(&events),
+                created_at,
+            },
+            tenant_id
+        )
+    )
+}
+
+/// Retrieves a webhook along with the internal id of its tenant
+pub fn get_webhook(webhook_id: u64, db: &mut DbConn) -> Result<(Webhook, TidInternal), UserAuthErrResponse> {
+    match webhooks_from_query(db, sql!("auth_webhooks.id {=}", webhook_id)).into_iter().next() {
+        Some(webhook) => Ok(webhook),
+        None => err_response!(WebhookEndpointError::NonExistentWebhook),
+    }
+}
+
+pub fn get_tenant_webhooks(tenant_id: TidInternal, db: &mut DbConn) -> Vec<Webhook> {
+    webhooks_from_query(db, sql!("auth_webhooks.tenant_id {=}", tenant_id))
+        .into_iter()
+        .map(|(webhook, _)| webhook)
+        .collect()
+}
+
+/// Removes a webhook along with its delivery log
+pub fn delete_webhook(webhook_id: u64, db: &mut DbConn) {
+    db.start_transaction();
+    db.query_drop(&sql!("DELETE FROM auth_webhook_deliveries WHERE webhook_id {=}", webhook_id));
+    db.query_drop(&sql!("DELETE FROM auth_webhooks WHERE id {=}", webhook_id));
+    db.commit();
+}
+
+/// Queues a delivery of an outbox event to every webhook of its tenant that subscribes to it.
+/// Deliveries are keyed by the outbox event, so handling the same event twice queues nothing new.
+pub fn enqueue(db: &mut DbConn, event: &OutboxEvent) {
+    let tenant_id = match event.tenant_id {
+        Some(tenant_id) => tenant_id,
+        None => return,
+    };
+    let payload = json!(event).to_string();
+
+    let subscribed: Vec<u64> = db.query_map(&sql!(
+        "SELECT id, events FROM auth_webhooks WHERE tenant_id {=}", tenant_id
+    ), |(id, events): (u64, String)| (id, events_from_db(&events)))
+        .into_iter()
+        .filter(|(_, events)| events.contains(&event.event))
+        .map(|(id, _)| id)
+        .collect();
+
+    for webhook_id in subscribed {
+        db.query_drop(&sql!(
+            "INSERT IGNORE INTO auth_webhook_deliveries
+                (webhook_id, outbox_event_id, event, payload, status, attempts, next_attempt_at, created_at)
+            VALUES ({}, {}, {}, {}, {}, 0, UNIX_TIMESTAMP(), UNIX_TIMESTAMP())",
+            webhook_id, event.event_id, event.event, payload, DeliveryStatus::Pending
+        ));
+    }
+}
+
+/// Retrieves a page of a webhook's delivery log, newest first
+pub fn get_deliveries(webhook_id: u64, before: Option<u64>, limit: u64, db: &mut DbConn) -> Vec<Delivery> {
+    let mut conditions = vec![sql!("webhook_id {=}", webhook_id)];
+    if let Some(before) = before {
+        conditions.push(sql!("id < {}", before));
+    }
+
+    let db_err = db.err_handler();
+    db.query_map(&format!("
+        SELECT id, webhook_id, event, payload, status, attempts, next_attempt_at, last_error, created_at, delivered_at
+        FROM auth_webhook_deliveries
+        WHERE {}
+        ORDER BY id DESC
+        LIMIT {}
+    ", conditions.join(" AND "), limit),
+        |(delivery_id, webhook_id, event, payload, status, attempts, next_attempt_at, last_error, created_at, delivered_at): (
+            u64, u64, String, String, String, u32, Option<u64>, Option<String>, u64, Option<u64>
+        )| Delivery {
+            delivery_id,
+            webhook_id,
+            event: EventType::from_str(&event).db_expect(&db_err),
+            payload: serde_json::from_str(&payload).unwrap_or(Value::Null),
+            status: DeliveryStatus::from_str(&status).db_expect(&db_err),
+            attempts,
+            next_attempt_at,
+            last_error,
+            created_at,
+            delivered_at,
+        }
+    )
+}
+
+/// Queues a previous delivery to be sent again from scratch
+pub fn redeliver(webhook_id: u64, delivery_id: u64, db: &mut DbConn) -> Result<(), UserAuthErrResponse> {
+    let exists = db.query_count(&sql!(
+        "SELECT COUNT(*) FROM auth_webhook_deliveries WHERE id {=} AND webhook_id {=}",
+        delivery_id, webhook_id
+    )) > 0;
+    if !exists {
+        return err_response!(WebhookEndpointError::NonExistentDelivery);
+    }
+
+    db.query_drop(&sql!("
+        UPDATE auth_webhook_deliveries SET
+            status = {},
+            attempts = 0,
+            next_attempt_at = UNIX_TIMESTAMP(),
+            last_error = NULL,
+            delivered_at = NULL
+        WHERE id = {}
+    ", DeliveryStatus::Pending, delivery_id));
+    Ok(())
+}
@@ -0,0 +1,8 @@
+pub mod delivery;
+pub mod endpoints;
+pub mod error;
+pub mod internal;
+pub mod structures;
+pub mod target;
+
+pub use error::*;
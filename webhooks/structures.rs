@@ -0,0 +1,103 @@
+use std::str::FromStr;
+
+use base::db::to_sql::AsSql;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use user_auth_structs::TenantRef;
+
+use crate::events::structures::EventType;
+use super::target;
+
+#[derive(Deserialize)]
+pub struct CreateWebhook {
+    pub url: String,
+    pub events: Vec<EventType>,
+    pub secret: String,
+}
+
+pub fn validate_create_webhook(webhook: &CreateWebhook) -> Result<(), &'static str> {
+    if webhook.url.len() > 255 {
+        return Err("Webhook url cannot be longer than 255 characters");
+    }
+    target::resolve(&webhook.url)?;
+
+    if webhook.events.len() == 0 {
+        return Err("Webhook must subscribe to at least one event");
+    }
+    else if webhook.secret.len() < 16 {
+        return Err("Webhook secret must be at least 16 characters");
+    }
+    else if webhook.secret.len() > 255 {
+        return Err("Webhook secret cannot be longer than 255 characters");
+    }
+    Ok(())
+}
+
+/// Stores a subscription's events as a comma separated list
+pub fn events_to_db(events: &Vec<EventType>) -> String {
+    events.iter().map(|e| e.as_str()).collect::<Vec<&str>>().join(",")
+}
+
+pub fn events_from_db(events: &str) -> Vec<EventType> {
+    events.split(",").filter_map(|e| EventType::from_str(e).ok()).collect()
+}
+
+/// A webhook subscription - the secret is never handed back out
+#[derive(Serialize, Debug, Clone)]
+pub struct Webhook {
+    pub webhook_id: u64,
+    pub tenant_ref: TenantRef,
+    pub url: String,
+    pub events: Vec<EventType>,
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p" => Ok(Self::Pending),
+            "d" => Ok(Self::Delivered),
+            "f" => Ok(Self::Failed),
+            _ => Err("Invalid delivery status in database"),
+        }
+    }
+}
+
+impl AsSql for DeliveryStatus {
+    fn as_sql(&self) -> String {
+        AsSql::as_sql(match self {
+            Self::Pending   => &"p",
+            Self::Delivered => &"d",
+            Self::Failed    => &"f",
+        })
+    }
+
+    fn get_eq_operator(&self) -> &'static str {
+        "="
+    }
+}
+
+/// One attempt, or series of retried attempts, to deliver an event to a webhook
+#[derive(Serialize, Debug, Clone)]
+pub struct Delivery {
+    pub delivery_id: u64,
+    pub webhook_id: u64,
+    pub event: EventType,
+    pub payload: Value,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub next_attempt_at: Option<u64>,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub delivered_at: Option<u64>,
+}
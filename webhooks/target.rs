@@ -0,0 +1,145 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+use reqwest::Url;
+
+/// Where a webhook is delivered to: its host and the addresses that host resolved to, all of
+/// them public. Deliveries are sent to these addresses so the host can't be re-pointed at an
+/// internal one between the check and the request.
+pub struct Target {
+    pub host: String,
+    pub addrs: Vec<SocketAddr>,
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified() || ip.is_loopback() || ip.is_private() || ip.is_link_local() ||
+        ip.is_broadcast() || ip.is_multicast() || ip.is_documentation() ||
+        a == 0 ||                           // "this" network
+        (a == 100 && (b & 0xC0) == 64) ||   // shared address space, 100.64.0.0/10
+        (a == 198 && (b & 0xFE) == 18) ||   // benchmarking, 198.18.0.0/15
+        a >= 240)                           // reserved
+}
+
+/// The IPv4 address an IPv6 one carries and is routed to: IPv4-mapped `::ffff:a.b.c.d`,
+/// IPv4-compatible `::a.b.c.d`, NAT64 `64:ff9b::/96` and 6to4 `2002::/16`
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let s = ip.segments();
+    let v4 = |high: u16, low: u16| Ipv4Addr::new((high >> 8) as u8, high as u8, (low >> 8) as u8, low as u8);
+    match s {
+        [0x0064, 0xFF9B, 0, 0, 0, 0, high, low] => Some(v4(high, low)),
+        [0x2002, high, low, ..] => Some(v4(high, low)),
+        // covers both mapped and compatible addresses, including :: and ::1 as 0.0.0.0 and 0.0.0.1
+        _ => ip.to_ipv4(),
+    }
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    if let Some(v4) = embedded_v4(ip) {
+        return is_public_v4(v4);
+    }
+    let first = ip.segments()[0];
+    !(ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() ||
+        (first & 0xFE00) == 0xFC00 ||       // unique local, fc00::/7
+        (first & 0xFFC0) == 0xFE80 ||       // link-local, fe80::/10
+        (first == 0x2001 && ip.segments()[1] == 0x0DB8))    // documentation, 2001:db8::/32
+}
+
+/// Checks an address is reachable on the public internet, rather than being loopback, private,
+/// link-local or otherwise reserved
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+/// Checks a webhook url is https and resolves its host, refusing it if any address it resolves
+/// to is not public. Done when the webhook is created and again before every delivery.
+pub fn resolve(url: &str) -> Result<Target, &'static str> {
+    let url = Url::parse(url).map_err(|_| "Webhook url is not a valid url")?;
+    if url.scheme() != "https" {
+        return Err("Webhook url must be an https url");
+    }
+    let host = url.host_str().ok_or("Webhook url must have a host")?.to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    // brackets are kept around IPv6 hosts in urls but not accepted when resolving
+    let addrs: Vec<SocketAddr> = (host.trim_start_matches('[').trim_end_matches(']'), port)
+        .to_socket_addrs()
+        .map_err(|_| "Webhook url host could not be resolved")?
+        .collect();
+    if addrs.is_empty() {
+        return Err("Webhook url host could not be resolved");
+    }
+    else if addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err("Webhook url must not point to a private, loopback or link-local address");
+    }
+    Ok(Target { host, addrs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_addresses() {
+        let cases = [
+            ("93.184.216.34", true),
+            ("1.1.1.1", true),
+            ("2606:4700:4700::1111", true),
+            ("127.0.0.1", false),
+            ("10.1.2.3", false),
+            ("172.16.0.1", false),
+            ("192.168.1.1", false),
+            ("169.254.169.254", false),
+            ("100.64.0.1", false),
+            ("0.0.0.0", false),
+            ("255.255.255.255", false),
+            ("224.0.0.1", false),
+            ("::1", false),
+            ("::", false),
+            ("fd00::1", false),
+            ("fe80::1", false),
+            ("::ffff:127.0.0.1", false),
+            ("::ffff:93.184.216.34", true),
+            ("::10.0.0.1", false),
+            ("::93.184.216.34", true),
+            ("64:ff9b::a9fe:a9fe", false),
+            ("64:ff9b::7f00:1", false),
+            ("64:ff9b::5db8:d822", true),
+            ("2002:c0a8:101::1", false),
+            ("2002:a9fe:a9fe::1", false),
+            ("2002:5db8:d822::1", true),
+        ];
+        for (ip, public) in cases.iter() {
+            assert_eq!(is_public(ip.parse().unwrap()), *public, "{}", ip);
+        }
+    }
+
+    #[test]
+    fn accepts_public_https_urls() {
+        let target = resolve("https://93.184.216.34:8443/hook").unwrap();
+        assert_eq!(target.host, "93.184.216.34");
+        assert_eq!(target.addrs, vec!["93.184.216.34:8443".parse().unwrap()]);
+    }
+
+    #[test]
+    fn refuses_other_urls() {
+        let urls = [
+            "http://93.184.216.34/hook",
+            "ftp://93.184.216.34/hook",
+            "not a url",
+            "https://127.0.0.1/hook",
+            "https://localhost/hook",
+            "https://10.0.0.1/hook",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/hook",
+            "https://[fe80::1]/hook",
+            "https://[64:ff9b::a9fe:a9fe]/latest/meta-data",
+            "https://[2002:c0a8:101::1]/hook",
+        ];
+        for url in urls.iter() {
+            assert!(resolve(url).is_err(), "{}", url);
+        }
+    }
+}